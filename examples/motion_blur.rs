@@ -0,0 +1,51 @@
+use raytracer::prelude::*;
+use std::f64::consts::FRAC_PI_3;
+
+const WIDTH: usize = 400;
+const HEIGHT: usize = 200;
+const TIME_SAMPLES: usize = 32;
+
+fn main() {
+    let floor = Sphere::new(
+        scaling(10.0, 0.01, 10.0),
+        Material {
+            color: color_rgb!(1.0, 0.9, 0.9),
+            specular: 0.0,
+            ..Material::default()
+        },
+    );
+
+    // A projectile flying left to right while the shutter is open
+    let start = translation(-2.0, 0.5, 0.0) * scaling(0.5, 0.5, 0.5);
+    let end = translation(1.0, 1.5, 0.0) * scaling(0.5, 0.5, 0.5);
+    let mut projectile = Sphere::new(start, Material {
+        color: color_rgb!(1.0, 0.2, 0.1),
+        diffuse: 0.7,
+        specular: 0.3,
+        ..Material::default()
+    });
+    projectile.set_end_transform(end);
+
+    let light = Light::new(point(-10.0, 10.0, -10.0), Color::WHITE);
+
+    let mut camera = Camera::new(
+        WIDTH,
+        HEIGHT,
+        FRAC_PI_3,
+        view_transform(
+            point(0.0, 1.5, -5.0),
+            point(0.0, 1.0, 0.0),
+            vector(0.0, 1.0, 0.0)
+        )
+    );
+    camera.shutter_open = 0.0;
+    camera.shutter_close = 1.0;
+    camera.time_samples = TIME_SAMPLES;
+
+    let world = World::new(vec![Box::new(floor), Box::new(projectile)], vec![light]);
+
+    let canvas = camera.render(&world);
+
+    canvas.save("out.ppm").unwrap();
+    println!("Saved to out.ppm");
+}
@@ -17,64 +17,58 @@ const FOV: f64 = FRAC_PI_3;
 
 fn main() {
     
-    let floor = Sphere {
-        transform: scaling(10.0, 0.01, 10.0),
-        material: Material {
+    let floor = Sphere::new(
+        scaling(10.0, 0.01, 10.0),
+        Material {
             color: color_rgb!(1.0, 0.9, 0.9),
             specular: 0.0,
             ..Material::default()
         },
-        ..Sphere::default()
-    };
-    let left_wall = Sphere {
-        transform: translation(0.0, 0.0, 5.0)
-                   * rotation_y(-FRAC_PI_4)
-                   * rotation_x(FRAC_PI_2)
-                   * scaling(10.0, 0.01, 10.0),
-        material: floor.material,
-        ..Sphere::default()
-    };
-    let right_wall = Sphere {
-        transform: translation(0.0, 0.0, 5.0)
-                   * rotation_y(FRAC_PI_4)
-                   * rotation_x(FRAC_PI_2)
-                   * scaling(7.0, 0.01, 7.0),
-        material: floor.material,
-        ..Sphere::default()
-    };
+    );
+    let left_wall = Sphere::new(
+        M4::IDENTITY
+            .scale(10.0, 0.01, 10.0)
+            .rotate_x(FRAC_PI_2)
+            .rotate_y(-FRAC_PI_4)
+            .translate(0.0, 0.0, 5.0),
+        floor.material,
+    );
+    let right_wall = Sphere::new(
+        M4::IDENTITY
+            .scale(7.0, 0.01, 7.0)
+            .rotate_x(FRAC_PI_2)
+            .rotate_y(FRAC_PI_4)
+            .translate(0.0, 0.0, 5.0),
+        floor.material,
+    );
 
-    let middle = Sphere {
-        transform: translation(-0.5, 1.0, 0.5),
-        material: Material {
+    let middle = Sphere::new(
+        translation(-0.5, 1.0, 0.5),
+        Material {
             color: color_rgb!(0.1, 1.0, 0.5),
             diffuse: 0.7,
             specular: 0.3,
             ..Material::default()
         },
-        ..Sphere::default()
-    };
-    let right = Sphere {
-        transform: translation(1.5, 0.5, -0.5)
-                   * scaling(0.5, 0.5, 0.5),
-        material: Material {
+    );
+    let right = Sphere::new(
+        M4::IDENTITY.scale(0.5, 0.5, 0.5).translate(1.5, 0.5, -0.5),
+        Material {
             color: color_rgb!(0.5, 1.0, 0.1),
             diffuse: 0.7,
             specular: 0.3,
             ..Material::default()
         },
-        ..Sphere::default()
-    };
-    let left = Sphere {
-        transform: translation(-1.5, 0.33, -0.75)
-                   * scaling(0.33, 0.33, 0.33),
-        material: Material {
+    );
+    let left = Sphere::new(
+        M4::IDENTITY.scale(0.33, 0.33, 0.33).translate(-1.5, 0.33, -0.75),
+        Material {
             color: color_rgb!(1.0, 0.8, 0.1),
             diffuse: 0.7,
             specular: 0.3,
             ..Material::default()
         },
-        ..Sphere::default()
-    };
+    );
 
     let light = Light::new(point(-10.0, 10.0, -10.0), Color::WHITE);
 
@@ -90,7 +84,10 @@ fn main() {
     );
 
     let world = World::new(
-        vec![floor, left_wall, right_wall, left, middle, right],
+        vec![
+            Box::new(floor), Box::new(left_wall), Box::new(right_wall),
+            Box::new(left), Box::new(middle), Box::new(right),
+        ],
         vec![light]
     );
 
@@ -33,7 +33,7 @@ fn main() {
             let ray = Ray::new(light_pos, pixel_pos - light_pos);
 
             sphere.intersect(ray, &mut xs);
-            if xs.len() > 0 {
+            if !xs.is_empty() {
                 canvas.write_pixel(row as i32, col as i32, SPHERE_COLOR);
             }
         }
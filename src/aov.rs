@@ -0,0 +1,194 @@
+// Arbitrary output variables: images besides the rendered one that say what
+// the camera saw in each pixel, for compositing and for debugging. They are
+// rendered together by `Camera::render_layers`.
+//
+// Each layer is a canvas holding the raw values, to be saved in a float
+// format such as PFM:
+//
+//   beauty   the rendered image, the same as `Camera::render`
+//   depth    distance along the camera ray to the nearest hit of any sample
+//            (the `t` of the hit), 0 where nothing was hit
+//   normal   world space normal facing the camera, x y z as red green blue,
+//            averaged over the samples
+//   albedo   the color of the surface before lighting, averaged over the
+//            samples
+//   object   index in `World::objects` of the object at the nearest hit, in
+//            every channel, -1 where nothing was hit. A group is one object.
+//   shadow   the fraction of the samples whose hit is shadowed from the
+//            first light
+//
+// `RenderLayers::image` turns a layer into something that looks right as a
+// PNG.
+use crate::prelude::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aov {
+    Beauty,
+    Depth,
+    Normal,
+    Albedo,
+    Object,
+    Shadow,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [Aov::Beauty, Aov::Depth, Aov::Normal, Aov::Albedo, Aov::Object, Aov::Shadow];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Beauty => "beauty",
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Object => "object",
+            Aov::Shadow => "shadow",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == s)
+    }
+}
+
+// What one pixel contributes to each layer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PixelLayers {
+    pub beauty: Color,
+    pub depth: Option<f64>,
+    pub normal: T4,
+    pub albedo: Color,
+    pub object: Option<usize>,
+    pub shadow: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderLayers {
+    pub beauty: Canvas,
+    pub depth: Canvas,
+    pub normal: Canvas,
+    pub albedo: Canvas,
+    pub object: Canvas,
+    pub shadow: Canvas,
+}
+
+impl RenderLayers {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            beauty: Canvas::new(width, height),
+            depth: Canvas::new(width, height),
+            normal: Canvas::new(width, height),
+            albedo: Canvas::new(width, height),
+            object: Canvas::new_with_color(width, height, color(-1.0, -1.0, -1.0)),
+            shadow: Canvas::new(width, height),
+        }
+    }
+
+    pub fn write_pixel(&mut self, x: i32, y: i32, p: PixelLayers) {
+        let gray = |v: f64| color(v, v, v);
+        self.beauty.write_pixel(x, y, p.beauty);
+        self.depth.write_pixel(x, y, gray(p.depth.unwrap_or(0.0)));
+        self.normal.write_pixel(x, y, color(p.normal.x, p.normal.y, p.normal.z));
+        self.albedo.write_pixel(x, y, p.albedo);
+        self.object.write_pixel(x, y, gray(p.object.map_or(-1.0, |i| i as f64)));
+        self.shadow.write_pixel(x, y, gray(p.shadow));
+    }
+
+    pub fn layer(&self, aov: Aov) -> &Canvas {
+        match aov {
+            Aov::Beauty => &self.beauty,
+            Aov::Depth => &self.depth,
+            Aov::Normal => &self.normal,
+            Aov::Albedo => &self.albedo,
+            Aov::Object => &self.object,
+            Aov::Shadow => &self.shadow,
+        }
+    }
+
+    // A layer with its values mapped into 0 to 1 for viewing:
+    //
+    //   depth    the nearest depth over the pixel's, white close to the camera
+    //            and fading with distance, black where nothing was hit
+    //   normal   each component mapped from -1..1 to 0..1
+    //   object   a different hue for each object, black where nothing was hit
+    //
+    // The others are shown as they are.
+    pub fn image(&self, aov: Aov) -> Canvas {
+        let layer = self.layer(aov);
+        let (w, h) = (layer.get_width(), layer.get_height());
+        let pixels = || (0..h).flat_map(move |y| (0..w).map(move |x| (x, y)));
+
+        let nearest = pixels().map(|(x, y)| self.depth.pixel_at(x, y).red)
+            .filter(|&d| d > 0.0)
+            .fold(f64::INFINITY, f64::min);
+        let map = |c: Color| match aov {
+            Aov::Depth if c.red > 0.0 => {
+                let v = nearest / c.red;
+                color(v, v, v)
+            },
+            Aov::Depth => Color::BLACK,
+            Aov::Normal => (c + Color::WHITE) * 0.5,
+            Aov::Object if c.red >= 0.0 => object_color(c.red as usize),
+            Aov::Object => Color::BLACK,
+            _ => c,
+        };
+
+        let mut image = Canvas::new(w as usize, h as usize);
+        for (x, y) in pixels() {
+            image.write_pixel(x, y, map(layer.pixel_at(x, y)));
+        }
+        return image;
+    }
+}
+
+// Bright colors with hues spread around the color wheel by the golden
+// ratio, so that neighbouring indices look different
+fn object_color(index: usize) -> Color {
+    let hue = (index as f64 * 0.618_033_988_75).fract() * 6.0;
+    let f = hue.fract();
+    let (r, g, b) = match hue as usize {
+        0 => (1.0, f, 0.0),
+        1 => (1.0 - f, 1.0, 0.0),
+        2 => (0.0, 1.0, f),
+        3 => (0.0, 1.0 - f, 1.0),
+        4 => (f, 0.0, 1.0),
+        _ => (1.0, 0.0, 1.0 - f),
+    };
+    return color(0.2 + 0.8 * r, 0.2 + 0.8 * g, 0.2 + 0.8 * b);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aov_names() {
+        for &aov in Aov::ALL.iter() {
+            assert_eq!(Aov::parse(aov.name()), Some(aov));
+        }
+        assert_eq!(Aov::parse("motion"), None);
+    }
+
+    #[test]
+    fn images_for_viewing() {
+        let mut layers = RenderLayers::new(3, 1);
+        let hit = PixelLayers {
+            beauty: Color::WHITE, depth: Some(2.0), normal: vector(0.0, 0.0, -1.0),
+            albedo: color(0.5, 0.5, 0.5), object: Some(0), shadow: 1.0,
+        };
+        layers.write_pixel(0, 0, hit);
+        layers.write_pixel(1, 0, PixelLayers { depth: Some(4.0), object: Some(1), ..hit });
+
+        assert_eq!(layers.depth.pixel_at(1, 0), color(4.0, 4.0, 4.0));
+        assert_eq!(layers.object.pixel_at(2, 0), color(-1.0, -1.0, -1.0));
+
+        let depth = layers.image(Aov::Depth);
+        assert_eq!(depth.pixel_at(0, 0), Color::WHITE);
+        assert_eq!(depth.pixel_at(1, 0), color(0.5, 0.5, 0.5));
+        assert_eq!(depth.pixel_at(2, 0), Color::BLACK);
+        assert_eq!(layers.image(Aov::Normal).pixel_at(0, 0), color(0.5, 0.5, 0.0));
+        let objects = layers.image(Aov::Object);
+        assert!(objects.pixel_at(0, 0) != objects.pixel_at(1, 0));
+        assert_eq!(objects.pixel_at(2, 0), Color::BLACK);
+        assert_eq!(layers.image(Aov::Shadow).pixel_at(0, 0), Color::WHITE);
+    }
+}
@@ -0,0 +1,134 @@
+use crate::prelude::*;
+
+// An axis aligned bounding box in world space, used by groups to skip their
+// children when a ray cannot possibly hit them.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min: T4,
+    pub max: T4,
+}
+
+impl Default for Bounds {
+    fn default() -> Self { Self::EMPTY }
+}
+
+impl Bounds {
+    pub const EMPTY: Self = Self {
+        min: T4 { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY, w: 1.0 },
+        max: T4 { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY, w: 1.0 },
+    };
+
+    pub const INFINITE: Self = Self {
+        min: T4 { x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY, w: 1.0 },
+        max: T4 { x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY, w: 1.0 },
+    };
+
+    pub fn new(min: T4, max: T4) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_points<I: IntoIterator<Item = T4>>(points: I) -> Self {
+        points.into_iter().fold(Self::EMPTY, |b, p| b.add_point(p))
+    }
+
+    pub fn add_point(self, p: T4) -> Self {
+        Self {
+            min: point(self.min.x.min(p.x), self.min.y.min(p.y), self.min.z.min(p.z)),
+            max: point(self.max.x.max(p.x), self.max.y.max(p.y), self.max.z.max(p.z)),
+        }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        self.add_point(other.min).add_point(other.max)
+    }
+
+    pub fn is_infinite(self) -> bool {
+        [self.min.x, self.min.y, self.min.z, self.max.x, self.max.y, self.max.z]
+            .iter()
+            .any(|x| x.is_infinite())
+    }
+
+    // The bounds of this box after being transformed, found by transforming
+    // all eight corners.
+    pub fn transform(self, m: M4) -> Self {
+        if self.is_infinite() {
+            return self;
+        }
+        let (a, b) = (self.min, self.max);
+        Self::from_points([
+            point(a.x, a.y, a.z), point(a.x, a.y, b.z),
+            point(a.x, b.y, a.z), point(a.x, b.y, b.z),
+            point(b.x, a.y, a.z), point(b.x, a.y, b.z),
+            point(b.x, b.y, a.z), point(b.x, b.y, b.z),
+        ].iter().map(|&p| m * p))
+    }
+
+    // Slab test
+    pub fn intersects(self, ray: Ray) -> bool {
+        let (tmin_x, tmax_x) = slab(ray.origin.x, ray.direction.x, self.min.x, self.max.x);
+        let (tmin_y, tmax_y) = slab(ray.origin.y, ray.direction.y, self.min.y, self.max.y);
+        let (tmin_z, tmax_z) = slab(ray.origin.z, ray.direction.z, self.min.z, self.max.z);
+
+        let tmin = tmin_x.max(tmin_y).max(tmin_z);
+        let tmax = tmax_x.min(tmax_y).min(tmax_z);
+        tmin <= tmax
+    }
+}
+
+fn slab(origin: f64, direction: f64, min: f64, max: f64) -> (f64, f64) {
+    // Only an exact zero is parallel; tiny components divide out to huge t
+    // values and infinities, which the min/max comparisons already handle
+    if direction == 0.0 {
+        if origin < min || origin > max {
+            return (f64::INFINITY, f64::NEG_INFINITY);
+        }
+        return (f64::NEG_INFINITY, f64::INFINITY);
+    }
+    let t1 = (min - origin) / direction;
+    let t2 = (max - origin) / direction;
+    if t1 < t2 { (t1, t2) } else { (t2, t1) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_4, SQRT_2};
+
+    #[test]
+    fn from_points() {
+        let b = Bounds::from_points([point(-1.0, 2.0, 0.0), point(3.0, -4.0, 1.0)].iter().copied());
+        assert_eq!(b.min, point(-1.0, -4.0, 0.0));
+        assert_eq!(b.max, point(3.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn transforming_bounds() {
+        let b = Bounds::new(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0))
+            .transform(rotation_x(FRAC_PI_4) * rotation_y(FRAC_PI_4));
+        assert_eq!(b.min, point(-SQRT_2, -1.70711, -1.70711));
+        assert_eq!(b.max, point(SQRT_2, 1.70711, 1.70711));
+    }
+
+    #[test]
+    fn ray_box_intersection() {
+        let b = Bounds::new(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0));
+        assert!(b.intersects(Ray::new(point(5.0, 0.5, 0.0), vector(-1.0, 0.0, 0.0))));
+        assert!(b.intersects(Ray::new(point(0.0, 0.5, 0.0), vector(0.0, 0.0, 1.0))));
+        assert!(!b.intersects(Ray::new(point(-2.0, 0.0, 0.0), vector(0.2673, 0.5345, 0.8018))));
+        assert!(!b.intersects(Ray::new(point(2.0, 2.0, 0.0), vector(-1.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn nearly_parallel_rays_are_not_treated_as_parallel() {
+        let b = Bounds::new(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0));
+        // Leaves the y slab at t = 2, well before reaching the box in x
+        assert!(!b.intersects(Ray::new(point(-5.0, 0.99999, 0.0), vector(1.0, 5e-6, 0.0))));
+        assert!(b.intersects(Ray::new(point(-5.0, 0.9, 0.0), vector(1.0, 5e-6, 0.0))));
+        assert!(b.intersects(Ray::new(point(-5.0, 0.0, 0.0), vector(1.0, 1e-300, -1e-300))));
+    }
+
+    #[test]
+    fn infinite_bounds_hit_everything() {
+        assert!(Bounds::INFINITE.intersects(Ray::new(point(0.0, 100.0, 0.0), vector(0.0, 1.0, 0.0))));
+    }
+}
@@ -0,0 +1,290 @@
+// Reflection models: how much of the light arriving from one direction a
+// surface sends off in another, and how the path tracer picks the direction
+// a path carries on in.
+//
+// Light is measured the way Phong shading measures it. A point light of
+// intensity I lights a white diffuse surface facing it to I, not I / π, so
+// each reflectance here is π times the BRDF times the cosine of the angle
+// between the light and the normal. For an ideal diffuse surface it is
+// simply `albedo * cos`.
+//
+// The metallic-roughness model is the one used by glTF and most authoring
+// tools:
+//
+//   - a GGX (Trowbridge-Reitz) distribution of microfacet normals, whose
+//     width is roughness squared
+//   - Smith shadowing and masking, the microfacets hidden from the light or
+//     the eye by their neighbours
+//   - Schlick's approximation of Fresnel reflection, 4% head on for
+//     dielectrics and the base color for metals
+//   - a diffuse layer under the specular one for dielectrics, lit by the
+//     light the specular layer lets through; metals have none
+//
+// Oren-Nayar is the diffuse reflection of a surface of tiny facets, each an
+// ideal diffuse reflector, with slopes spread by `sigma` radians. This is
+// their simpler qualitative model.
+use crate::prelude::*;
+use std::f64::consts::PI;
+
+// How much light a dielectric such as plastic reflects head on
+const DIELECTRIC_REFLECTANCE: f64 = 0.04;
+
+// Below this roughness highlights get too small to be found by sampling and
+// the numbers blow up, so smoother surfaces are treated as this rough
+const MIN_ROUGHNESS: f64 = 0.03;
+
+// Light reflected towards `eyev` from a light of unit intensity in the
+// direction `lightv`. All vectors point away from the surface.
+pub fn metallic_roughness(base: Color, metallic: f64, roughness: f64,
+                          lightv: T4, eyev: T4, normalv: T4) -> Color {
+    let n_dot_l = lightv * normalv;
+    let n_dot_v = eyev * normalv;
+    if n_dot_l <= 0.0 || n_dot_v <= 0.0 { return Color::BLACK; }
+
+    let halfv = (lightv + eyev).normalize();
+    let alpha = ggx_alpha(roughness);
+    let fresnel = schlick_fresnel(base_reflectance(base, metallic), eyev * halfv);
+
+    let diffuse = (Color::WHITE - fresnel) * base * ((1.0 - metallic) * n_dot_l);
+    // π D G F / (4 n·l n·v), times n·l
+    let specular = fresnel * (PI * ggx_distribution(normalv * halfv, alpha)
+                              * smith_g1(n_dot_l, alpha) * smith_g1(n_dot_v, alpha) / (4.0 * n_dot_v));
+    return diffuse + specular;
+}
+
+pub fn oren_nayar(albedo: Color, sigma: f64, lightv: T4, eyev: T4, normalv: T4) -> Color {
+    let n_dot_l = lightv * normalv;
+    let n_dot_v = eyev * normalv;
+    if n_dot_l <= 0.0 { return Color::BLACK; }
+
+    let s2 = sigma * sigma;
+    let a = 1.0 - 0.5 * s2 / (s2 + 0.33);
+    let b = 0.45 * s2 / (s2 + 0.09);
+    // cos(φl - φv) sin θl sin θv, which is zero when either is along the
+    // normal
+    let s = lightv * eyev - n_dot_l * n_dot_v;
+    // Dividing by the larger cosine gives sin α tan β, with α the larger of
+    // the two angles to the normal and β the smaller
+    let t = n_dot_l.max(n_dot_v);
+    let backscatter = if s > 0.0 && t > 0.0 { s / t } else { 0.0 };
+    return albedo * (n_dot_l * (a + b * backscatter));
+}
+
+// A direction for a path to continue in after hitting a metallic-roughness
+// surface, and the reflectance in that direction divided by π times the
+// probability density of picking it. Highlights are sampled from the GGX
+// distribution, the diffuse layer from a cosine weighted hemisphere. None
+// if the pick points into the surface, which ends the path.
+pub fn sample_metallic_roughness(base: Color, metallic: f64, roughness: f64,
+                                 eyev: T4, normalv: T4, rng: &mut Rng) -> Option<(T4, Color)> {
+    if eyev * normalv <= 0.0 { return None; }
+    let alpha = ggx_alpha(roughness);
+    // Metals have no diffuse layer to sample
+    let specular_chance = 0.5 + 0.5 * metallic.clamp(0.0, 1.0);
+
+    let lightv = if rng.next_f64() < specular_chance {
+        (-eyev).reflect(ggx_sample_normal(normalv, alpha, rng))
+    } else {
+        cosine_sample_hemisphere(normalv, rng)
+    };
+    let n_dot_l = lightv * normalv;
+    if n_dot_l <= 0.0 { return None; }
+
+    let halfv = (lightv + eyev).normalize();
+    let n_dot_h = normalv * halfv;
+    let specular_pdf = ggx_distribution(n_dot_h, alpha) * n_dot_h / (4.0 * (eyev * halfv));
+    let pdf = specular_chance * specular_pdf + (1.0 - specular_chance) * n_dot_l / PI;
+
+    let reflectance = metallic_roughness(base, metallic, roughness, lightv, eyev, normalv);
+    return Some((lightv, reflectance / (PI * pdf)));
+}
+
+// Dielectrics reflect a little light whatever their color, metals reflect
+// their color
+fn base_reflectance(base: Color, metallic: f64) -> Color {
+    let dielectric = color(DIELECTRIC_REFLECTANCE, DIELECTRIC_REFLECTANCE, DIELECTRIC_REFLECTANCE);
+    return dielectric * (1.0 - metallic) + base * metallic;
+}
+
+// Authoring tools square roughness so that it looks linear
+pub fn ggx_alpha(roughness: f64) -> f64 {
+    let r = roughness.clamp(MIN_ROUGHNESS, 1.0);
+    return r * r;
+}
+
+// Density of microfacet normals at an angle to the surface normal with
+// cosine `n_dot_h`, per unit solid angle and projected onto the surface, so
+// that it integrates to 1 over the hemisphere
+pub fn ggx_distribution(n_dot_h: f64, alpha: f64) -> f64 {
+    if n_dot_h <= 0.0 { return 0.0; }
+    let a2 = alpha * alpha;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// The fraction of microfacets facing a direction at cosine `n_dot_v` to the
+// normal that aren't hidden from it by other microfacets
+pub fn smith_g1(n_dot_v: f64, alpha: f64) -> f64 {
+    if n_dot_v <= 0.0 { return 0.0; }
+    let a2 = alpha * alpha;
+    return 2.0 * n_dot_v / (n_dot_v + (a2 + (1.0 - a2) * n_dot_v * n_dot_v).sqrt());
+}
+
+// The fraction of light reflected off a facet seen at cosine `cos`, for a
+// material that reflects `f0` head on
+pub fn schlick_fresnel(f0: Color, cos: f64) -> Color {
+    let m = (1.0 - cos.clamp(0.0, 1.0)).powi(5);
+    return f0 + (Color::WHITE - f0) * m;
+}
+
+// A random microfacet normal picked with density `ggx_distribution * cos`
+pub fn ggx_sample_normal(normal: T4, alpha: f64, rng: &mut Rng) -> T4 {
+    let u = rng.next_f64();
+    let phi = 2.0 * PI * rng.next_f64();
+    let cos_theta = ((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    return tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + normal * cos_theta;
+}
+
+// A random unit vector on the hemisphere around `normal`, more likely close
+// to the normal: the density is cos θ / π. Points are picked uniformly on
+// the unit disk and projected up onto the hemisphere.
+pub fn cosine_sample_hemisphere(normal: T4, rng: &mut Rng) -> T4 {
+    let r = rng.next_f64().sqrt();
+    let phi = 2.0 * PI * rng.next_f64();
+    let (x, y) = (r * phi.cos(), r * phi.sin());
+    let z = (1.0 - r * r).max(0.0).sqrt();
+
+    let (tangent, bitangent) = orthonormal_basis(normal);
+    return tangent * x + bitangent * y + normal * z;
+}
+
+// Two unit vectors perpendicular to the unit vector `n` and to each other,
+// without branching on which axis `n` is closest to (Duff et al. 2017)
+fn orthonormal_basis(n: T4) -> (T4, T4) {
+    let sign = 1f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    return (vector(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            vector(b, sign + n.y * n.y * a, -n.y));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cosine_samples_favour_the_normal() {
+        let mut rng = Rng::new(7);
+        let n = vector(1.0, 2.0, -2.0).normalize();
+        let samples = 20000;
+        let mut total_cos = 0.0;
+        for _ in 0..samples {
+            let d = cosine_sample_hemisphere(n, &mut rng);
+            assert!(float_eq!(d.mag(), 1.0));
+            assert!(d * n >= 0.0);
+            total_cos += d * n;
+        }
+        // The mean of cos θ over the density cos θ / π is 2/3
+        assert!((total_cos / samples as f64 - 2.0 / 3.0).abs() < 0.01);
+    }
+
+    // Projected onto the surface the microfacets cover it exactly once
+    #[test]
+    fn ggx_distribution_is_normalized() {
+        for &alpha in [0.1, 0.5, 1.0].iter() {
+            let steps = 20000;
+            let mut total = 0.0;
+            for i in 0..steps {
+                let theta = (i as f64 + 0.5) / steps as f64 * PI / 2.0;
+                total += ggx_distribution(theta.cos(), alpha) * theta.cos() * theta.sin();
+            }
+            total *= 2.0 * PI * (PI / 2.0) / steps as f64;
+            assert!((total - 1.0).abs() < 1e-3, "alpha {}: {}", alpha, total);
+        }
+    }
+
+    #[test]
+    fn fresnel_and_shadowing_limits() {
+        let f0 = color(0.04, 0.04, 0.04);
+        assert_eq!(schlick_fresnel(f0, 1.0), f0);
+        assert_eq!(schlick_fresnel(f0, 0.0), Color::WHITE);
+        assert!(float_eq!(smith_g1(1.0, 0.5), 1.0));
+        assert_eq!(smith_g1(0.0, 0.5), 0.0);
+        assert!(smith_g1(0.2, 0.8) < smith_g1(0.2, 0.2));
+    }
+
+    // A rough white dielectric is nearly diffuse; a metal has no diffuse
+    // layer and is brightest in the mirror direction
+    #[test]
+    fn metallic_roughness_reflectance() {
+        let n = vector(0.0, 1.0, 0.0);
+        let eye = vector(1.0, 1.0, 0.0).normalize();
+        let mirror = vector(-1.0, 1.0, 0.0).normalize();
+        let side = vector(0.0, 1.0, 1.0).normalize();
+
+        let plastic = metallic_roughness(Color::WHITE, 0.0, 1.0, side, eye, n);
+        assert!((plastic.red - side.y).abs() < 0.1, "{:?}", plastic);
+
+        let gold = color(1.0, 0.78, 0.34);
+        let highlight = metallic_roughness(gold, 1.0, 0.2, mirror, eye, n);
+        let off = metallic_roughness(gold, 1.0, 0.2, side, eye, n);
+        assert!(highlight.red > 10.0 * off.red);
+        assert!(highlight.blue < highlight.red);
+        assert_eq!(metallic_roughness(gold, 1.0, 0.2, -mirror, eye, n), Color::BLACK);
+    }
+
+    #[test]
+    fn oren_nayar_reflectance() {
+        let n = vector(0.0, 0.0, 1.0);
+        let eye = vector(0.0, 0.6, 0.8);
+        let light = vector(0.8, 0.0, 0.6);
+        let albedo = color(0.5, 0.5, 0.5);
+        // No roughness is Lambert
+        let lambert = oren_nayar(albedo, 0.0, light, eye, n);
+        assert_eq!(lambert, albedo * 0.6);
+        // Rough surfaces reflect less to the side and more back towards a
+        // low light
+        assert!(oren_nayar(albedo, 0.5, light, eye, n).red < lambert.red);
+        assert!(oren_nayar(albedo, 0.5, light, light, n).red > lambert.red);
+        assert_eq!(oren_nayar(albedo, 0.5, -light, eye, n), Color::BLACK);
+    }
+
+    // Sampled paths average to the reflectance integrated over the
+    // hemisphere, the fraction of light reflected. That never exceeds 1, and
+    // rough metals lose a lot to shadowing between microfacets.
+    #[test]
+    fn sampled_reflection_matches_integral() {
+        let n = vector(0.0, 0.0, 1.0);
+        let eye = vector(0.3, 0.0, 1.0).normalize();
+        let mut rng = Rng::new(3);
+        for &(metallic, roughness) in [(0.0, 0.5), (1.0, 0.1), (1.0, 0.9)].iter() {
+            let steps = 200;
+            let mut integral = 0.0;
+            for i in 0..steps {
+                for j in 0..steps {
+                    let theta = (i as f64 + 0.5) / steps as f64 * PI / 2.0;
+                    let phi = (j as f64 + 0.5) / steps as f64 * 2.0 * PI;
+                    let l = vector(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                    integral += metallic_roughness(Color::WHITE, metallic, roughness, l, eye, n).red * theta.sin();
+                }
+            }
+            integral *= (PI / 2.0) * (2.0 * PI) / (PI * (steps * steps) as f64);
+
+            let samples = 40000;
+            let mut total = Color::BLACK;
+            for _ in 0..samples {
+                if let Some((dir, weight)) = sample_metallic_roughness(Color::WHITE, metallic, roughness, eye, n, &mut rng) {
+                    assert!(dir * n > 0.0);
+                    total = total + weight;
+                }
+            }
+            let sampled = (total / samples as f64).red;
+            assert!(integral <= 1.0);
+            assert!((sampled - integral).abs() < 0.02, "metallic {} roughness {}: {} vs {}",
+                    metallic, roughness, sampled, integral);
+        }
+    }
+}
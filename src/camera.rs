@@ -1,13 +1,42 @@
 use crate::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Camera {
     pub hsize: usize,
     pub vsize: usize,
     pub field_of_view: f64,
-    pub transform: M4,
+
+    // Motion blur: every pixel averages `time_samples` rays cast at jittered
+    // times between the shutter opening and closing. Moving shapes go from
+    // their start to their end transform while the shutter is open, whatever
+    // the times are.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    pub time_samples: usize,
+
+    // Antialiasing: rays per pixel, each jittered within the pixel. Combined
+    // with motion blur every pixel casts `samples * time_samples` rays.
+    pub samples: usize,
+
+    // Adaptive sampling: pixels whose estimated error is still above
+    // `noise_threshold` after `samples` keep sampling, one more antialiasing
+    // sample at a time, until it drops below or they reach `max_samples`.
+    // The error is the standard error of the pixel's mean, in the units of
+    // the colors, so 0.01 is a hundredth of white. Flat, evenly lit areas
+    // stop early and the samples go to edges, soft shadows and the noisier
+    // parts of path traced images. Off while `max_samples` is no more than
+    // `samples`, which should be at least a few for the estimate to mean
+    // anything.
+    pub max_samples: usize,
+    pub noise_threshold: f64,
+
+    // How each ray's color is computed, Whitted shading by default. The path
+    // tracer is noisy and needs many `samples` per pixel.
+    pub integrator: Integrator,
 
     // Cached calculations
+    transform: Transform,
     pixel_size: f64,
     half_width: f64,
     half_height: f64,
@@ -15,6 +44,15 @@ pub struct Camera {
 
 impl Camera {
     pub fn new(hsize: usize, vsize: usize, field_of_view: f64, transform: M4) -> Self {
+        match Self::try_new(hsize, vsize, field_of_view, transform) {
+            Ok(camera) => camera,
+            Err(e) => panic!("Camera {}: {:?}", e, transform),
+        }
+    }
+
+    // Fails for view transforms that can't be inverted, e.g. one looking
+    // from a point to itself or with `up` along the view direction.
+    pub fn try_new(hsize: usize, vsize: usize, field_of_view: f64, transform: M4) -> Result<Self, TransformError> {
         let half_view = (field_of_view / 2.0).tan();
         let aspect = hsize as f64 / vsize as f64;
 
@@ -26,7 +64,14 @@ impl Camera {
 
         let pixel_size = half_width * 2.0 / hsize as f64;
 
-        Self { hsize, vsize, field_of_view, transform, pixel_size, half_width, half_height }
+        return Ok(Self {
+            hsize, vsize, field_of_view,
+            transform: Transform::try_new(transform)?,
+            shutter_open: 0.0, shutter_close: 0.0, time_samples: 1, samples: 1,
+            max_samples: 1, noise_threshold: 0.01,
+            integrator: Integrator::Whitted,
+            pixel_size, half_width, half_height,
+        });
     }
 
     pub fn simple(hsize: usize, vsize: usize, field_of_view: f64) -> Self {
@@ -37,9 +82,35 @@ impl Camera {
         self.pixel_size
     }
 
+    pub fn transform(self) -> M4 {
+        self.transform.matrix()
+    }
+
+    pub fn set_transform(&mut self, transform: M4) -> &mut Self {
+        self.transform = Transform::new(transform);
+        return self;
+    }
+
+    pub fn try_set_transform(&mut self, transform: M4) -> Result<&mut Self, TransformError> {
+        self.transform = Transform::try_new(transform)?;
+        return Ok(self);
+    }
+
     pub fn ray_for_pixel(self, px: usize, py: usize) -> Ray {
-        let xoffset = (px as f64 + 0.5) * self.pixel_size;
-        let yoffset = (py as f64 + 0.5) * self.pixel_size;
+        self.ray_for_pixel_at(px, py, self.shutter_open)
+    }
+
+    pub fn ray_for_pixel_at(self, px: usize, py: usize, time: f64) -> Ray {
+        self.ray_for_subpixel(px, py, 0.5, 0.5, time)
+    }
+
+    // A ray through the point (dx, dy) of the pixel, where (0.5, 0.5) is its
+    // center, at `time` between `shutter_open` and `shutter_close`. The ray
+    // carries the time as the fraction of the exposure gone by, the time
+    // shapes' motion is keyed to.
+    pub fn ray_for_subpixel(self, px: usize, py: usize, dx: f64, dy: f64, time: f64) -> Ray {
+        let xoffset = (px as f64 + dx) * self.pixel_size;
+        let yoffset = (py as f64 + dy) * self.pixel_size;
 
         let world_x = self.half_width - xoffset;
         let world_y = self.half_height - yoffset;
@@ -51,26 +122,317 @@ impl Camera {
 
         let direction = (pixel - origin).normalize();
 
-        return Ray::new(origin, direction);
+        return Ray::with_time(origin, direction, self.exposure_fraction(time));
+    }
+
+    // 0.0 when the shutter opens and 1.0 when it closes. A shutter that
+    // opens and closes at once sees everything at its start.
+    fn exposure_fraction(self, time: f64) -> f64 {
+        let exposure = self.shutter_close - self.shutter_open;
+        if exposure <= 0.0 { return 0.0; }
+        return (time - self.shutter_open) / exposure;
+    }
+
+    // The time of sample `i` out of `time_samples`. The shutter interval is
+    // split into equal strata and each sample is jittered within its own.
+    fn sample_time(self, i: usize, rng: &mut Rng) -> f64 {
+        let n = self.time_samples.max(1) as f64;
+        let u = (i as f64 + rng.next_f64()) / n;
+        self.shutter_open + u * (self.shutter_close - self.shutter_open)
     }
 
     pub fn render(self, world: &World) -> Canvas {
+        self.render_with(world, 1, |_| ())
+    }
+
+    // Render using `threads` worker threads, which take rows from a shared
+    // counter. `progress` is called from the workers with the number of rows
+    // finished so far. Every pixel seeds its own random numbers so the image
+    // does not depend on the number of threads.
+    pub fn render_with<F>(self, world: &World, threads: usize, progress: F) -> Canvas
+        where F: Fn(usize) + Sync {
+        return self.render_cancellable(world, threads, &CancelToken::new(), progress).0;
+    }
+
+    // `render_with` that gives up once `cancel` is cancelled or runs out of
+    // time. The pixels finished by then are in the image, the rest are black.
+    pub fn render_cancellable<F>(self, world: &World, threads: usize, cancel: &CancelToken,
+                                 progress: F) -> (Canvas, RenderReport)
+        where F: Fn(usize) + Sync {
+        let rows = self.render_rows(threads, cancel, progress, |col, row, xs1, xs2| {
+            self.render_pixel(world, col, row, xs1, xs2)
+        });
+
         let mut canvas = Canvas::new(self.hsize, self.vsize);
-        let mut xs1 = Intersections::empty();
-        let mut xs2 = Intersections::empty();
+        let mut pixels_done = 0;
+        for (row, colors) in rows {
+            pixels_done += colors.len();
+            for (col, c) in colors.into_iter().enumerate() {
+                canvas.write_pixel(col as i32, row as i32, c);
+            }
+        }
+        let pixels = self.hsize * self.vsize;
+        let report = RenderReport {
+            pixels_done, pixels,
+            passes_done: if pixels_done == pixels { 1 } else { 0 },
+            cancelled: pixels_done < pixels,
+        };
+        return (canvas, report);
+    }
 
-        for row in 0..self.vsize {
-            for col in 0..self.hsize {
+    // Renders pass by pass instead of row by row: every pass adds one
+    // antialiasing sample to each pixel that still needs one, and `snapshot`
+    // is called with the number of passes done and the image so far. It
+    // returns whether to go on, so a long render can be looked at early and
+    // stopped once it is good enough. There are `samples` passes, or
+    // `max_samples` with adaptive sampling, and run to the end the image is
+    // the same `render_with` gives.
+    //
+    // Cancelling `cancel` stops the render in the middle of a pass. The
+    // image returned then has the pixels of that pass that were sampled, one
+    // sample ahead of the last snapshot.
+    pub fn render_progressive<F>(self, world: &World, threads: usize, cancel: &CancelToken,
+                                 mut snapshot: F) -> (Canvas, RenderReport)
+        where F: FnMut(usize, &Canvas) -> bool {
+        let mut pixels: Vec<Vec<PixelSamples>> = (0..self.vsize)
+            .map(|row| (0..self.hsize).map(|col| PixelSamples::new(col, row)).collect())
+            .collect();
+        let mut canvas = Canvas::new(self.hsize, self.vsize);
+        let mut report = RenderReport {
+            pixels_done: 0, pixels: self.hsize * self.vsize, passes_done: 0, cancelled: false,
+        };
+
+        for pass in 1..=self.passes() {
+            let rows = self.render_rows(threads, cancel, |_| (), |col, row, xs1, xs2| {
+                let mut p = pixels[row][col];
+                if !self.pixel_done(&p) {
+                    self.sample_more(world, col, row, &mut p, xs1, xs2);
+                }
+                p
+            });
+            let mut sampled = 0;
+            for (row, samples) in rows {
+                sampled += samples.len();
+                for (col, p) in samples.into_iter().enumerate() {
+                    canvas.write_pixel(col as i32, row as i32, p.mean);
+                    pixels[row][col] = p;
+                }
+            }
+            if sampled < report.pixels {
+                report.cancelled = true;
+                break;
+            }
+            report.passes_done = pass;
+
+            let finished = pixels.iter().flatten().all(|p| self.pixel_done(p));
+            let go_on = snapshot(pass, &canvas);
+            if finished { break; }
+            if !go_on {
+                report.cancelled = true;
+                break;
+            }
+        }
+
+        report.pixels_done = pixels.iter().flatten().filter(|p| self.pixel_done(p)).count();
+        return (canvas, report);
+    }
+
+    // The most passes `render_progressive` makes
+    pub fn passes(self) -> usize {
+        self.max_samples.max(self.samples).max(1)
+    }
+
+    // The rendered image together with the other layers described in `aov`
+    pub fn render_layers(self, world: &World) -> RenderLayers {
+        self.render_layers_with(world, 1, |_| ())
+    }
+
+    // `render_layers` on several threads, like `render_with`. The beauty
+    // layer is the same image `render_with` gives.
+    pub fn render_layers_with<F>(self, world: &World, threads: usize, progress: F) -> RenderLayers
+        where F: Fn(usize) + Sync {
+        let rows = self.render_rows(threads, &CancelToken::new(), progress, |col, row, xs1, xs2| {
+            let beauty = self.render_pixel(world, col, row, xs1, xs2);
+            self.pixel_layers(world, col, row, beauty, xs1, xs2)
+        });
+
+        let mut layers = RenderLayers::new(self.hsize, self.vsize);
+        for (row, pixels) in rows {
+            for (col, p) in pixels.into_iter().enumerate() {
+                layers.write_pixel(col as i32, row as i32, p);
+            }
+        }
+        return layers;
+    }
+
+    // Runs `pixel` for every pixel on `threads` workers and returns the
+    // rows, in no particular order. Once `cancel` is cancelled the workers
+    // stop, leaving the rest of the rows out and the rows they were on cut
+    // short.
+    fn render_rows<'a, T, P, F>(self, threads: usize, cancel: &CancelToken, progress: F,
+                                pixel: P) -> Vec<(usize, Vec<T>)>
+        where T: Send,
+              P: Fn(usize, usize, &mut Intersections<'a>, &mut Intersections<'a>) -> T + Sync,
+              F: Fn(usize) + Sync {
+        let next_row = AtomicUsize::new(0);
+        let rows_done = AtomicUsize::new(0);
+
+        return std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads.max(1)).map(|_| scope.spawn(|| {
+                let mut rows = vec![];
+                let mut xs1 = Intersections::empty();
+                let mut xs2 = Intersections::empty();
+                loop {
+                    let row = next_row.fetch_add(1, Ordering::Relaxed);
+                    if row >= self.vsize || cancel.is_cancelled() { break; }
+                    let values: Vec<T> = (0..self.hsize)
+                        .map_while(|col| if cancel.is_cancelled() {
+                            None
+                        } else {
+                            Some(pixel(col, row, &mut xs1, &mut xs2))
+                        })
+                        .collect();
+                    let whole = values.len() == self.hsize;
+                    rows.push((row, values));
+                    if !whole { break; }
+                    progress(rows_done.fetch_add(1, Ordering::Relaxed) + 1);
+                }
+                rows
+            })).collect();
+            workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+        });
+    }
+
+    fn render_pixel<'a>(self, world: &'a World, col: usize, row: usize,
+                        xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> Color {
+        return self.sample_pixel(world, col, row, xs1, xs2).0;
+    }
+
+    // The color of a pixel and the number of rays it took
+    fn sample_pixel<'a>(self, world: &'a World, col: usize, row: usize,
+                        xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> (Color, usize) {
+        let mut p = PixelSamples::new(col, row);
+        while !self.pixel_done(&p) {
+            self.sample_more(world, col, row, &mut p, xs1, xs2);
+        }
+        return (p.mean, p.n);
+    }
+
+    // Whether a pixel has all the samples it needs: `samples`, and with
+    // adaptive sampling however many more it takes to bring the error
+    // down, up to `max_samples`
+    fn pixel_done(self, p: &PixelSamples) -> bool {
+        let time_samples = self.time_samples.max(1);
+        let min_samples = self.samples.max(1) * time_samples;
+        let max_samples = self.max_samples.max(self.samples).max(1) * time_samples;
+        if p.n >= max_samples { return true; }
+        if p.n < min_samples || p.n < 2 { return false; }
+        let variance = p.m2 / (p.n - 1) as f64;
+        let worst = variance.red.max(variance.green).max(variance.blue);
+        return (worst / p.n as f64).sqrt() < self.noise_threshold;
+    }
+
+    // One more antialiasing sample: a ray for each of the `time_samples`,
+    // so the shutter strata stay even
+    fn sample_more<'a>(self, world: &'a World, col: usize, row: usize, p: &mut PixelSamples,
+                       xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) {
+        for _ in 0..self.time_samples.max(1) {
+            xs1.clear();
+            xs2.clear();
+            let ray = self.sample_ray(col, row, p.n, &mut p.rng);
+            let c = self.integrator.color_at(world, ray, &mut p.rng, xs1, xs2);
+            p.add(c);
+        }
+    }
+
+    // The `i`th ray of a pixel, at a jittered time and position when there
+    // is motion blur or antialiasing
+    fn sample_ray(self, col: usize, row: usize, i: usize, rng: &mut Rng) -> Ray {
+        let time_samples = self.time_samples.max(1);
+        let time = if time_samples == 1 {
+            self.shutter_open
+        } else {
+            self.sample_time(i % time_samples, rng)
+        };
+        let (dx, dy) = if self.samples.max(self.max_samples) > 1 {
+            (rng.next_f64(), rng.next_f64())
+        } else {
+            (0.5, 0.5)
+        };
+        return self.ray_for_subpixel(col, row, dx, dy, time);
+    }
+
+    // The layers other than beauty cast their own rays, one for each of the
+    // beauty's samples, or of its first `samples` with adaptive sampling
+    fn pixel_layers<'a>(self, world: &'a World, col: usize, row: usize, beauty: Color,
+                        xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> PixelLayers {
+        let samples = self.samples.max(1) * self.time_samples.max(1);
+        let mut rng = Rng::for_pixel(col, row);
+        let mut p = PixelLayers {
+            beauty, depth: None, normal: vector(0.0, 0.0, 0.0), albedo: Color::BLACK, object: None, shadow: 0.0,
+        };
+
+        for i in 0..samples {
+            let ray = self.sample_ray(col, row, i, &mut rng);
+
+            // Objects one at a time, to know which one the hit belongs to
+            let mut nearest: Option<(Intersection, usize)> = None;
+            for (index, object) in world.objects.iter().enumerate() {
                 xs1.clear();
-                xs2.clear();
-                let ray = self.ray_for_pixel(col, row);
-                let c = world.color_at(ray, &mut xs1, &mut xs2);
+                object.intersect(ray, xs1);
+                if let Some(hit) = xs1.hit() {
+                    if nearest.is_none_or(|(n, _)| hit.t < n.t) {
+                        nearest = Some((hit, index));
+                    }
+                }
+            }
+            let (hit, index) = match nearest {
+                None => continue,
+                Some(n) => n,
+            };
 
-                canvas.write_pixel(col as i32, row as i32, c);
+            let comps = hit.prepare_computations(ray);
+            if p.depth.is_none_or(|d| hit.t < d) {
+                p.depth = Some(hit.t);
+                p.object = Some(index);
+            }
+            p.normal = p.normal + comps.normalv;
+            p.albedo = p.albedo + comps.object.material().albedo();
+            if !world.lights.is_empty() {
+                xs2.clear();
+                if world.is_shadowed(comps.over_point, comps.time, xs2) {
+                    p.shadow += 1.0;
+                }
             }
         }
 
-        return canvas;
+        p.normal = p.normal / samples as f64;
+        p.albedo = p.albedo / samples as f64;
+        p.shadow /= samples as f64;
+        return p;
+    }
+}
+
+// The samples of one pixel so far: their running mean and variance
+// (Welford's method), and the random numbers to carry on with
+#[derive(Copy, Clone, Debug)]
+struct PixelSamples {
+    rng: Rng,
+    n: usize,
+    mean: Color,
+    m2: Color,
+}
+
+impl PixelSamples {
+    fn new(col: usize, row: usize) -> Self {
+        Self { rng: Rng::for_pixel(col, row), n: 0, mean: Color::BLACK, m2: Color::BLACK }
+    }
+
+    fn add(&mut self, c: Color) {
+        self.n += 1;
+        let delta = c - self.mean;
+        self.mean = self.mean + delta / self.n as f64;
+        self.m2 = self.m2 + delta * (c - self.mean);
     }
 }
 
@@ -80,6 +442,7 @@ mod test {
     use std::f64::consts::FRAC_PI_2;
     use std::f64::consts::FRAC_PI_4;
     use std::f64::consts::FRAC_1_SQRT_2 as S2O2;
+    use std::time::Duration;
 
     #[test]
     fn constructing_a_camera() {
@@ -87,7 +450,7 @@ mod test {
         assert_eq!(c.hsize, 160);
         assert_eq!(c.vsize, 120);
         assert_eq!(c.field_of_view, FRAC_PI_2);
-        assert_eq!(c.transform, M4::IDENTITY);
+        assert_eq!(c.transform(), M4::IDENTITY);
     }
 
     #[test]
@@ -124,6 +487,25 @@ mod test {
         assert_eq!(r.direction, vector(S2O2, 0.0, -S2O2));
     }
 
+    #[test]
+    fn set_transform_updates_rays() {
+        let m = rotation_y(FRAC_PI_4) * translation(0.0, -2.0, 5.0);
+        let mut c = Camera::simple(201, 101, FRAC_PI_2);
+        c.set_transform(m);
+        assert_eq!(c.transform(), m);
+        assert_eq!(c.ray_for_pixel(100, 50), Camera::new(201, 101, FRAC_PI_2, m).ray_for_pixel(100, 50));
+    }
+
+    #[test]
+    fn degenerate_view_transform_is_an_error() {
+        let up_along_view = view_transform(point(0.0, 0.0, -5.0), T4::ZERO, vector(0.0, 0.0, 1.0));
+        assert!(Camera::try_new(11, 11, FRAC_PI_2, up_along_view).is_err());
+
+        let mut c = Camera::simple(11, 11, FRAC_PI_2);
+        assert!(c.try_set_transform(scaling(1.0, 0.0, 1.0)).is_err());
+        assert_eq!(c.transform(), M4::IDENTITY);
+    }
+
     #[test]
     fn rendering_world_with_camera() {
         let w = World::simple();
@@ -133,9 +515,270 @@ mod test {
         let to = T4::ZERO;
         let up = T4::EY;
 
-        c.transform = view_transform(from, to, up);
+        c.set_transform(view_transform(from, to, up));
 
         let col = c.render(&w).pixel_at(5, 5);
         assert_eq!(col, color_rgb!(0.38066, 0.47583, 0.2855))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ray_for_pixel_carries_time() {
+        let mut c = Camera::simple(201, 101, FRAC_PI_2);
+        c.shutter_open = 0.25;
+        assert_eq!(c.ray_for_pixel(100, 50).time, 0.0);
+        c.shutter_close = 0.75;
+        assert_eq!(c.ray_for_pixel(100, 50).time, 0.0);
+        assert_eq!(c.ray_for_pixel_at(100, 50, 0.5).time, 0.5);
+        assert_eq!(c.ray_for_pixel_at(100, 50, 0.75).time, 1.0);
+    }
+
+    #[test]
+    fn sample_times_stay_within_shutter() {
+        let mut c = Camera::simple(11, 11, FRAC_PI_2);
+        c.shutter_open = 0.2;
+        c.shutter_close = 0.6;
+        c.time_samples = 4;
+        let mut rng = Rng::new(1);
+        for i in 0..4 {
+            let t = c.sample_time(i, &mut rng);
+            assert!(t >= 0.2 + 0.1 * i as f64 && t < 0.2 + 0.1 * (i + 1) as f64);
+        }
+    }
+
+    #[test]
+    fn rendering_moving_sphere_blurs() {
+        let simple = World::simple();
+        let mut w = World::new(vec![], simple.lights.clone());
+        for obj in simple.objects.iter() {
+            let mut s = Sphere::new(obj.transform(), obj.material());
+            s.set_end_transform(translation(3.0, 0.0, 0.0) * obj.transform());
+            w.add_object(s);
+        }
+        let mut c = Camera::simple(11, 11, FRAC_PI_2);
+        c.set_transform(view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.shutter_close = 1.0;
+        c.time_samples = 16;
+
+        // The sphere only covers the centre for part of the exposure, so the
+        // pixel is a blend of the lit sphere and the black background.
+        let col = c.render(&w).pixel_at(5, 5);
+        assert!(col.red > 0.0 && col.red < 0.38066);
+    }
+
+    // The sphere covers its whole path while the shutter is open, wherever
+    // the shutter times are
+    #[test]
+    fn shutter_times_span_the_whole_motion() {
+        let mut w = World::default();
+        let mut s = Sphere::from_transform(scaling(0.5, 0.5, 0.5));
+        s.set_end_transform(translation(3.0, 0.0, 0.0) * scaling(0.5, 0.5, 0.5));
+        w.add_object(s);
+        let mut c = Camera::simple(11, 11, FRAC_PI_2);
+        c.set_transform(view_transform(point(1.5, 0.0, -5.0), point(1.5, 0.0, 0.0), T4::EY));
+        c.time_samples = 8;
+        c.shutter_close = 1.0;
+        let unit = c.render(&w);
+
+        for &(open, close) in [(0.2, 0.6), (2.0, 4.0), (0.0, 2.0)].iter() {
+            c.shutter_open = open;
+            c.shutter_close = close;
+            assert_eq!(c.ray_for_pixel_at(5, 5, close).time, 1.0);
+            let image = c.render(&w);
+            for y in 0..11 {
+                for x in 0..11 {
+                    assert_eq!(image.pixel_at(x, y), unit.pixel_at(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn threaded_render_matches_single_thread() {
+        let w = World::simple();
+        let mut c = Camera::simple(11, 11, FRAC_PI_2);
+        c.set_transform(view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 4;
+
+        let rows = AtomicUsize::new(0);
+        let threaded = c.render_with(&w, 4, |done| { rows.fetch_max(done, Ordering::Relaxed); });
+        let single = c.render(&w);
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(threaded.pixel_at(x, y), single.pixel_at(x, y));
+            }
+        }
+        assert_eq!(rows.load(Ordering::Relaxed), 11);
+    }
+
+    #[test]
+    fn antialiasing_softens_edges() {
+        let w = World::simple();
+        let mut c = Camera::simple(11, 11, FRAC_PI_2);
+        c.set_transform(view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        let hard = c.render(&w);
+        c.samples = 16;
+        let soft = c.render(&w);
+
+        // Pixels straddling the silhouette blend sphere and background
+        assert!((0..11).any(|x| soft.pixel_at(x, 5) != hard.pixel_at(x, 5)));
+    }
+
+    // The middle of the sphere is the same color wherever the ray lands in
+    // the pixel, its silhouette isn't
+    #[test]
+    fn adaptive_sampling_spends_samples_on_edges() {
+        let w = World::simple();
+        let mut c = Camera::new(11, 11, 0.6, view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 4;
+        c.max_samples = 64;
+        c.noise_threshold = 0.02;
+        let (xs1, xs2) = (&mut Intersections::empty(), &mut Intersections::empty());
+        let (_, inside) = c.sample_pixel(&w, 5, 5, xs1, xs2);
+        let (_, outside) = c.sample_pixel(&w, 0, 0, xs1, xs2);
+        assert_eq!((inside, outside), (4, 4));
+        let edge = (0..11).map(|x| c.sample_pixel(&w, x, 5, xs1, xs2).1).max().unwrap();
+        assert!(edge > 4 && edge <= 64, "{}", edge);
+
+        // Without a maximum above `samples` nothing changes
+        c.max_samples = 1;
+        assert!((0..11).all(|x| c.sample_pixel(&w, x, 5, xs1, xs2).1 == 4));
+    }
+
+    #[test]
+    fn adaptive_sampling_keeps_time_strata_whole() {
+        let w = World::simple();
+        let mut c = Camera::new(11, 11, 0.6, view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 2;
+        c.time_samples = 3;
+        c.max_samples = 8;
+        c.noise_threshold = 0.0;
+        let (_, n) = c.sample_pixel(&w, 5, 5, &mut Intersections::empty(), &mut Intersections::empty());
+        assert_eq!(n, 24);
+        c.noise_threshold = 1.0;
+        let (_, n) = c.sample_pixel(&w, 5, 5, &mut Intersections::empty(), &mut Intersections::empty());
+        assert_eq!(n, 6);
+    }
+
+    #[test]
+    fn progressive_render_ends_with_the_full_image() {
+        let w = World::simple();
+        let mut c = Camera::new(11, 11, 0.6, view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 4;
+        let mut passes = vec![];
+        let (progressive, report) = c.render_progressive(&w, 2, &CancelToken::new(), |pass, _| {
+            passes.push(pass);
+            true
+        });
+        assert!(report.is_complete() && !report.cancelled);
+        assert_eq!(passes, vec![1, 2, 3, 4]);
+        let full = c.render(&w);
+
+        // With adaptive sampling it stops once every pixel is done
+        c.max_samples = 64;
+        c.noise_threshold = 0.02;
+        let mut last = 0;
+        let (adaptive, report) = c.render_progressive(&w, 2, &CancelToken::new(), |pass, _| {
+            last = pass;
+            true
+        });
+        assert!(last > 4 && last <= c.passes());
+        assert_eq!(report.passes_done, last);
+        let adaptive_full = c.render(&w);
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(progressive.pixel_at(x, y), full.pixel_at(x, y));
+                assert_eq!(adaptive.pixel_at(x, y), adaptive_full.pixel_at(x, y));
+            }
+        }
+    }
+
+    // Stopped after two passes, every pixel has two samples
+    #[test]
+    fn progressive_render_can_stop_early() {
+        let w = World::simple();
+        let mut c = Camera::new(11, 11, 0.6, view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 16;
+        let mut snapshots = vec![];
+        let (early, report) = c.render_progressive(&w, 1, &CancelToken::new(), |pass, image| {
+            snapshots.push(image.pixel_at(3, 3));
+            pass < 2
+        });
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(report, RenderReport { pixels_done: 0, pixels: 121, passes_done: 2, cancelled: true });
+        assert_eq!(early.pixel_at(3, 3), snapshots[1]);
+
+        c.samples = 2;
+        let two = c.render(&w);
+        for y in 0..11 {
+            for x in 0..11 {
+                assert_eq!(early.pixel_at(x, y), two.pixel_at(x, y));
+            }
+        }
+    }
+
+    // One thread renders the rows in order, so cancelling after the third
+    // leaves the rest black
+    #[test]
+    fn cancelled_render_keeps_finished_rows() {
+        let w = World::simple();
+        let c = Camera::new(11, 11, 0.6, view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        let cancel = CancelToken::new();
+        let (partial, report) = c.render_cancellable(&w, 1, &cancel, |done| if done == 3 { cancel.cancel() });
+        assert_eq!(report, RenderReport { pixels_done: 33, pixels: 121, passes_done: 0, cancelled: true });
+        assert!(!report.is_complete());
+        let full = c.render(&w);
+        assert_eq!(partial.pixel_at(5, 2), full.pixel_at(5, 2));
+        assert_eq!(partial.pixel_at(5, 5), Color::BLACK);
+
+        let (_, report) = c.render_cancellable(&w, 2, &CancelToken::with_time_budget(Duration::ZERO), |_| ());
+        assert_eq!(report.pixels_done, 0);
+        let (_, report) = c.render_cancellable(&w, 2, &CancelToken::new(), |_| ());
+        assert!(report.is_complete() && report.passes_done == 1 && !report.cancelled);
+    }
+
+    #[test]
+    fn cancelled_progressive_render_keeps_finished_passes() {
+        let w = World::simple();
+        let mut c = Camera::new(11, 11, 0.6, view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 8;
+        let cancel = CancelToken::new();
+        let (partial, report) = c.render_progressive(&w, 2, &cancel, |pass, _| {
+            if pass == 2 { cancel.cancel(); }
+            true
+        });
+        assert_eq!((report.passes_done, report.pixels_done, report.cancelled), (2, 0, true));
+        c.samples = 2;
+        assert_eq!(partial.pixel_at(4, 4), c.render(&w).pixel_at(4, 4));
+    }
+
+    #[test]
+    fn rendering_layers() {
+        let mut w = World::simple();
+        // A small sphere between the light and the middle of the big one
+        w.add_object(Sphere::from_transform(translation(-0.6, 0.6, -1.5) * scaling(0.2, 0.2, 0.2)));
+        let mut c = Camera::new(11, 11, FRAC_PI_2,
+                                view_transform(point(0.0, 0.0, -5.0), T4::ZERO, T4::EY));
+        c.samples = 4;
+        let layers = c.render_layers_with(&w, 2, |_| ());
+
+        let beauty = c.render(&w);
+        for (x, y) in [(5, 5), (0, 0), (3, 4)].iter().copied() {
+            assert_eq!(layers.beauty.pixel_at(x, y), beauty.pixel_at(x, y));
+        }
+
+        c.samples = 1;
+        let layers = c.render_layers(&w);
+        // The middle of the big sphere faces the camera head on
+        assert_eq!(layers.depth.pixel_at(5, 5), color(4.0, 4.0, 4.0));
+        assert_eq!(layers.normal.pixel_at(5, 5), color(0.0, 0.0, -1.0));
+        assert_eq!(layers.albedo.pixel_at(5, 5), color(0.56, 0.7, 0.42));
+        assert_eq!(layers.object.pixel_at(5, 5), color(0.0, 0.0, 0.0));
+        assert_eq!(layers.shadow.pixel_at(5, 5), Color::WHITE);
+        assert_eq!(layers.shadow.pixel_at(4, 5), Color::BLACK);
+        // Nothing in the corner
+        assert_eq!(layers.depth.pixel_at(0, 0), Color::BLACK);
+        assert_eq!(layers.object.pixel_at(0, 0), color(-1.0, -1.0, -1.0));
+
+        assert_eq!(layers.object.pixel_at(4, 4), color(2.0, 2.0, 2.0));
+    }
+}
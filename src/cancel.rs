@@ -0,0 +1,82 @@
+// Stopping renders before they are done, for previews that have to give up
+// on a render as soon as the scene changes or that only have so much time.
+//
+// A `CancelToken` is handed to `Camera::render_cancellable` or
+// `Camera::render_progressive`; its clones share one flag, so another thread
+// can keep a clone and cancel the render with it. A token can also be given
+// a time budget, after which it counts as cancelled by itself. The render
+// returns the image as far as it got along with a `RenderReport`.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancelToken {
+    // A token that is only cancelled by `cancel`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A token that also cancels itself once `budget` has passed from now
+    pub fn with_time_budget(budget: Duration) -> Self {
+        Self { cancelled: Arc::default(), deadline: Instant::now().checked_add(budget) }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Relaxed)
+            || self.deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    }
+}
+
+// How far a render got
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct RenderReport {
+    // Pixels that got all their samples, out of `pixels`. The others are
+    // black in a row by row render, and have fewer samples, and so more
+    // noise, in a progressive one.
+    pub pixels_done: usize,
+    pub pixels: usize,
+    // Passes a progressive render finished over the whole image. A row by
+    // row render counts as a single pass.
+    pub passes_done: usize,
+    // Whether the render was stopped by its token or its snapshot callback
+    pub cancelled: bool,
+}
+
+impl RenderReport {
+    pub fn is_complete(self) -> bool {
+        self.pixels_done == self.pixels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_flag() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        std::thread::spawn(move || clone.cancel()).join().unwrap();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn time_budgets_run_out() {
+        assert!(CancelToken::with_time_budget(Duration::ZERO).is_cancelled());
+        let token = CancelToken::with_time_budget(Duration::from_secs(3600));
+        assert!(!token.is_cancelled());
+        token.cancel();
+        assert!(token.is_cancelled());
+    }
+}
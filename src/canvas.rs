@@ -1,4 +1,9 @@
 use crate::color::*;
+use crate::png::{self, BitDepth};
+use crate::hdr;
+use crate::tonemap::Encoding;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
 
 #[derive(Clone, Debug)]
 pub struct Canvas {
@@ -36,6 +41,10 @@ impl Canvas {
     }
 
     pub fn to_ppm(&self) -> String {
+        self.to_ppm_encoded(Encoding::default())
+    }
+
+    pub fn to_ppm_encoded(&self, encoding: Encoding) -> String {
         let mut s = WrappingStringBuilder::new(70);
 
         s.append_line("P3");
@@ -43,6 +52,7 @@ impl Canvas {
         s.append_line("255");
 
         for (i, p) in self.pixels.iter().copied().enumerate() {
+            let p = encoding.apply(p);
             let r = to_ppm_value(p.red);
             let g = to_ppm_value(p.green);
             let b = to_ppm_value(p.blue);
@@ -59,13 +69,108 @@ impl Canvas {
         return s.into_string();
     }
 
+    // Binary (P6) PPM, streamed straight to the writer. 8 bit samples use a
+    // maxval of 255, 16 bit samples a maxval of 65535 in big endian order.
+    pub fn write_ppm_binary<W: Write>(&self, out: &mut W, depth: BitDepth, encoding: Encoding) -> std::io::Result<()> {
+        let max = match depth {
+            BitDepth::Eight => 255.0,
+            BitDepth::Sixteen => 65535.0,
+        };
+        write!(out, "P6\n{} {}\n{}\n", self.width, self.height, max as u32)?;
+
+        let mut row = Vec::with_capacity(self.width * 6);
+        for line in self.pixels.chunks(self.width.max(1)) {
+            row.clear();
+            for p in line {
+                let p = encoding.apply(*p);
+                for x in [p.red, p.green, p.blue] {
+                    let v = to_channel_value(x, max);
+                    match depth {
+                        BitDepth::Eight => row.push(v as u8),
+                        BitDepth::Sixteen => row.extend_from_slice(&(v as u16).to_be_bytes()),
+                    }
+                }
+            }
+            out.write_all(&row)?;
+        }
+        return Ok(());
+    }
+
+    pub fn save_ppm_binary(&self, file: &str, depth: BitDepth, encoding: Encoding) -> std::io::Result<()> {
+        self.save_with(file, |c, out| c.write_ppm_binary(out, depth, encoding))
+    }
+
+    // Radiance RGBE, keeping the full dynamic range of the render
+    pub fn write_hdr<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        hdr::write_radiance(out, self.width, self.height, &self.pixels)
+    }
+
+    // Portable float map with 32 bit float samples
+    pub fn write_pfm<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        hdr::write_pfm(out, self.width, self.height, &self.pixels)
+    }
+
+    pub fn read_pfm<R: BufRead>(input: &mut R) -> std::io::Result<Self> {
+        let (width, height, pixels) = hdr::read_pfm(input)?;
+        Ok(Canvas { width, height, pixels })
+    }
+
+    pub fn load_pfm(file: &str) -> std::io::Result<Self> {
+        Self::read_pfm(&mut BufReader::new(File::open(file)?))
+    }
+
+    pub fn to_png(&self, depth: BitDepth, encoding: Encoding) -> Vec<u8> {
+        let max = match depth {
+            BitDepth::Eight => 255.0,
+            BitDepth::Sixteen => 65535.0,
+        };
+        let samples: Vec<u16> = self.pixels.iter()
+            .map(|&p| encoding.apply(p))
+            .flat_map(|p| [p.red, p.green, p.blue])
+            .map(|x| to_channel_value(x, max) as u16)
+            .collect();
+        png::encode(self.width, self.height, depth, &samples)
+    }
+
     pub fn save(&self, file: &str) -> std::io::Result<()> {
-        std::fs::write(file, self.to_ppm())
+        self.save_encoded(file, Encoding::default())
+    }
+
+    // The output format is chosen by the file extension: `.png` writes an
+    // 8 bit PNG, `.hdr` and `.pfm` the HDR formats, anything else an ASCII PPM.
+    // The encoding is ignored by the HDR formats, which store raw colours.
+    pub fn save_encoded(&self, file: &str, encoding: Encoding) -> std::io::Result<()> {
+        let ext = std::path::Path::new(file)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match ext.as_deref() {
+            Some("png") => std::fs::write(file, self.to_png(BitDepth::Eight, encoding)),
+            Some("hdr") => self.save_with(file, |c, out| c.write_hdr(out)),
+            Some("pfm") => self.save_with(file, |c, out| c.write_pfm(out)),
+            _ => std::fs::write(file, self.to_ppm_encoded(encoding)),
+        }
+    }
+
+    fn save_with<F>(&self, file: &str, write: F) -> std::io::Result<()>
+        where F: FnOnce(&Self, &mut BufWriter<File>) -> std::io::Result<()> {
+        let mut out = BufWriter::new(File::create(file)?);
+        write(self, &mut out)?;
+        out.flush()
+    }
+
+    pub fn save_png(&self, file: &str, depth: BitDepth, encoding: Encoding) -> std::io::Result<()> {
+        std::fs::write(file, self.to_png(depth, encoding))
     }
 }
 
 fn to_ppm_value(x: f64) -> i32 {
-    ((x * 255.0).round() as i32).max(0).min(255)
+    to_channel_value(x, 255.0) as i32
+}
+
+fn to_channel_value(x: f64, max: f64) -> u32 {
+    (x * max).round().clamp(0.0, max) as u32
 }
 
 #[derive(Debug, Clone)]
@@ -163,6 +268,73 @@ mod test {
 ");
     }
 
+    #[test]
+    fn canvas_ppm_binary() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, color(1.5, 0.5, 0.0));
+        c.write_pixel(1, 0, color(-0.5, 0.0, 1.0));
+        let mut out = Vec::new();
+        c.write_ppm_binary(&mut out, BitDepth::Eight, Encoding::default()).unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\xff\x80\x00\x00\x00\xff".to_vec());
+    }
+
+    #[test]
+    fn canvas_ppm_binary_sixteen_bit() {
+        let c = Canvas::new_with_color(1, 1, color(1.0, 0.5, 0.0));
+        let mut out = Vec::new();
+        c.write_ppm_binary(&mut out, BitDepth::Sixteen, Encoding::default()).unwrap();
+        assert_eq!(out, b"P6\n1 1\n65535\n\xff\xff\x80\x00\x00\x00".to_vec());
+    }
+
+    #[test]
+    fn canvas_ppm_encoded() {
+        use crate::tonemap::{ToneMap, Transfer};
+        let c = Canvas::new_with_color(1, 1, color(1.0, 0.5, 0.0));
+        let s = c.to_ppm_encoded(Encoding::new(ToneMap::Reinhard, Transfer::Linear));
+        assert_eq!(s, "P3\n1 1\n255\n128 85 0\n");
+        let s = c.to_ppm_encoded(Encoding::new(ToneMap::Clamp, Transfer::Srgb));
+        assert_eq!(s, "P3\n1 1\n255\n255 188 0\n");
+    }
+
+    #[test]
+    fn canvas_pfm_keeps_hdr_values() {
+        let c = Canvas::new_with_color(1, 1, color(7.5, 0.0, 0.0));
+        let mut out = Vec::new();
+        c.write_pfm(&mut out).unwrap();
+        assert_eq!(&out[out.len() - 12..out.len() - 8], &7.5f32.to_le_bytes());
+    }
+
+    #[test]
+    fn canvas_pfm_round_trip() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(2, 0, color(7.5, 0.25, 0.0));
+        c.write_pixel(0, 1, color(0.0, 0.0, 1.0));
+        let mut out = Vec::new();
+        c.write_pfm(&mut out).unwrap();
+        let back = Canvas::read_pfm(&mut &out[..]).unwrap();
+        assert_eq!((back.get_width(), back.get_height()), (3, 2));
+        assert_eq!(back.pixel_at(2, 0), color(7.5, 0.25, 0.0));
+        assert_eq!(back.pixel_at(0, 1), color(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn canvas_png_header() {
+        let c = Canvas::new_with_color(3, 2, color(1.0, 0.5, 0.0));
+        let png = c.to_png(BitDepth::Eight, Encoding::default());
+        assert_eq!(&png[1..4], b"PNG");
+        assert_eq!(&png[16..20], &3u32.to_be_bytes());
+        assert_eq!(&png[20..24], &2u32.to_be_bytes());
+        assert_eq!(png[24], 8);
+        assert_eq!(c.to_png(BitDepth::Sixteen, Encoding::default())[24], 16);
+    }
+
+    #[test]
+    fn channel_value_clamps() {
+        assert_eq!(to_channel_value(1.5, 65535.0), 65535);
+        assert_eq!(to_channel_value(0.5, 65535.0), 32768);
+        assert_eq!(to_channel_value(-0.5, 255.0), 0);
+    }
+
     #[test]
     fn canvas_ppm_max_width() {
         let s = Canvas::new_with_color(10, 2, color(1.0, 0.8, 0.6)).to_ppm();
@@ -0,0 +1,228 @@
+// Removes noise from renders with few samples, using the other layers of
+// `RenderLayers` to find the edges that should stay sharp. This is the
+// edge-avoiding à-trous wavelet filter (Dammertz et al. 2010):
+//
+//   - the image is blurred several times with a 5x5 kernel whose taps are
+//     spread twice as far apart at each pass, so five passes cover a 125
+//     pixel wide area for the price of 125 taps per pixel
+//   - each tap is weighted down where the normal, depth or albedo differ
+//     from the center pixel, so the blur stops at the edges of objects and
+//     creases, and where the colors differ, by less at every pass
+//   - the beauty is divided by the albedo first and multiplied back after,
+//     so that texture and color boundaries aren't blurred, only the lighting
+//
+// Pixels the camera saw nothing through keep their color and aren't blurred
+// into their neighbours.
+use crate::prelude::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Denoiser {
+    // Passes of the filter. More passes blur further, until the holes in
+    // the kernel are wider than the image and further passes change nothing.
+    pub iterations: usize,
+    // How different the lighting of two pixels can be before the blur stops
+    // between them. Halved at each pass.
+    pub sigma_color: f64,
+    // Length of the difference between two normals
+    pub sigma_normal: f64,
+    // Difference in depth relative to the center's depth
+    pub sigma_depth: f64,
+    // Length of the difference between two albedos
+    pub sigma_albedo: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self { iterations: 5, sigma_color: 1.0, sigma_normal: 0.3, sigma_depth: 0.05, sigma_albedo: 0.1 }
+    }
+}
+
+// B3 spline
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Albedo below this isn't divided out; it would only amplify noise
+const MIN_ALBEDO: f64 = 0.01;
+
+impl Denoiser {
+    pub fn denoise(self, layers: &RenderLayers) -> Canvas {
+        let (w, h) = (layers.beauty.get_width(), layers.beauty.get_height());
+        let read = |canvas: &Canvas| -> Vec<Color> {
+            (0..h).flat_map(|y| (0..w).map(move |x| (x, y))).map(|(x, y)| canvas.pixel_at(x, y)).collect()
+        };
+        let beauty = read(&layers.beauty);
+        let albedo: Vec<Color> = read(&layers.albedo).into_iter().map(demodulator).collect();
+        let normal = read(&layers.normal);
+        let depth: Vec<f64> = read(&layers.depth).into_iter().map(|c| c.red).collect();
+        let (w, h) = (w as usize, h as usize);
+
+        let mut image: Vec<Color> = beauty.iter().zip(albedo.iter())
+            .map(|(&c, &a)| color(c.red / a.red, c.green / a.green, c.blue / a.blue))
+            .collect();
+        let mut next = image.clone();
+
+        // Past this pass every tap but the centre is outside the image
+        let passes = self.iterations.min(w.max(h).max(1).ilog2() as usize + 1);
+        for i in 0..passes {
+            let step = 1_isize << i;
+            let sigma_color = self.sigma_color * 0.5f64.powi(i as i32);
+            for y in 0..h {
+                for x in 0..w {
+                    let p = y * w + x;
+                    if depth[p] <= 0.0 {
+                        next[p] = image[p];
+                        continue;
+                    }
+                    let mut sum = Color::BLACK;
+                    let mut total = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as isize + (j as isize - 2) * step;
+                        if qy < 0 || qy >= h as isize { continue; }
+                        for (k, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (k as isize - 2) * step;
+                            if qx < 0 || qx >= w as isize { continue; }
+                            let q = qy as usize * w + qx as usize;
+                            if depth[q] <= 0.0 { continue; }
+
+                            let weight = kx * ky
+                                * gaussian(distance(image[p], image[q]), sigma_color)
+                                * gaussian(distance(normal[p], normal[q]), self.sigma_normal)
+                                * gaussian((depth[p] - depth[q]).abs() / depth[p], self.sigma_depth)
+                                * gaussian(distance(albedo[p], albedo[q]), self.sigma_albedo);
+                            sum = sum + image[q] * weight;
+                            total += weight;
+                        }
+                    }
+                    // The center pixel always has a positive weight
+                    next[p] = sum / total;
+                }
+            }
+            std::mem::swap(&mut image, &mut next);
+        }
+
+        let mut canvas = Canvas::new(w, h);
+        for (p, (c, a)) in image.into_iter().zip(albedo).enumerate() {
+            canvas.write_pixel((p % w) as i32, (p / w) as i32, c * a);
+        }
+        return canvas;
+    }
+}
+
+// What the beauty is divided by: the albedo, except where a channel is too
+// dark to divide by
+fn demodulator(albedo: Color) -> Color {
+    let channel = |a: f64| if a < MIN_ALBEDO { 1.0 } else { a };
+    return color(channel(albedo.red), channel(albedo.green), channel(albedo.blue));
+}
+
+fn distance(a: Color, b: Color) -> f64 {
+    let d = a - b;
+    return (d.red * d.red + d.green * d.green + d.blue * d.blue).sqrt();
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    return (-x * x / (sigma * sigma)).exp();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A flat wall facing the camera, its right half a different object
+    // turned away
+    fn wall(noise: f64) -> RenderLayers {
+        let mut layers = RenderLayers::new(32, 32);
+        let mut rng = Rng::new(4);
+        for y in 0..32 {
+            for x in 0..32 {
+                let left = x < 16;
+                let lit = if left { 0.5 } else { 0.2 };
+                let c = lit + noise * (rng.next_f64() - 0.5);
+                layers.write_pixel(x, y, PixelLayers {
+                    beauty: color(c, c, c),
+                    depth: Some(5.0),
+                    normal: if left { vector(0.0, 0.0, -1.0) } else { vector(0.6, 0.0, -0.8) },
+                    albedo: Color::WHITE,
+                    object: Some(if left { 0 } else { 1 }),
+                    shadow: 0.0,
+                });
+            }
+        }
+        return layers;
+    }
+
+    fn mean_and_spread(canvas: &Canvas, xs: std::ops::Range<i32>) -> (f64, f64) {
+        let values: Vec<f64> = (0..32).flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| canvas.pixel_at(x, y).red)
+            .collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let spread = values.iter().map(|v| (v - mean).abs()).fold(0.0, f64::max);
+        return (mean, spread);
+    }
+
+    #[test]
+    fn noise_is_removed_but_edges_stay() {
+        let layers = wall(0.4);
+        let denoised = Denoiser::default().denoise(&layers);
+
+        let (before_mean, before) = mean_and_spread(&layers.beauty, 0..16);
+        let (after_mean, after) = mean_and_spread(&denoised, 0..16);
+        assert!(after < before / 4.0, "{} -> {}", before, after);
+        assert!((after_mean - before_mean).abs() < 0.02);
+
+        // The normals keep the two halves apart
+        assert!((mean_and_spread(&denoised, 15..16).0 - 0.5).abs() < 0.05);
+        assert!((mean_and_spread(&denoised, 16..17).0 - 0.2).abs() < 0.05);
+    }
+
+    #[test]
+    fn clean_images_and_background_are_unchanged() {
+        let mut layers = wall(0.0);
+        layers.write_pixel(3, 3, PixelLayers {
+            beauty: color(0.0, 0.0, 1.0), depth: None, normal: vector(0.0, 0.0, 0.0),
+            albedo: Color::BLACK, object: None, shadow: 0.0,
+        });
+        let denoised = Denoiser::default().denoise(&layers);
+        assert_eq!(denoised.pixel_at(3, 3), color(0.0, 0.0, 1.0));
+        // Edges stop the blur almost completely
+        for y in 0..32 {
+            for x in 0..32 {
+                assert!(distance(denoised.pixel_at(x, y), layers.beauty.pixel_at(x, y)) < 1e-3);
+            }
+        }
+    }
+
+    // Texture is divided out and put back, so it stays sharp
+    #[test]
+    fn passes_stop_at_the_image_size() {
+        let layers = wall(0.4);
+        let many = Denoiser { iterations: 200, ..Denoiser::default() }.denoise(&layers);
+        let enough = Denoiser { iterations: 6, ..Denoiser::default() }.denoise(&layers);
+        for y in 0..32 {
+            for x in 0..32 {
+                assert_eq!(many.pixel_at(x, y), enough.pixel_at(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn albedo_detail_is_kept() {
+        let mut layers = wall(0.0);
+        for y in 0..32 {
+            let stripes = if y % 2 == 0 { color(1.0, 0.2, 0.2) } else { color(0.2, 0.2, 1.0) };
+            for x in 0..16 {
+                let mut p = PixelLayers {
+                    beauty: stripes * 0.5, depth: Some(5.0), normal: vector(0.0, 0.0, -1.0),
+                    albedo: stripes, object: Some(0), shadow: 0.0,
+                };
+                if x == 8 && y == 8 { p.beauty = p.beauty * 1.5; }
+                layers.write_pixel(x, y, p);
+            }
+        }
+        let denoised = Denoiser::default().denoise(&layers);
+        let near = |a: Color, b: Color| distance(a, b) < 0.01;
+        assert!(near(denoised.pixel_at(4, 4), color(0.5, 0.1, 0.1)));
+        assert!(near(denoised.pixel_at(4, 5), color(0.1, 0.1, 0.5)));
+        // The bright speck is spread out
+        assert!(denoised.pixel_at(8, 8).red < 0.6);
+    }
+}
@@ -0,0 +1,365 @@
+// Points, vectors and normals as separate types. `T4` tells them apart by
+// `w` at runtime, so adding two points or moving a normal with the forward
+// transform compiles and quietly gives nonsense. Here only the operations
+// that mean something exist:
+//
+//   point - point = vector        point ± vector = point
+//   vector ± vector = vector      vector * k, -vector, dot, cross
+//   normal * k = vector           -normal, dot with a vector
+//
+// Matrices move points (with translation) and vectors (without). Normals
+// are only ever taken to another space by an inverse transpose, see
+// `Normal3::transform_by` and `Transform * Normal3`. All three convert to
+// and from `T4` so they can be used next to existing code.
+use crate::prelude::*;
+use std::convert::TryFrom;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Point3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+// A surface normal. Normals are unit length: the constructor and every
+// transform normalize them, and the fields can only be read. There is no
+// zero normal, so there is no default either.
+#[derive(Copy, Clone, Debug)]
+pub struct Normal3 {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+macro_rules! approx_eq {
+    ( $t:ty ) => {
+        impl PartialEq for $t {
+            fn eq(&self, other: &Self) -> bool {
+                float_eq!(self.x, other.x) && float_eq!(self.y, other.y) && float_eq!(self.z, other.z)
+            }
+        }
+    };
+}
+
+approx_eq!(Point3);
+approx_eq!(Vector3);
+approx_eq!(Normal3);
+
+impl Point3 {
+    pub const ORIGIN: Self = Point3 { x: 0.0, y: 0.0, z: 0.0 };
+
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl Vector3 {
+    pub const ZERO: Self = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+
+    pub const EX: Self = Vector3 { x: 1.0, y: 0.0, z: 0.0 };
+    pub const EY: Self = Vector3 { x: 0.0, y: 1.0, z: 0.0 };
+    pub const EZ: Self = Vector3 { x: 0.0, y: 0.0, z: 1.0 };
+
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        Self { x, y, z }
+    }
+
+    pub fn mag(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(self) -> Self {
+        self / self.mag()
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Self) -> Self {
+        Self::new(self.y * other.z - self.z * other.y,
+                  self.z * other.x - self.x * other.z,
+                  self.x * other.y - self.y * other.x)
+    }
+
+    pub fn reflect(self, normal: Normal3) -> Self {
+        self - normal * 2.0 * normal.dot(self)
+    }
+}
+
+impl Normal3 {
+    // A zero vector has no direction to normalize to. Use `TryFrom<T4>` for
+    // vectors that may be zero.
+    pub fn new(x: f64, y: f64, z: f64) -> Self {
+        debug_assert!(x != 0.0 || y != 0.0 || z != 0.0, "a normal can't be a zero vector");
+        let Vector3 { x, y, z } = Vector3::new(x, y, z).normalize();
+        return Self { x, y, z };
+    }
+
+    pub fn x(self) -> f64 { self.x }
+    pub fn y(self) -> f64 { self.y }
+    pub fn z(self) -> f64 { self.z }
+
+    pub fn dot(self, v: Vector3) -> f64 {
+        Vector3::from(self).dot(v)
+    }
+
+    // Take the normal to another space. `inverse_transpose` is the inverse
+    // transpose of the matrix that takes points there, e.g.
+    // `Transform::inverse_transpose`. Only the upper 3x3 part is used, so
+    // the translation column can't leak in.
+    pub fn transform_by(self, inverse_transpose: M4) -> Self {
+        let m = inverse_transpose;
+        Self::new(m[(0, 0)] * self.x + m[(0, 1)] * self.y + m[(0, 2)] * self.z,
+                  m[(1, 0)] * self.x + m[(1, 1)] * self.y + m[(1, 2)] * self.z,
+                  m[(2, 0)] * self.x + m[(2, 1)] * self.y + m[(2, 2)] * self.z)
+    }
+}
+
+impl From<Normal3> for Vector3 {
+    fn from(n: Normal3) -> Self { Vector3::new(n.x, n.y, n.z) }
+}
+
+// Point arithmetic
+
+impl Sub for Point3 {
+    type Output = Vector3;
+    fn sub(self, other: Self) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Add<Vector3> for Point3 {
+    type Output = Self;
+    fn add(self, v: Vector3) -> Self {
+        Self::new(self.x + v.x, self.y + v.y, self.z + v.z)
+    }
+}
+
+impl Sub<Vector3> for Point3 {
+    type Output = Self;
+    fn sub(self, v: Vector3) -> Self {
+        Self::new(self.x - v.x, self.y - v.y, self.z - v.z)
+    }
+}
+
+// Vector arithmetic
+
+impl Add for Vector3 {
+    type Output = Self;
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Self;
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vector3 {
+    type Output = Self;
+    fn mul(self, k: f64) -> Self {
+        Self::new(self.x * k, self.y * k, self.z * k)
+    }
+}
+
+impl Mul<Vector3> for f64 {
+    type Output = Vector3;
+    fn mul(self, v: Vector3) -> Vector3 { v * self }
+}
+
+impl Div<f64> for Vector3 {
+    type Output = Self;
+    fn div(self, k: f64) -> Self {
+        Self::new(self.x / k, self.y / k, self.z / k)
+    }
+}
+
+impl Neg for Vector3 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+// Normal arithmetic. Scaling a normal gives an offset, e.g. to move a hit
+// point off the surface, which is a plain vector.
+
+impl Mul<f64> for Normal3 {
+    type Output = Vector3;
+    fn mul(self, k: f64) -> Vector3 { Vector3::from(self) * k }
+}
+
+impl Neg for Normal3 {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self { x: -self.x, y: -self.y, z: -self.z }
+    }
+}
+
+// Matrices
+
+impl Mul<Point3> for M4 {
+    type Output = Point3;
+    fn mul(self, p: Point3) -> Point3 {
+        let t = self * T4::from(p);
+        Point3::new(t.x, t.y, t.z)
+    }
+}
+
+impl Mul<Vector3> for M4 {
+    type Output = Vector3;
+    fn mul(self, v: Vector3) -> Vector3 {
+        let t = self * T4::from(v);
+        Vector3::new(t.x, t.y, t.z)
+    }
+}
+
+impl Mul<Point3> for Transform {
+    type Output = Point3;
+    fn mul(self, p: Point3) -> Point3 { self.matrix() * p }
+}
+
+impl Mul<Vector3> for Transform {
+    type Output = Vector3;
+    fn mul(self, v: Vector3) -> Vector3 { self.matrix() * v }
+}
+
+impl Mul<Normal3> for Transform {
+    type Output = Normal3;
+    fn mul(self, n: Normal3) -> Normal3 { n.transform_by(self.inverse_transpose()) }
+}
+
+// Conversions to and from T4. Going back from a T4 checks `w`; normals
+// are any non-zero vector and get normalized.
+
+impl From<Point3> for T4 {
+    fn from(p: Point3) -> Self { point(p.x, p.y, p.z) }
+}
+
+impl From<Vector3> for T4 {
+    fn from(v: Vector3) -> Self { vector(v.x, v.y, v.z) }
+}
+
+impl From<Normal3> for T4 {
+    fn from(n: Normal3) -> Self { vector(n.x, n.y, n.z) }
+}
+
+impl TryFrom<T4> for Point3 {
+    type Error = T4;
+    fn try_from(t: T4) -> Result<Self, T4> {
+        match t.is_point() {
+            true => Ok(Point3::new(t.x, t.y, t.z)),
+            false => Err(t),
+        }
+    }
+}
+
+impl TryFrom<T4> for Vector3 {
+    type Error = T4;
+    fn try_from(t: T4) -> Result<Self, T4> {
+        match t.is_vector() {
+            true => Ok(Vector3::new(t.x, t.y, t.z)),
+            false => Err(t),
+        }
+    }
+}
+
+impl TryFrom<T4> for Normal3 {
+    type Error = T4;
+    fn try_from(t: T4) -> Result<Self, T4> {
+        match t.is_vector() && t.mag() > 0.0 {
+            true => Ok(Normal3::new(t.x, t.y, t.z)),
+            false => Err(t),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+    #[test]
+    fn point_and_vector_arithmetic() {
+        let p = Point3::new(3.0, 2.0, 1.0);
+        let q = Point3::new(5.0, 6.0, 7.0);
+        assert_eq!(p - q, Vector3::new(-2.0, -4.0, -6.0));
+        assert_eq!(p + (q - p), q);
+        assert_eq!(p - Vector3::new(5.0, 6.0, 7.0), Point3::new(-2.0, -4.0, -6.0));
+        assert_eq!(-2.0 * Vector3::new(1.0, -2.0, 3.0), Vector3::new(-2.0, 4.0, -6.0));
+    }
+
+    #[test]
+    fn vector_products() {
+        let a = Vector3::new(1.0, 2.0, 3.0);
+        let b = Vector3::new(2.0, 3.0, 4.0);
+        assert_eq!(a.dot(b), 20.0);
+        assert_eq!(a.cross(b), Vector3::new(-1.0, 2.0, -1.0));
+        assert_eq!(b.cross(a), Vector3::new(1.0, -2.0, 1.0));
+        assert!(float_eq!(a.normalize().mag(), 1.0));
+    }
+
+    #[test]
+    fn normals_are_unit_length() {
+        let n = Normal3::new(0.0, 3.0, 4.0);
+        assert_eq!(n, Normal3::new(0.0, 0.6, 0.8));
+        assert_eq!(n * 5.0, Vector3::new(0.0, 3.0, 4.0));
+        assert_eq!((n.x(), n.y(), n.z()), (0.0, 0.6, 0.8));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "zero vector")]
+    fn zero_normals_are_rejected() {
+        Normal3::new(0.0, 0.0, 0.0);
+    }
+
+    #[test]
+    fn reflecting_off_slanted_surface() {
+        let v = Vector3::new(0.0, -1.0, 0.0);
+        let n = Normal3::new(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0);
+        assert_eq!(v.reflect(n), Vector3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn matrices_translate_points_but_not_vectors() {
+        let m = translation(5.0, -3.0, 2.0);
+        assert_eq!(m * Point3::new(-3.0, 4.0, 5.0), Point3::new(2.0, 1.0, 7.0));
+        assert_eq!(m * Vector3::new(-3.0, 4.0, 5.0), Vector3::new(-3.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn normals_use_the_inverse_transpose() {
+        let t = Transform::new(scaling(1.0, 0.5, 1.0) * rotation_z(PI / 5.0));
+        // The normal of a transformed sphere, as in normal_at
+        let local = t.inverse() * Point3::new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+        let n = Normal3::new(local.x, local.y, local.z);
+        assert_eq!(t * n, Normal3::new(0.0, 0.97014, -0.24254));
+
+        // A translation doesn't move a normal
+        assert_eq!(Transform::new(translation(0.0, 1.0, 0.0)) * n, n);
+    }
+
+    #[test]
+    fn converting_to_and_from_t4() {
+        assert_eq!(T4::from(Point3::new(1.0, 2.0, 3.0)), point(1.0, 2.0, 3.0));
+        assert_eq!(T4::from(Vector3::new(1.0, 2.0, 3.0)), vector(1.0, 2.0, 3.0));
+        assert_eq!(Point3::try_from(point(1.0, 2.0, 3.0)), Ok(Point3::new(1.0, 2.0, 3.0)));
+        assert_eq!(Point3::try_from(vector(1.0, 2.0, 3.0)), Err(vector(1.0, 2.0, 3.0)));
+        assert_eq!(Vector3::try_from(point(1.0, 2.0, 3.0)), Err(point(1.0, 2.0, 3.0)));
+        assert_eq!(Normal3::try_from(vector(0.0, 0.0, 2.0)), Ok(Normal3::new(0.0, 0.0, 1.0)));
+        assert!(Normal3::try_from(T4::ZERO).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use crate::prelude::*;
+
+// A collection of shapes sharing a transform. The group's transform is
+// passed down to each child when it is added, so children are always in
+// world space and the group never has to transform rays itself.
+#[derive(Debug)]
+pub struct Group {
+    pub name: Option<String>,
+    local: M4,
+    parent: M4,
+    // `parent * local`, what the children are placed in
+    transform: Transform,
+    material: Material,
+    children: Vec<Box<dyn Shape>>,
+    bounds: Bounds,
+    // Whether any child gives off light, so that looking for lights can skip
+    // the group
+    emissive: bool,
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            name: None,
+            local: M4::IDENTITY,
+            parent: M4::IDENTITY,
+            transform: Transform::IDENTITY,
+            material: Material::default(),
+            children: vec![],
+            bounds: Bounds::EMPTY,
+            emissive: false,
+        }
+    }
+}
+
+impl Group {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn named(name: &str) -> Self {
+        Self { name: Some(name.to_string()), ..Self::new() }
+    }
+
+    pub fn add_child(&mut self, mut child: Box<dyn Shape>) -> &mut Self {
+        child.set_parent_transform(self.transform.matrix());
+        self.bounds = self.bounds.union(child.bounds());
+        self.emissive |= child.is_emissive();
+        self.children.push(child);
+        return self;
+    }
+
+    pub fn len(&self) -> usize { self.children.len() }
+
+    pub fn is_empty(&self) -> bool { self.children.is_empty() }
+
+    // Recomposes the children from their own transforms, so nothing is
+    // inverted and no round-off is carried over from earlier transforms
+    fn update_transform(&mut self) {
+        self.transform = Transform::new(self.parent * self.local);
+        let transform = self.transform.matrix();
+        for child in self.children.iter_mut() {
+            child.set_parent_transform(transform);
+        }
+        self.update_bounds();
+    }
+
+    fn update_bounds(&mut self) {
+        self.bounds = self.children.iter()
+            .fold(Bounds::EMPTY, |b, c| b.union(c.bounds()));
+    }
+}
+
+impl Shape for Group {
+    // Children are already in world space, so the ray goes to them untouched
+    fn intersect<'a>(&'a self, ray: Ray, xs: &mut Intersections<'a>) {
+        self.local_intersect(ray, xs);
+    }
+
+    fn local_intersect<'a>(&'a self, ray: Ray, xs: &mut Intersections<'a>) {
+        if !self.bounds.intersects(ray) { return; }
+        for child in self.children.iter() {
+            child.intersect(ray, xs);
+        }
+    }
+
+    fn local_normal_at(&self, _local_point: T4) -> T4 {
+        panic!("Groups have no surface of their own, normals come from their children");
+    }
+
+    fn material(&self) -> Material { self.material }
+    fn set_material(&mut self, material: Material) -> &mut Self {
+        self.material = material;
+        return self;
+    }
+
+    fn transform(&self) -> M4 { self.transform.matrix() }
+    fn set_transform(&mut self, transform: M4) -> &mut Self {
+        self.local = transform;
+        self.update_transform();
+        return self;
+    }
+    fn set_parent_transform(&mut self, parent: M4) {
+        self.parent = parent;
+        self.update_transform();
+    }
+
+    fn inverse_transform_at(&self, _time: f64) -> M4 { self.transform.inverse() }
+    fn normal_transform_at(&self, _time: f64) -> M4 { self.transform.inverse_transpose() }
+
+    fn bounds(&self) -> Bounds {
+        self.bounds
+    }
+
+    fn children(&self) -> &[Box<dyn Shape>] {
+        &self.children
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.emissive
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn creating_new_group() {
+        let g = Group::new();
+        assert_eq!(g.transform(), M4::IDENTITY);
+        assert!(g.is_empty());
+    }
+
+    #[test]
+    fn intersecting_empty_group() {
+        let g = Group::new();
+        let mut xs = Intersections::empty();
+        g.intersect(Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0)), &mut xs);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn intersecting_nonempty_group() {
+        let mut g = Group::new();
+        g.add_child(Box::new(Sphere::default()));
+        g.add_child(Box::new(Sphere::from_transform(translation(0.0, 0.0, -3.0))));
+        g.add_child(Box::new(Sphere::from_transform(translation(5.0, 0.0, 0.0))));
+        let mut xs = Intersections::empty();
+        g.intersect(Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)), &mut xs);
+
+        let s1: &dyn Shape = g.children()[0].as_ref();
+        let s2: &dyn Shape = g.children()[1].as_ref();
+        assert_eq!(xs.len(), 4);
+        assert_eq!(xs[0].object, s2);
+        assert_eq!(xs[1].object, s2);
+        assert_eq!(xs[2].object, s1);
+        assert_eq!(xs[3].object, s1);
+    }
+
+    #[test]
+    fn intersecting_transformed_group() {
+        let mut g = Group::new();
+        g.set_transform(scaling(2.0, 2.0, 2.0));
+        g.add_child(Box::new(Sphere::from_transform(translation(5.0, 0.0, 0.0))));
+        let mut xs = Intersections::empty();
+        g.intersect(Ray::new(point(10.0, 0.0, -10.0), vector(0.0, 0.0, 1.0)), &mut xs);
+        assert_eq!(xs.len(), 2);
+    }
+
+    #[test]
+    fn transforming_group_after_adding_children() {
+        let mut g = Group::new();
+        g.add_child(Box::new(Sphere::from_transform(translation(5.0, 0.0, 0.0))));
+        g.set_transform(scaling(2.0, 2.0, 2.0));
+        assert_eq!(g.children()[0].transform(), scaling(2.0, 2.0, 2.0) * translation(5.0, 0.0, 0.0));
+        g.set_transform(M4::IDENTITY);
+        assert_eq!(g.children()[0].transform(), translation(5.0, 0.0, 0.0));
+    }
+
+    // Children are recomposed from their own transforms, so no round-off
+    // builds up however often the group is moved
+    #[test]
+    fn resetting_group_transform_is_exact() {
+        let mut inner = Group::new();
+        inner.set_transform(scaling(0.5, 0.5, 0.5));
+        inner.add_child(Box::new(Sphere::from_transform(translation(5.0, 0.0, 0.0))));
+        let mut g = Group::new();
+        g.add_child(Box::new(inner));
+        for i in 0..100 {
+            g.set_transform(rotation_y(0.1 * i as f64) * scaling(3.0, 3.0, 3.0));
+        }
+        let last = rotation_y(9.9) * scaling(3.0, 3.0, 3.0);
+        g.set_transform(last);
+
+        let child = g.children()[0].children()[0].transform();
+        let expected = last * (scaling(0.5, 0.5, 0.5) * translation(5.0, 0.0, 0.0));
+        for row in 0..4 {
+            for col in 0..4 {
+                assert_eq!(child[(row, col)].to_bits(), expected[(row, col)].to_bits());
+            }
+        }
+    }
+
+    #[test]
+    fn normal_on_child_object() {
+        let mut g2 = Group::new();
+        g2.set_transform(scaling(1.0, 2.0, 3.0));
+        g2.add_child(Box::new(Sphere::from_transform(translation(5.0, 0.0, 0.0))));
+        let mut g1 = Group::new();
+        g1.set_transform(rotation_y(FRAC_PI_2));
+        g1.add_child(Box::new(g2));
+
+        let mut xs = Intersections::empty();
+        let ray = Ray::new(point(1.7321, 1.1547, -10.0), vector(0.0, 0.0, 1.0));
+        g1.intersect(ray, &mut xs);
+        let n = xs[0].object.normal_at(point(1.7321, 1.1547, -5.5774));
+        let expected = vector(0.2857, 0.4286, -0.8571);
+        assert!((n - expected).mag() < 0.0001);
+    }
+
+    #[test]
+    fn group_bounds_skip_children() {
+        let mut g = Group::new();
+        g.add_child(Box::new(Sphere::from_transform(translation(5.0, 0.0, 0.0))));
+        assert!(!g.bounds().intersects(Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0))));
+        assert!(g.bounds().intersects(Ray::new(point(5.0, 0.0, -5.0), vector(0.0, 0.0, 1.0))));
+    }
+
+    #[test]
+    fn groups_know_about_emissive_children() {
+        let glowing = Material { emission: color(1.0, 1.0, 1.0), ..Material::default() };
+        let mut inner = Group::new();
+        inner.add_child(Box::new(Sphere::from_material(glowing)));
+        let mut g = Group::new();
+        g.add_child(Box::new(Sphere::default()));
+        assert!(!g.is_emissive());
+        g.add_child(Box::new(inner));
+        assert!(g.is_emissive());
+        assert!(!g.children()[0].is_emissive());
+    }
+}
@@ -0,0 +1,258 @@
+// High dynamic range image formats. Both store the unclamped floating point
+// colours so that exposure and tone mapping can be decided later.
+use crate::prelude::*;
+use std::io::{self, BufRead, Write};
+
+// Radiance RGBE (.hdr), written with the run length encoded scanlines that
+// all modern readers expect.
+// See https://www.graphics.cornell.edu/~bjw/rgbe.html
+pub fn write_radiance<W: Write>(out: &mut W, width: usize, height: usize, pixels: &[Color]) -> std::io::Result<()> {
+    write!(out, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width)?;
+
+    let mut channels: [Vec<u8>; 4] = Default::default();
+    let mut encoded = Vec::new();
+    for row in pixels.chunks(width.max(1)) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(|&c| to_rgbe(c)).collect();
+
+        // Run length encoding is only defined for these widths
+        if !(8..=0x7FFF).contains(&width) {
+            for p in rgbe.iter() {
+                out.write_all(p)?;
+            }
+            continue;
+        }
+
+        for (i, channel) in channels.iter_mut().enumerate() {
+            channel.clear();
+            channel.extend(rgbe.iter().map(|p| p[i]));
+        }
+
+        encoded.clear();
+        encoded.extend_from_slice(&[2, 2, (width >> 8) as u8, width as u8]);
+        for channel in channels.iter() {
+            rle_encode(channel, &mut encoded);
+        }
+        out.write_all(&encoded)?;
+    }
+    return Ok(());
+}
+
+pub fn to_rgbe(c: Color) -> [u8; 4] {
+    let max = c.red.max(c.green).max(c.blue);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1). The exponent byte
+    // is biased by 128, so anything brighter than 2^127 saturates to white
+    let exponent = (max.log2().floor() + 1.0).min(127.0) as i32;
+    let scale = 256.0 / 2f64.powi(exponent);
+    let channel = |x: f64| (x.max(0.0) * scale).min(255.0) as u8;
+    [channel(c.red), channel(c.green), channel(c.blue), (exponent + 128) as u8]
+}
+
+// A run is stored as (128 + count, value), a literal dump as (count, values..)
+fn rle_encode(data: &[u8], out: &mut Vec<u8>) {
+    const MIN_RUN: usize = 4;
+
+    let mut i = 0;
+    while i < data.len() {
+        // Find the next run long enough to be worth encoding
+        let mut run_start = i;
+        let mut run_len = 0;
+        while run_start < data.len() {
+            run_len = 1;
+            while run_start + run_len < data.len()
+                && run_len < 127
+                && data[run_start + run_len] == data[run_start] {
+                run_len += 1;
+            }
+            if run_len >= MIN_RUN { break; }
+            run_start += run_len;
+        }
+
+        // Everything before the run is written as literal dumps
+        while i < run_start.min(data.len()) {
+            let count = (run_start - i).min(128);
+            out.push(count as u8);
+            out.extend_from_slice(&data[i..i + count]);
+            i += count;
+        }
+
+        if run_start < data.len() && run_len >= MIN_RUN {
+            out.push(128 + run_len as u8);
+            out.push(data[run_start]);
+            i = run_start + run_len;
+        }
+    }
+}
+
+// Portable float map (.pfm). Three little endian f32 per pixel, with rows
+// stored from the bottom of the image to the top.
+pub fn write_pfm<W: Write>(out: &mut W, width: usize, height: usize, pixels: &[Color]) -> std::io::Result<()> {
+    // A negative scale marks the data as little endian
+    write!(out, "PF\n{} {}\n-1.0\n", width, height)?;
+
+    let mut row_bytes = Vec::with_capacity(width * 12);
+    for row in pixels.chunks(width.max(1)).rev() {
+        row_bytes.clear();
+        for c in row {
+            for x in [c.red, c.green, c.blue] {
+                row_bytes.extend_from_slice(&(x as f32).to_le_bytes());
+            }
+        }
+        out.write_all(&row_bytes)?;
+    }
+    return Ok(());
+}
+
+// Read a colour (PF) or greyscale (Pf) float map in either byte order,
+// returning the pixels top row first.
+pub fn read_pfm<R: BufRead>(input: &mut R) -> io::Result<(usize, usize, Vec<Color>)> {
+    let bad = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("pfm: {}", message));
+
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(bad("truncated header"));
+        }
+        header.extend(line.split_whitespace().map(str::to_string));
+    }
+    if header.len() != 4 {
+        return Err(bad("malformed header"));
+    }
+
+    let channels = match header[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(bad("not a float map")),
+    };
+    let width: usize = header[1].parse().map_err(|_| bad("bad width"))?;
+    let height: usize = header[2].parse().map_err(|_| bad("bad height"))?;
+    let scale: f64 = header[3].parse().map_err(|_| bad("bad scale"))?;
+    let little_endian = scale < 0.0;
+
+    let mut data = vec![0u8; width * height * channels * 4];
+    input.read_exact(&mut data)?;
+    let samples: Vec<f64> = data.chunks_exact(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            let x = if little_endian { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) };
+            x as f64
+        })
+        .collect();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in samples.chunks_exact((width * channels).max(1)).rev().take(height) {
+        pixels.extend(row.chunks_exact(channels).map(|s| match channels {
+            3 => color(s[0], s[1], s[2]),
+            _ => color(s[0], s[0], s[0]),
+        }));
+    }
+    return Ok((width, height, pixels));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn from_rgbe(p: [u8; 4]) -> Color {
+        if p[3] == 0 { return Color::BLACK; }
+        let f = 2f64.powi(p[3] as i32 - 128 - 8);
+        color((p[0] as f64 + 0.5) * f, (p[1] as f64 + 0.5) * f, (p[2] as f64 + 0.5) * f)
+    }
+
+    fn rle_decode(data: &[u8], len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while out.len() < len {
+            if data[i] > 128 {
+                out.extend(std::iter::repeat_n(data[i + 1], data[i] as usize - 128));
+                i += 2;
+            } else {
+                let n = data[i] as usize;
+                out.extend_from_slice(&data[i + 1..i + 1 + n]);
+                i += 1 + n;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn rgbe_black() {
+        assert_eq!(to_rgbe(Color::BLACK), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rgbe_keeps_values_above_one() {
+        let c = color(12.5, 1.0, 0.25);
+        let back = from_rgbe(to_rgbe(c));
+        assert!((back.red - 12.5).abs() < 0.1);
+        assert!((back.green - 1.0).abs() < 0.05);
+        assert!((back.blue - 0.25).abs() < 0.05);
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let mut data = vec![1, 2, 3];
+        data.extend(std::iter::repeat_n(9, 300));
+        data.extend(&[4, 4, 5, 5, 5, 5, 5, 6]);
+        let mut encoded = Vec::new();
+        rle_encode(&data, &mut encoded);
+        assert!(encoded.len() < data.len());
+        assert_eq!(rle_decode(&encoded, data.len()), data);
+    }
+
+    #[test]
+    fn rgbe_saturates_huge_values() {
+        assert_eq!(to_rgbe(color(1e300, 0.0, 0.0)), [255, 0, 0, 255]);
+        assert_eq!(to_rgbe(color(f64::INFINITY, 1.0, 1.0)), [255, 0, 0, 255]);
+        let p = to_rgbe(color(1e38, 1e38, 1e38));
+        assert_eq!(p[3], 255);
+        assert!(p[0] > 0);
+    }
+
+    #[test]
+    fn radiance_header_and_scanline() {
+        let pixels = vec![Color::WHITE; 8];
+        let mut out = Vec::new();
+        write_radiance(&mut out, 8, 1, &pixels).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n";
+        assert_eq!(&out[..header.len()], &header[..]);
+        assert_eq!(&out[header.len()..header.len() + 4], &[2, 2, 0, 8]);
+    }
+
+    #[test]
+    fn pfm_bottom_to_top() {
+        let pixels = vec![color(1.0, 2.0, 3.0), color(4.0, 5.0, 6.0)];
+        let mut out = Vec::new();
+        write_pfm(&mut out, 1, 2, &pixels).unwrap();
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], &header[..]);
+        let data = &out[header.len()..];
+        assert_eq!(data.len(), 24);
+        assert_eq!(&data[..4], &4f32.to_le_bytes());
+        assert_eq!(&data[12..16], &1f32.to_le_bytes());
+    }
+
+    #[test]
+    fn pfm_round_trip() {
+        let pixels = vec![color(1.0, 2.0, 3.0), color(-4.0, 0.5, 6.0), color(0.0, 0.0, 1e6)];
+        let mut out = Vec::new();
+        write_pfm(&mut out, 3, 1, &pixels).unwrap();
+        let (width, height, back) = read_pfm(&mut &out[..]).unwrap();
+        assert_eq!((width, height), (3, 1));
+        assert_eq!(back, pixels);
+    }
+
+    #[test]
+    fn pfm_big_endian_greyscale() {
+        let mut data = b"Pf\n1 2\n1.0\n".to_vec();
+        data.extend_from_slice(&0.25f32.to_be_bytes());
+        data.extend_from_slice(&0.75f32.to_be_bytes());
+        let (_, _, pixels) = read_pfm(&mut &data[..]).unwrap();
+        assert_eq!(pixels, vec![color(0.75, 0.75, 0.75), color(0.25, 0.25, 0.25)]);
+        assert!(read_pfm(&mut &b"P6\n1 1\n255\n"[..]).is_err());
+    }
+}
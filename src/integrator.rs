@@ -0,0 +1,182 @@
+// How the color seen along a camera ray is worked out. Every render picks
+// one through `Camera::integrator`.
+//
+// Whitted is the renderer from the book: Phong shading lit directly by the
+// first light, with a constant ambient term standing in for light bounced
+// off everything else.
+//
+// Ambient occlusion renders the world's `AmbientOcclusion`, or the default
+// one if it has none, as a grayscale image.
+//
+// The path tracer follows each ray as it bounces around the scene, so light
+// reflected off other surfaces is included: color bleeds from a red wall
+// onto a white floor and shadows are filled in by indirect light. Surfaces
+// reflect light as their material's `reflectance` says; Phong materials are
+// ideal diffuse reflectors with albedo `color * diffuse`, their ambient,
+// specular and shininess are ignored. At every bounce the path
+//
+//   - adds direct light from each of the world's lights and from a random
+//     point on an emissive shape, with shadow rays (next event estimation),
+//     instead of waiting to hit a light by chance
+//   - continues in a random direction picked by the material, mostly where
+//     it reflects the most light
+//   - after a few bounces ends at random, with a probability that follows
+//     how much light the path can still carry, and otherwise is weighted up
+//     by the same amount so the average stays right (Russian roulette)
+//
+// Because emissive shapes are already sampled as lights, their emission is
+// only added where the camera sees them directly; counting it again when a
+// bounce happens to hit one would make them twice as bright.
+//
+// Point lights don't fall off with distance in this renderer. Their direct
+// contribution to a diffuse surface is `intensity * albedo * cos`, the same
+// as the diffuse term of Phong shading, so both integrators give images of
+// similar brightness.
+use crate::prelude::*;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Integrator {
+    #[default]
+    Whitted,
+    // `max_depth` is a hard limit on bounces. Russian roulette ends nearly
+    // every path well before it, except in scenes that reflect almost all
+    // light.
+    PathTracer { max_depth: usize },
+    AmbientOcclusion,
+}
+
+// Paths always get this many bounces before Russian roulette may end them
+const ROULETTE_DEPTH: usize = 3;
+
+impl Integrator {
+    pub const PATH_TRACER: Self = Integrator::PathTracer { max_depth: 16 };
+
+    pub fn color_at<'a>(self, world: &'a World, ray: Ray, rng: &mut Rng,
+                        xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> Color {
+        match self {
+            Integrator::Whitted => world.color_at(ray, xs1, xs2),
+            Integrator::PathTracer { max_depth } => path_trace(world, ray, max_depth, rng, xs1, xs2),
+            Integrator::AmbientOcclusion =>
+                world.ambient_occlusion.unwrap_or_default().color_at(world, ray, rng, xs1, xs2),
+        }
+    }
+}
+
+// One random path starting with `ray`. Its expected value is the light
+// arriving along the ray; average many paths to get rid of the noise.
+pub fn path_trace<'a>(world: &'a World, mut ray: Ray, max_depth: usize, rng: &mut Rng,
+                      xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> Color {
+    let mut radiance = Color::BLACK;
+    // How much of the light arriving at the current bounce reaches the camera
+    let mut throughput = Color::WHITE;
+
+    for depth in 0..max_depth {
+        xs1.clear();
+        world.intersect(ray, xs1);
+        let comps = match xs1.hit() {
+            None => break,
+            Some(hit) => hit.prepare_computations(ray),
+        };
+        let material = comps.object.material();
+
+        if depth == 0 {
+            radiance = radiance + material.emission;
+        }
+
+        for light in world.lights.iter() {
+            let lightv = (light.pos - comps.over_point).normalize();
+            if lightv * comps.normalv <= 0.0 { continue; }
+            xs2.clear();
+            if !world.is_occluded(comps.over_point, light.pos, comps.time, xs2) {
+                radiance = radiance + throughput * light.intensity
+                    * material.reflectance(lightv, comps.eyev, comps.normalv);
+            }
+        }
+        if let Some(light) = world.sample_area_light(comps.over_point, comps.time, rng, xs2) {
+            let lightv = (light.pos - comps.over_point).normalize();
+            radiance = radiance + throughput * light.intensity
+                * material.reflectance(lightv, comps.eyev, comps.normalv);
+        }
+
+        let (direction, weight) = match material.sample_reflection(comps.eyev, comps.normalv, rng) {
+            None => break,
+            Some(sample) => sample,
+        };
+        throughput = throughput * weight;
+        if depth + 1 >= ROULETTE_DEPTH {
+            let survival = throughput.red.max(throughput.green).max(throughput.blue).min(1.0);
+            if rng.next_f64() >= survival { break; }
+            throughput = throughput / survival;
+        }
+
+        ray = Ray::with_time(comps.over_point, direction, comps.time);
+    }
+
+    return radiance;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::brdf::cosine_sample_hemisphere;
+
+    fn trace(world: &World, ray: Ray, rng: &mut Rng) -> Color {
+        path_trace(world, ray, 64, rng, &mut Intersections::empty(), &mut Intersections::empty())
+    }
+
+    #[test]
+    fn whitted_is_world_color_at() {
+        let w = World::simple();
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let c = Integrator::Whitted.color_at(&w, r, &mut Rng::new(1),
+                                             &mut Intersections::empty(), &mut Intersections::empty());
+        assert_eq!(c, color_rgb!(0.38066, 0.47583, 0.2855));
+    }
+
+    // A convex object can't light itself, so the only light is the direct
+    // light, the same as the diffuse part of Phong shading
+    #[test]
+    fn direct_light_matches_phong_diffuse() {
+        let mut w = World::simple();
+        let material = Material { ambient: 0.0, specular: 0.0, ..w.objects[0].material() };
+        w.objects = vec![Box::new(Sphere::from_material(material))];
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let whitted = w.color_at(r, &mut Intersections::empty(), &mut Intersections::empty());
+        assert_eq!(trace(&w, r, &mut Rng::new(3)), whitted);
+
+        let metal = Material {
+            model: ShadingModel::MetallicRoughness, metallic: 1.0, roughness: 0.4, ambient: 0.0, ..material
+        };
+        w.objects = vec![Box::new(Sphere::from_material(metal))];
+        let whitted = w.color_at(r, &mut Intersections::empty(), &mut Intersections::empty());
+        assert_eq!(trace(&w, r, &mut Rng::new(3)), whitted);
+    }
+
+    #[test]
+    fn emissive_surfaces_are_visible() {
+        let mut w = World::default();
+        w.add_object(Sphere::from_material(Material { emission: color(0.5, 0.25, 0.0), ..Material::default() }));
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        assert_eq!(trace(&w, r, &mut Rng::new(3)), color(0.5, 0.25, 0.0));
+    }
+
+    // Inside a glowing sphere that reflects half the light, every bounce adds
+    // half as much again: e + e/2 + e/4 + ... = 2e. Russian roulette must
+    // not change that average.
+    #[test]
+    fn furnace_inside_glowing_sphere() {
+        let mut w = World::default();
+        w.add_object(Sphere::from_material(Material {
+            color: Color::WHITE, diffuse: 0.5, emission: color(0.5, 0.5, 0.5), ..Material::default()
+        }));
+        let mut rng = Rng::new(11);
+        let samples = 4000;
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            let d = cosine_sample_hemisphere(vector(0.0, 0.0, 1.0), &mut rng);
+            total = total + trace(&w, Ray::new(point(0.0, 0.0, 0.0), d), &mut rng);
+        }
+        let mean = total / samples as f64;
+        assert!((mean.red - 1.0).abs() < 0.03, "{:?}", mean);
+    }
+}
@@ -4,12 +4,19 @@ use std::borrow::Borrow;
 #[derive(Clone, Debug, Copy, PartialEq)]
 pub struct Intersection<'a> {
     pub t: f64,
-    pub object: &'a Sphere,
+    pub object: &'a dyn Shape,
+    // Where on the surface the hit is, for shapes that need it (triangles)
+    pub u: f64,
+    pub v: f64,
 }
 
 impl<'a> Intersection<'a> {
-    pub fn new(t: f64, object: &'a Sphere) -> Self {
-        Self { t, object }
+    pub fn new(t: f64, object: &'a dyn Shape) -> Self {
+        Self::with_uv(t, object, 0.0, 0.0)
+    }
+
+    pub fn with_uv(t: f64, object: &'a dyn Shape, u: f64, v: f64) -> Self {
+        Self { t, object, u, v }
     }
 
     pub fn prepare_computations(self, ray: Ray) -> Computations<'a> {
@@ -19,28 +26,32 @@ impl<'a> Intersection<'a> {
         let eyev = -ray.direction;
 
         let mut inside = false;
-        let mut normalv = object.normal_at(point);
-
-        let over_point = point + crate::consts::OVER_POINT_SHIFT_LENGTH * normalv;
+        let time = ray.time;
+        let mut normalv = object.normal_at_uv(point, time, self.u, self.v);
 
         if normalv * eyev < 0.0 {
             inside = true;
             normalv = -normalv;
         }
 
-        Computations { t, object, point, over_point, eyev, normalv, inside }
+        // Shift along the flipped normal so that hits from inside an object
+        // are not shadowed by the object's own surface
+        let over_point = point + crate::consts::OVER_POINT_SHIFT_LENGTH * normalv;
+
+        Computations { t, object, point, over_point, eyev, normalv, inside, time }
     }
 }
 
 #[derive(Clone, Debug, Copy, PartialEq)]
 pub struct Computations<'a> {
     pub t: f64,
-    pub object: &'a Sphere,
+    pub object: &'a dyn Shape,
     pub point: T4,
     pub over_point: T4,
     pub eyev: T4,
     pub normalv: T4,
     pub inside: bool,
+    pub time: f64,
 }
 
 #[derive(Clone, Debug)]
@@ -80,6 +91,8 @@ impl<'a> Intersections<'a> {
 
     pub fn len(&self) -> usize { self.0.len() }
 
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
     pub fn hit(&self) -> Option<Intersection<'a>> {
         self.0.iter().find(|x| x.t > 0.0).copied()
     }
@@ -100,7 +113,15 @@ mod test {
         let i = Intersection::new(3.5, &s);
 
         assert_eq!(i.t, 3.5);
-        assert_eq!(*i.object, s);
+        assert_eq!(i.object, &s as &dyn Shape);
+    }
+
+    #[test]
+    fn intersection_with_uv() {
+        let s = Sphere::default();
+        let i = Intersection::with_uv(3.5, &s, 0.2, 0.4);
+        assert_eq!(i.u, 0.2);
+        assert_eq!(i.v, 0.4);
     }
 
     #[test]
@@ -157,7 +178,7 @@ mod test {
         let shape = Sphere::default();
         let i = Intersection::new(4.0, &shape);
         let comps = i.prepare_computations(ray);
-        assert_eq!(comps.inside, false);
+        assert!(!comps.inside);
     }
 
     #[test]
@@ -169,102 +190,26 @@ mod test {
         assert_eq!(comps.point, point(0.0, 0.0, 1.0));
         assert_eq!(comps.eyev, vector(0.0, 0.0, -1.0));
         assert_eq!(comps.normalv, vector(0.0, 0.0, -1.0));
-        assert_eq!(comps.inside, true);
+        assert!(comps.inside);
+    }
+
+    // From inside the point is pushed inwards, towards the eye, not out
+    // through the surface
+    #[test]
+    fn hit_inside_should_offset_point_towards_eye() {
+        let ray = Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
+        let shape = Sphere::default();
+        let comps = Intersection::new(1.0, &shape).prepare_computations(ray);
+        assert!(comps.over_point.z < 1.0 - crate::consts::OVER_POINT_SHIFT_LENGTH / 2.0);
     }
 
     #[test]
     fn hit_should_offset_point() {
         let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
-        let shape = Sphere {
-            transform: translation(0.0, 0.0, 1.0),
-            ..Sphere::default()
-        };
+        let shape = Sphere::from_transform(translation(0.0, 0.0, 1.0));
         let i = Intersection::new(5.0, &shape);
         let comps = i.prepare_computations(r);
         assert!(comps.over_point.z < -crate::consts::OVER_POINT_SHIFT_LENGTH / 2.0);
         assert!(comps.point.z > comps.over_point.z);
     }
 }
-/*
-Feature: Intersections
-Scenario: Precomputing the reflection vector
-  Given shape â† plane()
-    And r â† ray(point(0, 1, -1), vector(0, -âˆš2/2, âˆš2/2)) 
-    And i â† intersection(âˆš2, shape)                      
-  When comps â† prepare_computations(i, r)
-  Then comps.reflectv = vector(0, âˆš2/2, âˆš2/2)                
-
-Scenario: The hit should offset the point
-  Given r â† ray(point(0, 0, -5), vector(0, 0, 1))
-    And shape â† sphere() with:
-      | transform | translation(0, 0, 1) |
-    And i â† intersection(5, shape)
-  When comps â† prepare_computations(i, r)
-  Then comps.over_point.z < -EPSILON/2
-    And comps.point.z > comps.over_point.z
-
-Scenario: The under point is offset below the surface
-  Given r â† ray(point(0, 0, -5), vector(0, 0, 1))
-    And shape â† glass_sphere() with:
-      | transform | translation(0, 0, 1) |
-    And i â† intersection(5, shape)
-    And xs â† intersections(i)
-  When comps â† prepare_computations(i, r, xs)
-  Then comps.under_point.z > EPSILON/2
-    And comps.point.z < comps.under_point.z
-
-Scenario Outline: Finding n1 and n2 at various intersections
-  Given A â† glass_sphere() with:
-      | transform                 | scaling(2, 2, 2) |
-      | material.refractive_index | 1.5              |
-    And B â† glass_sphere() with:
-      | transform                 | translation(0, 0, -0.25) |
-      | material.refractive_index | 2.0                      |
-    And C â† glass_sphere() with:
-      | transform                 | translation(0, 0, 0.25) |
-      | material.refractive_index | 2.5                     |
-    And r â† ray(point(0, 0, -4), vector(0, 0, 1))
-    And xs â† intersections(2:A, 2.75:B, 3.25:C, 4.75:B, 5.25:C, 6:A)
-  When comps â† prepare_computations(xs[<index>], r, xs)  
-  Then comps.n1 = <n1>
-    And comps.n2 = <n2>             
-
-  Examples:
-    | index | n1  | n2  |
-    | 0     | 1.0 | 1.5 |                 
-    | 1     | 1.5 | 2.0 |
-    | 2     | 2.0 | 2.5 |
-    | 3     | 2.5 | 2.5 |
-    | 4     | 2.5 | 1.5 |
-    | 5     | 1.5 | 1.0 |
-
-Scenario: The Schlick approximation under total internal reflection
-  Given shape â† glass_sphere()
-    And r â† ray(point(0, 0, âˆš2/2), vector(0, 1, 0))
-    And xs â† intersections(-âˆš2/2:shape, âˆš2/2:shape)
-  When comps â† prepare_computations(xs[1], r, xs)
-    And reflectance â† schlick(comps)
-  Then reflectance = 1.0
-
-Scenario: The Schlick approximation with a perpendicular viewing angle
-  Given shape â† glass_sphere()
-    And r â† ray(point(0, 0, 0), vector(0, 1, 0))
-    And xs â† intersections(-1:shape, 1:shape)
-  When comps â† prepare_computations(xs[1], r, xs)
-    And reflectance â† schlick(comps)
-  Then reflectance = 0.04
-
-Scenario: The Schlick approximation with small angle and n2 > n1
-  Given shape â† glass_sphere()
-    And r â† ray(point(0, 0.99, -2), vector(0, 0, 1))
-    And xs â† intersections(1.8589:shape)
-  When comps â† prepare_computations(xs[0], r, xs)
-    And reflectance â† schlick(comps)
-  Then reflectance = 0.48873
-
-Scenario: An intersection can encapsulate `u` and `v`
-  Given s â† triangle(point(0, 1, 0), point(-1, 0, 0), point(1, 0, 0))
-  When i â† intersection_with_uv(3.5, s, 0.2, 0.4)
-  Then i.u = 0.2
-    And i.v = 0.4
-*/
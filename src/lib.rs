@@ -1,16 +1,38 @@
+// Functions end in an explicit `return` throughout, in the style of the
+// book's pseudocode
+#![allow(clippy::needless_return)]
+
 pub mod tuple;
+pub mod geometry;
 pub mod color;
 pub mod canvas;
+pub mod png;
+pub mod hdr;
+pub mod tonemap;
 pub mod matrix;
 pub mod transformations;
+pub mod quaternion;
 pub mod ray;
 pub mod sphere;
 pub mod intersection;
 pub mod light;
 pub mod material;
+pub mod brdf;
 pub mod world;
+pub mod integrator;
+pub mod occlusion;
 pub mod camera;
+pub mod cancel;
+pub mod aov;
+pub mod denoise;
 pub mod shape;
+pub mod bounds;
+pub mod triangle;
+pub mod group;
+pub mod obj;
+pub mod yaml;
+pub mod scene;
+pub mod rng;
 
 pub mod consts {
     pub const EPSILON: f64 = 0.00001;
@@ -19,24 +41,39 @@ pub mod consts {
 
 #[macro_export]
 macro_rules! float_eq {
-    ( $x:expr, $y:expr ) => { ($x - $y).abs() < crate::consts::EPSILON }
+    ( $x:expr, $y:expr ) => { ($x - $y).abs() < $crate::consts::EPSILON }
 }
 
 pub mod prelude {
     pub use crate::{color_rgb};
     pub use crate::tuple::{tuple, point, vector, T4};
+    pub use crate::geometry::{Point3, Vector3, Normal3};
     pub use crate::color::{color, Color};
     pub use crate::canvas::{Canvas};
+    pub use crate::png::{BitDepth};
+    pub use crate::tonemap::{Encoding, ToneMap, Transfer};
     pub use crate::matrix::{M2, M3, M4, matrix2, matrix3, matrix4};
-    pub use crate::transformations::{translation, scaling, rotation_x, rotation_y, rotation_z, shearing, view_transform};
+    pub use crate::transformations::{translation, scaling, rotation_x, rotation_y, rotation_z, rotation, rotation_about, shearing, view_transform, Transform, TransformError};
+    pub use crate::quaternion::{Quat, Decomposition};
     pub use crate::ray::{Ray};
     pub use crate::sphere::{Sphere};
     pub use crate::intersection::{Intersection, Intersections, Computations};
     pub use crate::light::{Light};
-    pub use crate::material::{Material};
+    pub use crate::material::{Material, ShadingModel};
     pub use crate::world::{World};
+    pub use crate::integrator::{Integrator};
+    pub use crate::occlusion::{AmbientOcclusion};
     pub use crate::camera::{Camera};
-    pub use crate::shape::{Shape};
+    pub use crate::cancel::{CancelToken, RenderReport};
+    pub use crate::aov::{Aov, PixelLayers, RenderLayers};
+    pub use crate::denoise::{Denoiser};
+    pub use crate::shape::{Shape, SurfaceSample};
+    pub use crate::bounds::{Bounds};
+    pub use crate::triangle::{Triangle};
+    pub use crate::group::{Group};
+    pub use crate::obj::{ObjFile, ObjError};
+    pub use crate::scene::{Scene, SceneError};
+    pub use crate::rng::{Rng};
     pub use crate::float_eq;
 }
 
@@ -64,6 +101,6 @@ pub mod test_prelude {
     }
 
     fn num_char(c: char) -> bool {
-        ('0' <= c && c <= '9') || c == '.' || c == '-'
+        c.is_ascii_digit() || c == '.' || c == '-'
     }
 }
@@ -1,3 +1,443 @@
+// Command line renderer: loads a scene file, renders it and writes the image.
+// Exit codes are stable so scripts can tell failures apart (see `USAGE`).
+use raytracer::prelude::*;
+use std::io::Write;
+use std::process::exit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "\
+Usage: raytracer [OPTIONS] <SCENE>
+
+Renders a YAML scene file.
+
+Options:
+  -o, --output <FILE>    Output image [default: render.png]
+  -f, --format <FORMAT>  png, png16, ppm, ppm-binary, hdr or pfm
+                         [default: from the output extension]
+  -W, --width <N>        Image width, keeping the camera's aspect ratio
+                         unless --height is also given
+  -H, --height <N>       Image height
+  -s, --samples <N>      Antialiasing samples per pixel
+      --max-samples <N>  Keep sampling noisy pixels up to this many samples
+                         (adaptive sampling) [default: from the scene]
+      --noise-threshold <X>
+                         Error below which a pixel stops sampling
+                         [default: from the scene, or 0.01]
+      --integrator <I>   whitted, path or ao (ambient occlusion)
+                         [default: from the scene]
+      --aovs <LIST>      Also write these layers, comma separated: depth,
+                         normal, albedo, object, shadow, or all. Each goes
+                         next to the output, e.g. render.depth.png. Float
+                         formats get the raw values.
+      --denoise          Clean up the noise of few samples, guided by the
+                         depth, normal and albedo layers
+      --progressive      Render one sample per pixel at a time and write
+                         the output after each pass, to watch a long render
+                         and stop it once it looks good enough. Can't be
+                         used with --aovs or --denoise.
+      --time-limit <S>   Stop rendering after this many seconds and write
+                         what is done. Can't be used with --aovs or
+                         --denoise.
+  -t, --threads <N>      Render threads [default: available cores]
+      --tone-map <OP>    clamp, reinhard or aces [default: clamp]
+      --srgb             Encode the output with the sRGB transfer curve
+  -q, --quiet            Only print errors
+  -h, --help             Print this help
+
+Exit codes:
+  0   success
+  64  invalid command line
+  65  the scene file could not be parsed
+  74  reading the scene or writing the image failed
+";
+
+const EXIT_USAGE: i32 = 64;
+const EXIT_PARSE: i32 = 65;
+const EXIT_IO: i32 = 74;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Png(BitDepth),
+    Ppm,
+    PpmBinary,
+    Hdr,
+    Pfm,
+}
+
+impl Format {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Some(Format::Png(BitDepth::Eight)),
+            "png16" => Some(Format::Png(BitDepth::Sixteen)),
+            "ppm" => Some(Format::Ppm),
+            "ppm-binary" | "p6" => Some(Format::PpmBinary),
+            "hdr" => Some(Format::Hdr),
+            "pfm" => Some(Format::Pfm),
+            _ => None,
+        }
+    }
+
+    // Unknown extensions fall back to ASCII PPM, like `Canvas::save`
+    fn from_path(path: &str) -> Self {
+        std::path::Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(Self::parse)
+            .unwrap_or(Format::Ppm)
+    }
+}
+
+#[derive(Debug)]
+struct Options {
+    scene: String,
+    output: String,
+    format: Option<Format>,
+    width: Option<usize>,
+    height: Option<usize>,
+    samples: Option<usize>,
+    max_samples: Option<usize>,
+    noise_threshold: Option<f64>,
+    integrator: Option<Integrator>,
+    aovs: Vec<Aov>,
+    denoise: bool,
+    progressive: bool,
+    time_limit: Option<Duration>,
+    threads: usize,
+    encoding: Encoding,
+    quiet: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut scene = None;
+    let mut opts = Options {
+        scene: String::new(),
+        output: "render.png".to_string(),
+        format: None,
+        width: None,
+        height: None,
+        samples: None,
+        max_samples: None,
+        noise_threshold: None,
+        integrator: None,
+        aovs: vec![],
+        denoise: false,
+        progressive: false,
+        time_limit: None,
+        threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        encoding: Encoding::default(),
+        quiet: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                exit(0);
+            }
+            "-o" | "--output" => opts.output = value(&arg)?,
+            "-f" | "--format" => {
+                let v = value(&arg)?;
+                opts.format = Some(Format::parse(&v).ok_or(format!("unknown format '{}'", v))?);
+            }
+            "-W" | "--width" => opts.width = Some(positive(&arg, &value(&arg)?)?),
+            "-H" | "--height" => opts.height = Some(positive(&arg, &value(&arg)?)?),
+            "-s" | "--samples" => opts.samples = Some(positive(&arg, &value(&arg)?)?),
+            "--max-samples" => opts.max_samples = Some(positive(&arg, &value(&arg)?)?),
+            "--noise-threshold" => {
+                let v = value(&arg)?;
+                opts.noise_threshold = match v.parse::<f64>() {
+                    Ok(x) if x >= 0.0 => Some(x),
+                    _ => return Err(format!("{} must be a non-negative number, found '{}'", arg, v)),
+                }
+            }
+            "--integrator" => {
+                opts.integrator = Some(match value(&arg)?.as_str() {
+                    "whitted" => Integrator::Whitted,
+                    "path" => Integrator::PATH_TRACER,
+                    "ao" => Integrator::AmbientOcclusion,
+                    v => return Err(format!("unknown integrator '{}'", v)),
+                })
+            }
+            "--aovs" => opts.aovs = parse_aovs(&value(&arg)?)?,
+            "--denoise" => opts.denoise = true,
+            "--progressive" => opts.progressive = true,
+            "--time-limit" => {
+                let v = value(&arg)?;
+                opts.time_limit = match v.parse::<f64>() {
+                    Ok(s) if s > 0.0 && s.is_finite() => Some(Duration::from_secs_f64(s)),
+                    _ => return Err(format!("{} must be a positive number of seconds, found '{}'", arg, v)),
+                }
+            }
+            "-t" | "--threads" => opts.threads = positive(&arg, &value(&arg)?)?,
+            "--tone-map" => {
+                opts.encoding.tone_map = match value(&arg)?.as_str() {
+                    "clamp" => ToneMap::Clamp,
+                    "reinhard" => ToneMap::Reinhard,
+                    "aces" => ToneMap::AcesFilmic,
+                    v => return Err(format!("unknown tone map '{}'", v)),
+                }
+            }
+            "--srgb" => opts.encoding.transfer = Transfer::Srgb,
+            "-q" | "--quiet" => opts.quiet = true,
+            s if s.starts_with('-') && s.len() > 1 => return Err(format!("unknown option '{}'", s)),
+            _ if scene.is_none() => scene = Some(arg),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    opts.scene = scene.ok_or("no scene file given")?;
+    if opts.denoise || !opts.aovs.is_empty() {
+        if opts.progressive {
+            return Err("--progressive can't be used with --aovs or --denoise".to_string());
+        }
+        if opts.time_limit.is_some() {
+            return Err("--time-limit can't be used with --aovs or --denoise".to_string());
+        }
+    }
+    Ok(opts)
+}
+
+fn parse_aovs(list: &str) -> Result<Vec<Aov>, String> {
+    if list == "all" {
+        return Ok(Aov::ALL.iter().copied().filter(|&aov| aov != Aov::Beauty).collect());
+    }
+    list.split(',')
+        .map(|name| Aov::parse(name).ok_or(format!("unknown layer '{}'", name)))
+        .collect()
+}
+
+// render.png becomes render.depth.png
+fn aov_path(output: &str, aov: Aov) -> String {
+    let path = std::path::Path::new(output);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("render");
+    let name = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => format!("{}.{}.{}", stem, aov.name(), ext),
+        None => format!("{}.{}", stem, aov.name()),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn positive(name: &str, value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} must be a positive integer, found '{}'", name, value)),
+    }
+}
+
+// The camera at the requested resolution. Given only one dimension the
+// other follows the scene's aspect ratio.
+fn resize(camera: Camera, width: Option<usize>, height: Option<usize>) -> Camera {
+    let aspect = camera.hsize as f64 / camera.vsize as f64;
+    let (w, h) = match (width, height) {
+        (None, None) => return camera,
+        (Some(w), Some(h)) => (w, h),
+        (Some(w), None) => (w, ((w as f64 / aspect).round() as usize).max(1)),
+        (None, Some(h)) => (((h as f64 * aspect).round() as usize).max(1), h),
+    };
+    let mut resized = Camera::new(w, h, camera.field_of_view, camera.transform());
+    resized.shutter_open = camera.shutter_open;
+    resized.shutter_close = camera.shutter_close;
+    resized.time_samples = camera.time_samples;
+    resized.samples = camera.samples;
+    resized.max_samples = camera.max_samples;
+    resized.noise_threshold = camera.noise_threshold;
+    resized.integrator = camera.integrator;
+    resized
+}
+
+fn write_image(canvas: &Canvas, path: &str, format: Format, encoding: Encoding) -> std::io::Result<()> {
+    match format {
+        Format::Png(depth) => canvas.save_png(path, depth, encoding),
+        Format::Ppm => std::fs::write(path, canvas.to_ppm_encoded(encoding)),
+        Format::PpmBinary => canvas.save_ppm_binary(path, BitDepth::Eight, encoding),
+        Format::Hdr | Format::Pfm => {
+            let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+            if format == Format::Hdr {
+                canvas.write_hdr(&mut out)?;
+            } else {
+                canvas.write_pfm(&mut out)?;
+            }
+            out.flush()
+        }
+    }
+}
+
 fn main() {
-    println!("Hello, world!");
+    let opts = match parse_args(std::env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            exit(EXIT_USAGE);
+        }
+    };
+
+    let start = Instant::now();
+    let scene = match Scene::load(&opts.scene) {
+        Ok(scene) => scene,
+        Err(e @ SceneError::Io(_)) => {
+            eprintln!("error: {}: {}", opts.scene, e);
+            exit(EXIT_IO);
+        }
+        Err(e @ SceneError::Parse { .. }) => {
+            eprintln!("error: {}: {}", opts.scene, e);
+            exit(EXIT_PARSE);
+        }
+    };
+    if !opts.quiet {
+        for w in scene.warnings.iter() {
+            eprintln!("warning: {}: {}", opts.scene, w);
+        }
+        eprintln!("Loaded {} in {:.2?}", opts.scene, start.elapsed());
+    }
+
+    let mut camera = resize(scene.camera, opts.width, opts.height);
+    if let Some(samples) = opts.samples {
+        camera.samples = samples;
+    }
+    if let Some(max_samples) = opts.max_samples {
+        camera.max_samples = max_samples;
+    }
+    if let Some(threshold) = opts.noise_threshold {
+        camera.noise_threshold = threshold;
+    }
+    if let Some(integrator) = opts.integrator {
+        camera.integrator = integrator;
+    }
+
+    let format = opts.format.unwrap_or_else(|| Format::from_path(&opts.output));
+    let start = Instant::now();
+    let rows = camera.vsize;
+    let quiet = opts.quiet;
+    let last_percent = AtomicUsize::new(usize::MAX);
+    let progress = |done| {
+        let percent = done * 100 / rows;
+        if !quiet && last_percent.swap(percent, Ordering::Relaxed) != percent {
+            eprint!("\rRendering {}x{}: {:3}%", camera.hsize, camera.vsize, percent);
+        }
+    };
+    let layers = if opts.aovs.is_empty() && !opts.denoise {
+        None
+    } else {
+        Some(camera.render_layers_with(&scene.world, opts.threads, progress))
+    };
+    let cancel = opts.time_limit.map_or_else(CancelToken::new, CancelToken::with_time_budget);
+    let mut report = None;
+    let canvas = match &layers {
+        Some(layers) if opts.denoise => Denoiser::default().denoise(layers),
+        Some(layers) => layers.beauty.clone(),
+        None if opts.progressive => {
+            let passes = camera.passes();
+            let (canvas, r) = camera.render_progressive(&scene.world, opts.threads, &cancel, |pass, image| {
+                if let Err(e) = write_image(image, &opts.output, format, opts.encoding) {
+                    eprintln!("\nerror: {}: {}", opts.output, e);
+                    exit(EXIT_IO);
+                }
+                if !quiet {
+                    eprint!("\rRendering {}x{}: pass {}/{}", camera.hsize, camera.vsize, pass, passes);
+                }
+                true
+            });
+            report = Some(r);
+            canvas
+        }
+        None => {
+            let (canvas, r) = camera.render_cancellable(&scene.world, opts.threads, &cancel, progress);
+            report = Some(r);
+            canvas
+        }
+    };
+    if !opts.quiet {
+        match report {
+            Some(r) if r.cancelled => eprintln!("\rStopped {}x{} after {:.2?}: {} of {} pixels and {} passes done",
+                                                camera.hsize, camera.vsize, start.elapsed(),
+                                                r.pixels_done, r.pixels, r.passes_done),
+            _ => eprintln!("\rRendered {}x{} with {} thread(s) in {:.2?}",
+                           camera.hsize, camera.vsize, opts.threads, start.elapsed()),
+        }
+    }
+
+    let mut outputs = vec![(opts.output.clone(), canvas)];
+    if let Some(layers) = layers {
+        for &aov in opts.aovs.iter() {
+            let image = match format {
+                Format::Hdr | Format::Pfm => layers.layer(aov).clone(),
+                _ => layers.image(aov),
+            };
+            outputs.push((aov_path(&opts.output, aov), image));
+        }
+    }
+    for (path, image) in outputs.iter() {
+        let start = Instant::now();
+        if let Err(e) = write_image(image, path, format, opts.encoding) {
+            eprintln!("error: {}: {}", path, e);
+            exit(EXIT_IO);
+        }
+        if !opts.quiet {
+            eprintln!("Wrote {} in {:.2?}", path, start.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn parsing_arguments() {
+        let opts = parse_args(args("scene.yml -o out.hdr -W 320 -s 4 -t 2 --srgb --integrator path")).unwrap();
+        assert_eq!(opts.scene, "scene.yml");
+        assert_eq!(opts.output, "out.hdr");
+        assert_eq!(opts.width, Some(320));
+        assert_eq!(opts.samples, Some(4));
+        assert_eq!(opts.integrator, Some(Integrator::PATH_TRACER));
+        assert_eq!(opts.threads, 2);
+        assert_eq!(opts.encoding.transfer, Transfer::Srgb);
+        assert_eq!(Format::from_path(&opts.output), Format::Hdr);
+
+        assert!(parse_args(args("")).is_err());
+        assert!(parse_args(args("a.yml b.yml")).is_err());
+        assert!(parse_args(args("a.yml -t 0")).is_err());
+        assert!(parse_args(args("a.yml -f gif")).is_err());
+        assert!(parse_args(args("a.yml --integrator photon")).is_err());
+
+        let opts = parse_args(args("a.yml --max-samples 64 --noise-threshold 0.02")).unwrap();
+        assert_eq!((opts.max_samples, opts.noise_threshold), (Some(64), Some(0.02)));
+        assert!(parse_args(args("a.yml --noise-threshold -1")).is_err());
+    }
+
+    #[test]
+    fn layers_are_written_next_to_the_output() {
+        let opts = parse_args(args("a.yml --aovs depth,object --denoise")).unwrap();
+        assert_eq!(opts.aovs, vec![Aov::Depth, Aov::Object]);
+        assert!(opts.denoise);
+        assert_eq!(parse_args(args("a.yml --aovs all")).unwrap().aovs.len(), 5);
+        assert!(parse_args(args("a.yml --aovs depth,motion")).is_err());
+        assert!(parse_args(args("a.yml --progressive")).unwrap().progressive);
+        assert!(parse_args(args("a.yml --progressive --denoise")).is_err());
+        let opts = parse_args(args("a.yml --time-limit 1.5")).unwrap();
+        assert_eq!(opts.time_limit, Some(Duration::from_millis(1500)));
+        assert!(parse_args(args("a.yml --time-limit 0")).is_err());
+        assert!(parse_args(args("a.yml --time-limit 5 --aovs depth")).is_err());
+
+        assert_eq!(aov_path("render.png", Aov::Depth), "render.depth.png");
+        assert_eq!(aov_path("out/frame.01.pfm", Aov::Normal), "out/frame.01.normal.pfm");
+        assert_eq!(aov_path("image", Aov::Albedo), "image.albedo");
+    }
+
+    #[test]
+    fn resizing_keeps_aspect_ratio() {
+        let c = Camera::simple(200, 100, 1.0);
+        let r = resize(c, Some(50), None);
+        assert_eq!((r.hsize, r.vsize), (50, 25));
+        let r = resize(c, None, Some(50));
+        assert_eq!((r.hsize, r.vsize), (100, 50));
+        let r = resize(c, Some(10), Some(10));
+        assert_eq!((r.hsize, r.vsize), (10, 10));
+    }
 }
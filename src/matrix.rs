@@ -154,6 +154,8 @@ pub struct M3 {
     pub a20: f64, pub a21: f64, pub a22: f64,
 }
 
+// One argument per element, in row order
+#[allow(clippy::too_many_arguments)]
 pub fn matrix3(a00: f64, a01: f64, a02: f64,
                a10: f64, a11: f64, a12: f64,
                a20: f64, a21: f64, a22: f64,) -> M3 {
@@ -353,6 +355,8 @@ pub struct M4 {
     pub a30: f64, pub a31: f64, pub a32: f64, pub a33: f64,
 }
 
+// One argument per element, in row order
+#[allow(clippy::too_many_arguments)]
 pub fn matrix4(a00: f64, a01: f64, a02: f64, a03: f64,
                a10: f64, a11: f64, a12: f64, a13: f64,
                a20: f64, a21: f64, a22: f64, a23: f64,
@@ -588,13 +592,28 @@ impl M4 {
     }
 
     pub fn invertible(self) -> bool {
-        !float_eq!(self.determinant(), 0.0)
+        self.try_inverse().is_some()
     }
 
-    pub fn inverse(self) -> Self {
+    // None for singular matrices, e.g. a scaling by zero along some axis, and
+    // for matrices with NaN or infinite elements. The determinant is judged
+    // relative to the product of the column lengths, which bounds it
+    // (Hadamard's inequality): tiny scalings are legitimate and have tiny
+    // determinants, while a rotated zero scaling leaves a residue of rounding
+    // error that an exact comparison with zero lets through. An affine
+    // matrix has the determinant of its upper left 3x3, so its translation
+    // column is left out.
+    pub fn try_inverse(self) -> Option<Self> {
+        const SINGULAR: f64 = 1e-12;
+
         let det = self.determinant();
-        if float_eq!(self.determinant(), 0.0) {
-            panic!("Attempt to compute inverse of non-invertible matrix: {:?}", self);
+        let affine = [self.a30, self.a31, self.a32, self.a33] == [0.0, 0.0, 0.0, 1.0];
+        let n = if affine { 3 } else { Self::SIZE };
+        let volume: f64 = (0..n)
+            .map(|col| (0..n).map(|row| self[(row, col)].powi(2)).sum::<f64>().sqrt())
+            .product();
+        if !det.is_finite() || det.abs() <= SINGULAR * volume {
+            return None;
         }
 
         let mut result = Self::default();
@@ -603,7 +622,27 @@ impl M4 {
                 result[(col, row)] = self.cofactor(row, col) / det;
             }
         }
-        return result;
+        if !result.is_finite() {
+            return None;
+        }
+        return Some(result);
+    }
+
+    pub fn inverse(self) -> Self {
+        match self.try_inverse() {
+            Some(inverse) => inverse,
+            None => panic!("Attempt to compute inverse of non-invertible matrix: {:?}", self),
+        }
+    }
+
+    pub fn is_finite(self) -> bool {
+        (0..Self::SIZE).all(|row| (0..Self::SIZE).all(|col| self[(row, col)].is_finite()))
+    }
+
+    // Element-wise linear interpolation between two matrices. Exact for
+    // translations, only an approximation for rotations.
+    pub fn lerp(self, other: Self, t: f64) -> Self {
+        self * (1.0 - t) + other * t
     }
 }
 
@@ -810,6 +849,33 @@ mod test {
         assert!(!m.invertible());
     }
 
+    #[test]
+    fn matrix4_try_inverse() {
+        assert_eq!(scaling(1.0, 0.0, 1.0).try_inverse(), None);
+        assert_eq!(scaling(2.0, 2.0, 2.0).try_inverse(), Some(scaling(0.5, 0.5, 0.5)));
+
+        // Small but perfectly valid, even though the determinant is 1e-9
+        let tiny = scaling(0.001, 0.001, 0.001).try_inverse().unwrap();
+        assert_eq!(tiny * point(0.001, 0.0, 0.0), point(1.0, 0.0, 0.0));
+
+        // A zero scaling between rotations: rounding leaves det ≈ 3e-18
+        let flat = rotation_y(0.7) * rotation_x(0.3) * scaling(1.0, 0.0, 1.0) * rotation_z(0.2);
+        assert_ne!(flat.determinant(), 0.0);
+        assert_eq!(flat.try_inverse(), None);
+        assert!(!flat.invertible());
+        assert!(translation(1e6, -1e6, 1e6).invertible());
+
+        // Translation never makes an affine matrix singular
+        let far = translation(1e12, 0.0, -1e15);
+        assert_eq!(far.try_inverse(), Some(translation(-1e12, 0.0, 1e15)));
+        assert!((translation(1e14, 0.0, 0.0) * scaling(0.01, 0.01, 0.01)).invertible());
+
+        let mut m = M4::IDENTITY;
+        m[(0, 3)] = f64::NAN;
+        assert_eq!(m.try_inverse(), None);
+        assert!(!m.invertible());
+    }
+
     #[test]
     fn matrix4_inverse() {
         let a = parse_matrix4("
@@ -870,6 +936,15 @@ mod test {
         "));
     }
 
+    #[test]
+    fn matrix4_lerp() {
+        let a = translation(0.0, 0.0, 0.0);
+        let b = translation(2.0, -4.0, 6.0);
+        assert_eq!(a.lerp(b, 0.0), a);
+        assert_eq!(a.lerp(b, 1.0), b);
+        assert_eq!(a.lerp(b, 0.5), translation(1.0, -2.0, 3.0));
+    }
+
     #[test]
     fn matrix4_inverse_product() {
         let a = parse_matrix4("
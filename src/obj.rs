@@ -0,0 +1,411 @@
+// Wavefront OBJ loading. Only geometry is read: vertices, normals, texture
+// coordinates, faces and groups. Anything else (materials, smoothing groups,
+// curves...) is skipped and reported through `ObjFile::ignored`.
+// See http://paulbourke.net/dataformats/obj/
+use crate::prelude::*;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(e) => write!(f, "{}", e),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(e: std::io::Error) -> Self { ObjError::Io(e) }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup {
+    // None for faces that appear before any `g` statement
+    pub name: Option<String>,
+    pub triangles: Vec<Triangle>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjFile {
+    pub vertices: Vec<T4>,
+    pub normals: Vec<T4>,
+    pub texcoords: Vec<(f64, f64)>,
+    pub groups: Vec<ObjGroup>,
+    // Line numbers of statements that were not understood
+    pub ignored: Vec<usize>,
+}
+
+impl ObjFile {
+    pub fn load(path: &str) -> Result<Self, ObjError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, ObjError> {
+        let mut obj = ObjFile::default();
+        obj.groups.push(ObjGroup { name: None, triangles: vec![] });
+        // Where faces go, the last group named by a `g` statement
+        let mut current = 0;
+
+        for (i, line) in src.lines().enumerate() {
+            let line_no = i + 1;
+            let err = |message: String| ObjError::Parse { line: line_no, message };
+
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let keyword = match words.next() {
+                None => continue,
+                Some(w) => w,
+            };
+            let args: Vec<&str> = words.collect();
+
+            match keyword {
+                "v" => {
+                    let [x, y, z] = parse_floats::<3>(&args).map_err(err)?;
+                    obj.vertices.push(point(x, y, z));
+                }
+                "vn" => {
+                    let [x, y, z] = parse_floats::<3>(&args).map_err(&err)?;
+                    let normal = vector(x, y, z);
+                    if normal.mag() == 0.0 {
+                        return Err(err("a vertex normal can't have zero length".to_string()));
+                    }
+                    obj.normals.push(normal.normalize());
+                }
+                "vt" => {
+                    // v is optional, a trailing w is ignored
+                    let [u] = parse_floats::<1>(&args).map_err(&err)?;
+                    let v = match args.get(1) {
+                        None => 0.0,
+                        Some(s) => parse_float(s).map_err(&err)?,
+                    };
+                    obj.texcoords.push((u, v));
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(err(format!("a face needs at least 3 vertices, found {}", args.len())));
+                    }
+                    let corners = args.iter()
+                        .map(|a| obj.face_vertex(a))
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(err)?;
+                    let triangles = fan_triangulation(&corners);
+                    obj.groups[current].triangles.extend(triangles);
+                }
+                "g" => {
+                    // A bare `g` goes back to the unnamed group, and naming a
+                    // group again adds to it
+                    let name = Some(args.join(" ")).filter(|name| !name.is_empty());
+                    current = match obj.groups.iter().position(|g| g.name == name) {
+                        Some(index) => index,
+                        None => {
+                            obj.groups.push(ObjGroup { name, triangles: vec![] });
+                            obj.groups.len() - 1
+                        }
+                    };
+                }
+                _ => obj.ignored.push(line_no),
+            }
+        }
+
+        // Drop groups that ended up without any faces
+        obj.groups.retain(|g| !g.triangles.is_empty());
+        return Ok(obj);
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ObjGroup> {
+        self.groups.iter().find(|g| g.name.as_deref() == Some(name))
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.groups.iter().map(|g| g.triangles.len()).sum()
+    }
+
+    // A single group holding one child group per OBJ group, with every
+    // triangle given `material`.
+    pub fn to_group(&self, material: Material) -> Group {
+        let mut root = Group::new();
+        for g in self.groups.iter() {
+            let mut child = match &g.name {
+                Some(name) => Group::named(name),
+                None => Group::new(),
+            };
+            for t in g.triangles.iter() {
+                let mut t = *t;
+                t.set_material(material);
+                child.add_child(Box::new(t));
+            }
+            root.add_child(Box::new(child));
+        }
+        return root;
+    }
+
+    // A face vertex is `v`, `v/vt`, `v//vn` or `v/vt/vn`. Indices start at 1
+    // and negative indices count back from the most recent element.
+    fn face_vertex(&self, s: &str) -> Result<FaceVertex, String> {
+        let mut parts = s.split('/');
+        let v = parts.next().unwrap_or("");
+        let vt = parts.next().filter(|p| !p.is_empty());
+        let vn = parts.next().filter(|p| !p.is_empty());
+
+        let position = self.vertices[resolve_index(v, self.vertices.len(), "vertex")?];
+        let texcoord = match vt {
+            None => None,
+            Some(i) => Some(self.texcoords[resolve_index(i, self.texcoords.len(), "texture coordinate")?]),
+        };
+        let normal = match vn {
+            None => None,
+            Some(i) => Some(self.normals[resolve_index(i, self.normals.len(), "normal")?]),
+        };
+        Ok(FaceVertex { position, texcoord, normal })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct FaceVertex {
+    position: T4,
+    texcoord: Option<(f64, f64)>,
+    normal: Option<T4>,
+}
+
+fn resolve_index(s: &str, len: usize, what: &str) -> Result<usize, String> {
+    let i: i64 = s.parse().map_err(|_| format!("invalid {} index '{}'", what, s))?;
+    let idx = if i < 0 { len as i64 + i } else { i - 1 };
+    if i == 0 || idx < 0 || idx >= len as i64 {
+        return Err(format!("{} index {} out of range (have {})", what, i, len));
+    }
+    Ok(idx as usize)
+}
+
+// Polygons are split into triangles that all share the first vertex. Smooth
+// triangles are only made when every corner has a normal.
+fn fan_triangulation(corners: &[FaceVertex]) -> Vec<Triangle> {
+    let mut triangles = Vec::with_capacity(corners.len() - 2);
+    for i in 1..corners.len() - 1 {
+        let (a, b, c) = (corners[0], corners[i], corners[i + 1]);
+        let mut t = match (a.normal, b.normal, c.normal) {
+            (Some(n1), Some(n2), Some(n3)) =>
+                Triangle::smooth(a.position, b.position, c.position, n1, n2, n3),
+            _ => Triangle::new(a.position, b.position, c.position),
+        };
+        if let (Some(t1), Some(t2), Some(t3)) = (a.texcoord, b.texcoord, c.texcoord) {
+            t.texcoords = Some([t1, t2, t3]);
+        }
+        triangles.push(t);
+    }
+    return triangles;
+}
+
+fn parse_float(s: &str) -> Result<f64, String> {
+    s.parse().map_err(|_| format!("invalid number '{}'", s))
+}
+
+fn parse_floats<const N: usize>(args: &[&str]) -> Result<[f64; N], String> {
+    if args.len() < N {
+        return Err(format!("expected {} numbers, found {}", N, args.len()));
+    }
+    let mut out = [0.0; N];
+    for (o, a) in out.iter_mut().zip(args) {
+        *o = parse_float(a)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignoring_unrecognized_lines() {
+        let obj = ObjFile::parse("There was a young lady named Bright
+who traveled much faster than light.
+She set out one day
+in a relative way,
+and came back the previous night.").unwrap();
+        assert_eq!(obj.ignored, vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn vertex_records() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1.0000 0.5000 0.0000
+v 1 0 0
+v 1 1 0").unwrap();
+        assert_eq!(obj.vertices, vec![
+            point(-1.0, 1.0, 0.0), point(-1.0, 0.5, 0.0),
+            point(1.0, 0.0, 0.0), point(1.0, 1.0, 0.0),
+        ]);
+    }
+
+    #[test]
+    fn parsing_triangle_faces() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+
+f 1 2 3
+f 1 3 4").unwrap();
+        let t = &obj.groups[0].triangles;
+        assert_eq!(t.len(), 2);
+        assert_eq!((t[0].p1, t[0].p2, t[0].p3), (obj.vertices[0], obj.vertices[1], obj.vertices[2]));
+        assert_eq!((t[1].p1, t[1].p2, t[1].p3), (obj.vertices[0], obj.vertices[2], obj.vertices[3]));
+    }
+
+    #[test]
+    fn triangulating_polygons() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+v 0 2 0
+
+f 1 2 3 4 5").unwrap();
+        let t = &obj.groups[0].triangles;
+        assert_eq!(t.len(), 3);
+        assert_eq!((t[2].p1, t[2].p2, t[2].p3), (obj.vertices[0], obj.vertices[3], obj.vertices[4]));
+    }
+
+    #[test]
+    fn triangles_in_groups() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+
+g FirstGroup
+f 1 2 3
+g SecondGroup
+f 1 3 4").unwrap();
+        assert_eq!(obj.groups.len(), 2);
+        assert_eq!(obj.group("FirstGroup").unwrap().triangles[0].p3, obj.vertices[2]);
+        assert_eq!(obj.group("SecondGroup").unwrap().triangles[0].p3, obj.vertices[3]);
+    }
+
+    #[test]
+    fn repeated_group_names_add_to_the_group() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+
+g First
+f 1 2 3
+g Second
+f 1 3 4
+g First
+f 2 3 4").unwrap();
+        assert_eq!(obj.groups.len(), 2);
+        assert_eq!(obj.group("First").unwrap().triangles.len(), 2);
+        assert_eq!(obj.group("Second").unwrap().triangles.len(), 1);
+    }
+
+    #[test]
+    fn unnamed_g_goes_back_to_the_default_group() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1 0 0
+v 1 0 0
+
+f 1 2 3
+g Named
+f 1 2 3
+g
+f 1 2 3").unwrap();
+        assert_eq!(obj.groups.len(), 2);
+        assert_eq!(obj.groups[0].name, None);
+        assert_eq!(obj.groups[0].triangles.len(), 2);
+        assert_eq!(obj.group("").map(|g| g.triangles.len()), None);
+    }
+
+    #[test]
+    fn converting_to_group() {
+        let obj = ObjFile::parse("v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+g FirstGroup
+f 1 2 3
+g SecondGroup
+f 1 3 4").unwrap();
+        let g = obj.to_group(Material::default());
+        assert_eq!(g.len(), 2);
+        let mut xs = Intersections::empty();
+        g.intersect(Ray::new(point(0.5, 0.5, -2.0), vector(0.0, 0.0, 1.0)), &mut xs);
+        assert_eq!(xs.len(), 1);
+    }
+
+    #[test]
+    fn vertex_normal_records() {
+        let obj = ObjFile::parse("vn 0 0 1
+vn 0.707 0 -0.707
+vn 1 2 3").unwrap();
+        assert_eq!(obj.normals[0], vector(0.0, 0.0, 1.0));
+        assert_eq!(obj.normals[1], vector(0.707, 0.0, -0.707).normalize());
+        assert_eq!(obj.normals[2], vector(1.0, 2.0, 3.0).normalize());
+    }
+
+    #[test]
+    fn faces_with_normals_and_texcoords() {
+        let obj = ObjFile::parse("v 0 1 0
+v -1 0 0
+v 1 0 0
+vt 0 0
+vt 1 0
+vt 0.5 1
+
+vn -1 0 0
+vn 1 0 0
+vn 0 1 0
+
+f 1//3 2//1 3//2
+f 1/1/3 2/2/1 3/3/2").unwrap();
+        let t1 = obj.groups[0].triangles[0];
+        let t2 = obj.groups[0].triangles[1];
+        assert_eq!(t1.normals, Some([obj.normals[2], obj.normals[0], obj.normals[1]]));
+        assert_eq!(t1.texcoords, None);
+        assert_eq!(t2.normals, t1.normals);
+        assert_eq!(t2.texcoords, Some([(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)]));
+    }
+
+    #[test]
+    fn negative_indices() {
+        let obj = ObjFile::parse("v 0 1 0
+v -1 0 0
+v 1 0 0
+f -3 -2 -1").unwrap();
+        assert_eq!(obj.groups[0].triangles[0].p1, point(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        match ObjFile::parse("v 0 1 0\nv 1 0 nope\n") {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 2),
+            other => panic!("unexpected {:?}", other),
+        }
+        match ObjFile::parse("v 0 1 0\n\nf 1 2 3\n") {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 3);
+                assert!(message.contains("out of range"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match ObjFile::parse("f 1 2\n") {
+            Err(ObjError::Parse { line: 1, .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        match ObjFile::parse("vn 0 0 1\nvn 0 0 0\n") {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 2);
+                assert!(message.contains("zero length"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
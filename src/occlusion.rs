@@ -0,0 +1,119 @@
+// Ambient occlusion: how much of the sky a point on a surface can see. Rays
+// are cast over the hemisphere around the normal, more of them close to the
+// normal (cosine weighted), and any that hit something within `max_distance`
+// count as blocked. Creases, corners and the ground under an object see
+// less and come out darker.
+//
+// Set on a world it scales the ambient term of Whitted shading, which is
+// otherwise the same everywhere. `Integrator::AmbientOcclusion` renders it
+// on its own as a grayscale image, white where nothing is in the way.
+use crate::prelude::*;
+use crate::brdf::cosine_sample_hemisphere;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AmbientOcclusion {
+    // Rays per shading point
+    pub samples: usize,
+    // Geometry further away than this doesn't occlude, so that the walls of
+    // a room don't darken everything inside it. Infinite by default.
+    pub max_distance: f64,
+}
+
+impl Default for AmbientOcclusion {
+    fn default() -> Self {
+        Self { samples: 16, max_distance: f64::INFINITY }
+    }
+}
+
+impl AmbientOcclusion {
+    pub fn new(samples: usize, max_distance: f64) -> Self {
+        Self { samples, max_distance }
+    }
+
+    // The fraction of rays from `point` that escape, between 0 (fully
+    // occluded) and 1 (open sky). `point` should be the over point of a hit,
+    // so the surface doesn't occlude itself.
+    pub fn visibility<'a>(self, world: &'a World, point: T4, normal: T4, time: f64, rng: &mut Rng,
+                          xs: &mut Intersections<'a>) -> f64 {
+        let samples = self.samples.max(1);
+        let mut open = 0;
+        for _ in 0..samples {
+            let ray = Ray::with_time(point, cosine_sample_hemisphere(normal, rng), time);
+            xs.clear();
+            world.intersect(ray, xs);
+            match xs.hit() {
+                Some(hit) if hit.t < self.max_distance => (),
+                _ => open += 1,
+            }
+        }
+        return open as f64 / samples as f64;
+    }
+
+    // The grayscale image: the visibility of the first surface along `ray`,
+    // white where the ray hits nothing
+    pub fn color_at<'a>(self, world: &'a World, ray: Ray, rng: &mut Rng,
+                        xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> Color {
+        xs1.clear();
+        world.intersect(ray, xs1);
+        let comps = match xs1.hit() {
+            None => return Color::WHITE,
+            Some(hit) => hit.prepare_computations(ray),
+        };
+        let v = self.visibility(world, comps.over_point, comps.normalv, comps.time, rng, xs2);
+        return color(v, v, v);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn floor() -> Triangle {
+        Triangle::new(point(-100.0, 0.0, -100.0), point(-100.0, 0.0, 100.0), point(100.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn open_surfaces_are_unoccluded() {
+        let mut w = World::default();
+        w.add_object(floor());
+        let ao = AmbientOcclusion::default();
+        let v = ao.visibility(&w, point(0.0, 0.0001, 0.0), vector(0.0, 1.0, 0.0), 0.0,
+                              &mut Rng::new(1), &mut Intersections::empty());
+        assert_eq!(v, 1.0);
+    }
+
+    // Under a unit sphere resting on the floor, a point at distance d from
+    // the contact sees the sphere over a cone. Close to the contact nearly
+    // half the hemisphere is blocked; a short max distance lets more through.
+    #[test]
+    fn contact_points_are_darkened() {
+        let mut w = World::default();
+        w.add_object(floor()).add_object(Sphere::from_transform(translation(0.0, 1.0, 0.0)));
+        let at = |x: f64, ao: AmbientOcclusion| ao.visibility(&w, point(x, 0.0001, 0.0), vector(0.0, 1.0, 0.0), 0.0,
+                                                               &mut Rng::new(2), &mut Intersections::empty());
+        let near = at(0.3, AmbientOcclusion::new(4000, f64::INFINITY));
+        let far = at(3.0, AmbientOcclusion::new(4000, f64::INFINITY));
+        assert!(near < 0.6, "{}", near);
+        assert!(far > near && far < 1.0, "{}", far);
+        assert!(at(3.0, AmbientOcclusion::new(4000, 0.5)) == 1.0);
+    }
+
+    #[test]
+    fn rendering_occlusion() {
+        let mut w = World::default();
+        w.add_object(floor());
+        let ao = AmbientOcclusion::default();
+        let down = Ray::new(point(0.0, 1.0, 0.0), vector(0.0, -1.0, 0.0));
+        let up = Ray::new(point(0.0, 0.2, 0.0), vector(0.0, 1.0, 0.0));
+        let mut rng = Rng::new(3);
+        assert_eq!(ao.color_at(&w, down, &mut rng, &mut Intersections::empty(), &mut Intersections::empty()),
+                   Color::WHITE);
+        w.add_object(Sphere::from_transform(translation(0.0, 0.5, 0.0) * scaling(2.0, 0.1, 2.0)));
+        let under = ao.color_at(&w, Ray::new(point(0.0, 0.2, -10.0), vector(0.0, -0.02, 1.0).normalize()),
+                                &mut rng, &mut Intersections::empty(), &mut Intersections::empty());
+        assert!(under.red < 0.5 && under.red == under.blue, "{:?}", under);
+        // The underside of the disk only sees the floor
+        assert_eq!(ao.color_at(&w, up, &mut rng, &mut Intersections::empty(), &mut Intersections::empty()),
+                   Color::BLACK);
+    }
+}
@@ -0,0 +1,380 @@
+// A minimal PNG encoder: truecolour RGB images at 8 or 16 bits per channel.
+// The zlib stream is produced by a small in-crate deflate implementation
+// (LZ77 with fixed Huffman codes) so no external crates are required.
+// See https://www.w3.org/TR/png/ and RFC 1950/1951.
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn bytes(self) -> usize {
+        match self {
+            BitDepth::Eight => 1,
+            BitDepth::Sixteen => 2,
+        }
+    }
+}
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Encode an image given as row-major RGB samples. Samples are already
+// quantised to the range of the bit depth (0..=255 or 0..=65535).
+pub fn encode(width: usize, height: usize, depth: BitDepth, samples: &[u16]) -> Vec<u8> {
+    assert_eq!(samples.len(), width * height * 3);
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&SIGNATURE);
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    ihdr.push(match depth { BitDepth::Eight => 8, BitDepth::Sixteen => 16 });
+    ihdr.push(2); // Colour type: truecolour
+    ihdr.push(0); // Compression method: deflate
+    ihdr.push(0); // Filter method: adaptive
+    ihdr.push(0); // Interlace method: none
+    write_chunk(&mut out, b"IHDR", &ihdr);
+
+    let raw = filter_rows(width, height, depth, samples);
+    write_chunk(&mut out, b"IDAT", &zlib_compress(&raw));
+    write_chunk(&mut out, b"IEND", &[]);
+
+    return out;
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+/////////////////////////////////////////////////////////
+// Filtering
+/////////////////////////////////////////////////////////
+
+// Serialise each scanline and prefix it with the filter type that gives the
+// smallest sum of absolute differences, the heuristic suggested by the spec.
+fn filter_rows(width: usize, height: usize, depth: BitDepth, samples: &[u16]) -> Vec<u8> {
+    let bpp = 3 * depth.bytes();
+    let stride = width * bpp;
+
+    let mut out = Vec::with_capacity((stride + 1) * height);
+    let mut prev = vec![0u8; stride];
+    let mut curr = vec![0u8; stride];
+    let mut candidate = vec![0u8; stride];
+    let mut best = vec![0u8; stride];
+
+    for y in 0..height {
+        let row = &samples[y * width * 3..(y + 1) * width * 3];
+        for (i, &s) in row.iter().enumerate() {
+            match depth {
+                BitDepth::Eight => curr[i] = s as u8,
+                BitDepth::Sixteen => {
+                    curr[2 * i] = (s >> 8) as u8;
+                    curr[2 * i + 1] = s as u8;
+                }
+            }
+        }
+
+        let mut best_filter = 0;
+        let mut best_score = u64::MAX;
+        for filter in 0..5u8 {
+            apply_filter(filter, bpp, &curr, &prev, &mut candidate);
+            let score: u64 = candidate.iter().map(|&b| (b as i8).unsigned_abs() as u64).sum();
+            if score < best_score {
+                best_score = score;
+                best_filter = filter;
+                best.copy_from_slice(&candidate);
+            }
+        }
+
+        out.push(best_filter);
+        out.extend_from_slice(&best);
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    return out;
+}
+
+fn apply_filter(filter: u8, bpp: usize, curr: &[u8], prev: &[u8], out: &mut [u8]) {
+    for i in 0..curr.len() {
+        let a = if i >= bpp { curr[i - bpp] } else { 0 };
+        let b = prev[i];
+        let c = if i >= bpp { prev[i - bpp] } else { 0 };
+        let predictor = match filter {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            _ => paeth(a, b, c),
+        };
+        out[i] = curr[i].wrapping_sub(predictor);
+    }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc { a } else if pb <= pc { b } else { c }
+}
+
+/////////////////////////////////////////////////////////
+// Checksums
+/////////////////////////////////////////////////////////
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    return !crc;
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest block for which b cannot overflow before reducing
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    return (b << 16) | a;
+}
+
+/////////////////////////////////////////////////////////
+// Deflate
+/////////////////////////////////////////////////////////
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: usize = 15;
+
+// Length codes 257..=285: (base length, extra bits)
+const LENGTH_TABLE: [(u16, u8); 29] = [
+    (3, 0), (4, 0), (5, 0), (6, 0), (7, 0), (8, 0), (9, 0), (10, 0),
+    (11, 1), (13, 1), (15, 1), (17, 1), (19, 2), (23, 2), (27, 2), (31, 2),
+    (35, 3), (43, 3), (51, 3), (59, 3), (67, 4), (83, 4), (99, 4), (115, 4),
+    (131, 5), (163, 5), (195, 5), (227, 5), (258, 0),
+];
+
+// Distance codes 0..=29: (base distance, extra bits)
+const DISTANCE_TABLE: [(u16, u8); 30] = [
+    (1, 0), (2, 0), (3, 0), (4, 0), (5, 1), (7, 1), (9, 2), (13, 2),
+    (17, 3), (25, 3), (33, 4), (49, 4), (65, 5), (97, 5), (129, 6), (193, 6),
+    (257, 7), (385, 7), (513, 8), (769, 8), (1025, 9), (1537, 9),
+    (2049, 10), (3073, 10), (4097, 11), (6145, 11), (8193, 12), (12289, 12),
+    (16385, 13), (24577, 13),
+];
+
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::new();
+    // CMF: deflate with a 32K window. FLG: no dictionary, check bits so that
+    // the 16 bit header is a multiple of 31.
+    w.bytes.extend_from_slice(&[0x78, 0x01]);
+    deflate(data, &mut w);
+    let mut out = w.finish();
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    return out;
+}
+
+// Emit the whole input as a single final block using the fixed Huffman codes.
+fn deflate(data: &[u8], w: &mut BitWriter) {
+    w.write_bits(1, 1); // BFINAL
+    w.write_bits(1, 2); // BTYPE = 01, fixed Huffman
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut prev = vec![usize::MAX; WINDOW_SIZE];
+
+    let mut i = 0;
+    while i < data.len() {
+        let (len, dist) = longest_match(data, i, &head, &prev);
+        let step = if len >= MIN_MATCH {
+            write_length(w, len);
+            write_distance(w, dist);
+            len
+        } else {
+            write_literal(w, data[i] as u16);
+            1
+        };
+
+        for j in i..(i + step) {
+            if j + MIN_MATCH <= data.len() {
+                let h = hash(data, j);
+                prev[j % WINDOW_SIZE] = head[h];
+                head[h] = j;
+            }
+        }
+        i += step;
+    }
+
+    write_literal(w, 256); // End of block
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as usize) << 16 | (data[i + 1] as usize) << 8 | data[i + 2] as usize;
+    (v.wrapping_mul(2_654_435_761) >> 7) & ((1 << HASH_BITS) - 1)
+}
+
+fn longest_match(data: &[u8], i: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if i + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+
+    let max_len = MAX_MATCH.min(data.len() - i);
+    let (mut best_len, mut best_dist) = (0, 0);
+    let mut candidate = head[hash(data, i)];
+
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::MAX || i - candidate > WINDOW_SIZE {
+            break;
+        }
+        let len = data[candidate..].iter()
+            .zip(&data[i..i + max_len])
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best_len {
+            best_len = len;
+            best_dist = i - candidate;
+            if len == max_len { break; }
+        }
+        let next = prev[candidate % WINDOW_SIZE];
+        // Entries older than the window may have been overwritten
+        if next == usize::MAX || next >= candidate { break; }
+        candidate = next;
+    }
+
+    return (best_len, best_dist);
+}
+
+fn write_literal(w: &mut BitWriter, value: u16) {
+    // Fixed Huffman literal/length codes, RFC 1951 section 3.2.6
+    let (code, len) = match value {
+        0..=143 => (0x30 + value, 8),
+        144..=255 => (0x190 + value - 144, 9),
+        256..=279 => (value - 256, 7),
+        _ => (0xC0 + value - 280, 8),
+    };
+    w.write_huffman(code as u32, len);
+}
+
+fn write_length(w: &mut BitWriter, len: usize) {
+    let idx = LENGTH_TABLE.iter().rposition(|&(base, _)| base as usize <= len).unwrap();
+    let (base, extra) = LENGTH_TABLE[idx];
+    write_literal(w, 257 + idx as u16);
+    w.write_bits((len - base as usize) as u32, extra);
+}
+
+fn write_distance(w: &mut BitWriter, dist: usize) {
+    let idx = DISTANCE_TABLE.iter().rposition(|&(base, _)| base as usize <= dist).unwrap();
+    let (base, extra) = DISTANCE_TABLE[idx];
+    // Distance codes are fixed 5 bit codes
+    w.write_huffman(idx as u32, 5);
+    w.write_bits((dist - base as usize) as u32, extra);
+}
+
+// Deflate packs data elements starting at the least significant bit, except
+// Huffman codes which are packed most significant bit first.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    count: u8,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self { bytes: Vec::new(), acc: 0, count: 0 }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u8) {
+        self.acc |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.bytes.push(self.acc as u8);
+            self.acc >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn write_huffman(&mut self, code: u32, len: u8) {
+        let reversed = code.reverse_bits() >> (32 - len as u32);
+        self.write_bits(reversed, len);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.acc as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn crc32_known_value() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn adler32_known_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn zlib_header_and_trailer() {
+        let z = zlib_compress(b"hello hello hello hello");
+        assert_eq!((z[0] as u16 * 256 + z[1] as u16) % 31, 0);
+        assert_eq!(&z[z.len() - 4..], &adler32(b"hello hello hello hello").to_be_bytes());
+    }
+
+    #[test]
+    fn repeated_data_compresses() {
+        let data = vec![7u8; 10_000];
+        assert!(zlib_compress(&data).len() < 200);
+    }
+
+    #[test]
+    fn png_structure() {
+        let png = encode(2, 1, BitDepth::Eight, &[255, 0, 0, 0, 0, 255]);
+        assert_eq!(&png[..8], &SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..20], &2u32.to_be_bytes());
+        assert_eq!(&png[20..24], &1u32.to_be_bytes());
+        assert_eq!(png[24], 8);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+
+    #[test]
+    fn png_sixteen_bit_depth() {
+        let png = encode(1, 1, BitDepth::Sixteen, &[65535, 0, 32768]);
+        assert_eq!(png[24], 16);
+    }
+
+    #[test]
+    fn paeth_predictor() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+    }
+}
@@ -0,0 +1,315 @@
+// Unit quaternions for rotations, and splitting a transform into
+// translation, rotation and scale so that keyframed transforms can be
+// interpolated smoothly. `M4::lerp` interpolates element by element, which
+// is exact for translations but shrinks and shears an object halfway
+// through a rotation; interpolating the rotation as a quaternion keeps it
+// rigid.
+use crate::prelude::*;
+
+#[derive(Copy, Clone, Debug)]
+pub struct Quat {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl PartialEq for Quat {
+    fn eq(&self, other: &Self) -> bool {
+        float_eq!(self.w, other.w)
+        && float_eq!(self.x, other.x)
+        && float_eq!(self.y, other.y)
+        && float_eq!(self.z, other.z)
+    }
+}
+
+impl Default for Quat {
+    fn default() -> Self { Self::IDENTITY }
+}
+
+// Composition: `a * b` rotates by `b` first, then by `a`, like matrices
+impl std::ops::Mul for Quat {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        Self {
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+        }
+    }
+}
+
+impl Quat {
+    pub const IDENTITY: Self = Quat { w: 1.0, x: 0.0, y: 0.0, z: 0.0 };
+
+    // A rotation of `angle` radians about `axis`, turning the same way as
+    // `rotation_x` and friends do about theirs.
+    pub fn from_axis_angle(axis: Vector3, angle: f64) -> Self {
+        let axis = axis.normalize();
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        return Self { w: cos, x: axis.x * sin, y: axis.y * sin, z: axis.z * sin };
+    }
+
+    // The rotation part of a matrix whose upper 3x3 is a pure rotation
+    pub fn from_rotation_matrix(m: M4) -> Self {
+        let trace = m[(0, 0)] + m[(1, 1)] + m[(2, 2)];
+        // Divide by the largest of the four candidates for precision
+        let q = if trace > 0.0 {
+            let s = (trace + 1.0).sqrt() * 2.0;
+            Self { w: s / 4.0,
+                   x: (m[(2, 1)] - m[(1, 2)]) / s,
+                   y: (m[(0, 2)] - m[(2, 0)]) / s,
+                   z: (m[(1, 0)] - m[(0, 1)]) / s }
+        } else if m[(0, 0)] > m[(1, 1)] && m[(0, 0)] > m[(2, 2)] {
+            let s = (1.0 + m[(0, 0)] - m[(1, 1)] - m[(2, 2)]).sqrt() * 2.0;
+            Self { w: (m[(2, 1)] - m[(1, 2)]) / s,
+                   x: s / 4.0,
+                   y: (m[(0, 1)] + m[(1, 0)]) / s,
+                   z: (m[(0, 2)] + m[(2, 0)]) / s }
+        } else if m[(1, 1)] > m[(2, 2)] {
+            let s = (1.0 + m[(1, 1)] - m[(0, 0)] - m[(2, 2)]).sqrt() * 2.0;
+            Self { w: (m[(0, 2)] - m[(2, 0)]) / s,
+                   x: (m[(0, 1)] + m[(1, 0)]) / s,
+                   y: s / 4.0,
+                   z: (m[(1, 2)] + m[(2, 1)]) / s }
+        } else {
+            let s = (1.0 + m[(2, 2)] - m[(0, 0)] - m[(1, 1)]).sqrt() * 2.0;
+            Self { w: (m[(1, 0)] - m[(0, 1)]) / s,
+                   x: (m[(0, 2)] + m[(2, 0)]) / s,
+                   y: (m[(1, 2)] + m[(2, 1)]) / s,
+                   z: s / 4.0 }
+        };
+        return q.normalize();
+    }
+
+    pub fn dot(self, other: Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalize(self) -> Self {
+        let m = self.dot(self).sqrt();
+        return Self { w: self.w / m, x: self.x / m, y: self.y / m, z: self.z / m };
+    }
+
+    // The opposite rotation
+    pub fn conjugate(self) -> Self {
+        Self { w: self.w, x: -self.x, y: -self.y, z: -self.z }
+    }
+
+    // Spherical linear interpolation: turns at a constant rate from `self`
+    // at t = 0.0 to `other` at t = 1.0, the short way round.
+    pub fn slerp(self, other: Self, t: f64) -> Self {
+        // q and -q are the same rotation, pick the one closer to self
+        let (other, cos) = match self.dot(other) {
+            d if d < 0.0 => (Self { w: -other.w, x: -other.x, y: -other.y, z: -other.z }, -d),
+            d => (other, d),
+        };
+
+        // Nearly the same rotation: sin(angle) is too small to divide by,
+        // and a straight line is indistinguishable from the arc
+        let (a, b) = if cos > 1.0 - crate::consts::EPSILON {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        return Self {
+            w: a * self.w + b * other.w,
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+        }.normalize();
+    }
+
+    pub fn to_m4(self) -> M4 {
+        let Quat { w, x, y, z } = self.normalize();
+        matrix4(1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z),       2.0 * (x * z + w * y),       0.0,
+                2.0 * (x * y + w * z),       1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x),       0.0,
+                2.0 * (x * z - w * y),       2.0 * (y * z + w * x),       1.0 - 2.0 * (x * x + y * y), 0.0,
+                0.0,                         0.0,                         0.0,                         1.0)
+    }
+}
+
+// A transform split into `translation * rotation * scaling`, i.e. scaled
+// first, then rotated, then moved.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Decomposition {
+    pub translation: Vector3,
+    pub rotation: Quat,
+    pub scale: Vector3,
+}
+
+impl Decomposition {
+    // None for transforms that aren't made of a translation, rotation and
+    // scale alone, e.g. ones with shearing, a scaling by zero, or a
+    // projective bottom row.
+    pub fn new(m: M4) -> Option<Self> {
+        let bottom = [m[(3, 0)], m[(3, 1)], m[(3, 2)], m[(3, 3)]];
+        if bottom != [0.0, 0.0, 0.0, 1.0] || !m.is_finite() {
+            return None;
+        }
+
+        let column = |c| Vector3::new(m[(0, c)], m[(1, c)], m[(2, c)]);
+        let columns = [column(0), column(1), column(2)];
+        // Judged by `invertible` rather than the size of the scale, which is
+        // legitimately tiny for small objects
+        if !m.invertible() {
+            return None;
+        }
+        let mut scale = Vector3::new(columns[0].mag(), columns[1].mag(), columns[2].mag());
+        // A mirror image: put the reflection in the scale so that what is
+        // left is a rotation
+        if columns[0].cross(columns[1]).dot(columns[2]) < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let axes = [columns[0] / scale.x, columns[1] / scale.y, columns[2] / scale.z];
+        // Shearing leaves the axes skewed
+        let square = float_eq!(axes[0].dot(axes[1]), 0.0)
+            && float_eq!(axes[0].dot(axes[2]), 0.0)
+            && float_eq!(axes[1].dot(axes[2]), 0.0);
+        if !square {
+            return None;
+        }
+
+        let mut rotation = M4::IDENTITY;
+        for (c, axis) in axes.iter().enumerate() {
+            rotation[(0, c)] = axis.x;
+            rotation[(1, c)] = axis.y;
+            rotation[(2, c)] = axis.z;
+        }
+
+        return Some(Self {
+            translation: Vector3::new(m[(0, 3)], m[(1, 3)], m[(2, 3)]),
+            rotation: Quat::from_rotation_matrix(rotation),
+            scale,
+        });
+    }
+
+    pub fn to_m4(self) -> M4 {
+        let Decomposition { translation: t, rotation, scale: s } = self;
+        translation(t.x, t.y, t.z) * rotation.to_m4() * scaling(s.x, s.y, s.z)
+    }
+
+    // Translation and scale are interpolated linearly and the rotation
+    // along the arc. A mirror image can't be reached without flattening the
+    // object on the way, so when only one of the two is mirrored the scale
+    // keeps the sign of `self` throughout.
+    pub fn interpolate(self, other: Self, t: f64) -> Self {
+        let mut end_scale = other.scale;
+        if (self.scale.x < 0.0) != (other.scale.x < 0.0) {
+            end_scale.x = -end_scale.x;
+        }
+        Self {
+            translation: self.translation * (1.0 - t) + other.translation * t,
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale * (1.0 - t) + end_scale * t,
+        }
+    }
+}
+
+// The transform a fraction `t` of the way between two keyframes. Transforms
+// that can't be decomposed fall back to `M4::lerp`.
+pub fn interpolate(start: M4, end: M4, t: f64) -> M4 {
+    match (Decomposition::new(start), Decomposition::new(end)) {
+        (Some(a), Some(b)) => a.interpolate(b, t).to_m4(),
+        _ => start.lerp(end, t),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_3, FRAC_PI_4, PI};
+
+    #[test]
+    fn axis_angle_matches_euler_rotations() {
+        for &angle in [FRAC_PI_4, FRAC_PI_2, -FRAC_PI_3, PI].iter() {
+            assert_eq!(Quat::from_axis_angle(Vector3::EX, angle).to_m4(), rotation_x(angle));
+            assert_eq!(Quat::from_axis_angle(Vector3::EY, angle).to_m4(), rotation_y(angle));
+            assert_eq!(Quat::from_axis_angle(Vector3::EZ * 3.0, angle).to_m4(), rotation_z(angle));
+        }
+    }
+
+    #[test]
+    fn composing_quaternions() {
+        let a = Quat::from_axis_angle(Vector3::EX, FRAC_PI_2);
+        let b = Quat::from_axis_angle(Vector3::EY, FRAC_PI_4);
+        assert_eq!((a * b).to_m4(), rotation_x(FRAC_PI_2) * rotation_y(FRAC_PI_4));
+        assert_eq!(a * a.conjugate(), Quat::IDENTITY);
+    }
+
+    #[test]
+    fn slerp_turns_at_constant_rate() {
+        let a = Quat::IDENTITY;
+        let b = Quat::from_axis_angle(Vector3::EZ, FRAC_PI_2);
+        assert_eq!(a.slerp(b, 0.0), a);
+        assert_eq!(a.slerp(b, 1.0), b);
+        assert_eq!(a.slerp(b, 0.5), Quat::from_axis_angle(Vector3::EZ, FRAC_PI_4));
+        assert_eq!(a.slerp(b, 1.0 / 3.0).to_m4(), rotation_z(PI / 6.0));
+
+        // -b is the same rotation, and is still taken the short way
+        let minus_b = Quat { w: -b.w, x: -b.x, y: -b.y, z: -b.z };
+        assert_eq!(a.slerp(minus_b, 0.5).to_m4(), rotation_z(FRAC_PI_4));
+    }
+
+    #[test]
+    fn decomposing_a_transform() {
+        let m = translation(1.0, -2.0, 3.0) * rotation_y(FRAC_PI_3) * rotation_x(0.3) * scaling(2.0, 0.5, 1.5);
+        let d = Decomposition::new(m).unwrap();
+        assert_eq!(d.translation, Vector3::new(1.0, -2.0, 3.0));
+        assert_eq!(d.scale, Vector3::new(2.0, 0.5, 1.5));
+        assert_eq!(d.rotation.to_m4(), rotation_y(FRAC_PI_3) * rotation_x(0.3));
+        assert_eq!(d.to_m4(), m);
+
+        // Reflections survive the round trip
+        let mirror = rotation_z(2.0) * scaling(-1.0, 1.0, 1.0);
+        assert_eq!(Decomposition::new(mirror).unwrap().to_m4(), mirror);
+    }
+
+    #[test]
+    fn shearing_does_not_decompose() {
+        assert_eq!(Decomposition::new(shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0)), None);
+        assert_eq!(Decomposition::new(scaling(1.0, 0.0, 1.0)), None);
+        let flat = rotation_y(0.7) * rotation_x(0.3) * scaling(1.0, 0.0, 1.0) * rotation_z(0.2);
+        assert_eq!(Decomposition::new(flat), None);
+    }
+
+    #[test]
+    fn small_scales_decompose() {
+        let m = translation(1.0, 0.0, 0.0) * rotation_z(0.4) * scaling(0.02, 0.02, 0.02);
+        let d = Decomposition::new(m).unwrap();
+        assert_eq!(d.scale, Vector3::new(0.02, 0.02, 0.02));
+        assert_eq!(d.rotation.to_m4(), rotation_z(0.4));
+        assert_eq!(d.to_m4(), m);
+    }
+
+    #[test]
+    fn interpolating_keyframes_keeps_objects_rigid() {
+        let start = translation(0.0, 0.0, 0.0);
+        let end = translation(4.0, 0.0, 0.0) * rotation_y(PI * 0.9) * scaling(3.0, 3.0, 3.0);
+        let halfway = interpolate(start, end, 0.5);
+        assert_eq!(halfway, translation(2.0, 0.0, 0.0) * rotation_y(PI * 0.45) * scaling(2.0, 2.0, 2.0));
+
+        // Straight interpolation of the elements nearly collapses the object
+        // instead
+        let turn = rotation_y(PI * 0.9);
+        assert!((M4::IDENTITY.lerp(turn, 0.5) * vector(1.0, 0.0, 0.0)).mag() < 0.2);
+        assert!(float_eq!((interpolate(M4::IDENTITY, turn, 0.5) * vector(1.0, 0.0, 0.0)).mag(), 1.0));
+    }
+
+    #[test]
+    fn interpolating_towards_a_mirror_image_never_flattens() {
+        let mirror = translation(2.0, 0.0, 0.0) * scaling(-2.0, 2.0, 2.0);
+        for &t in [0.25, 0.5, 0.75].iter() {
+            let m = interpolate(M4::IDENTITY, mirror, t);
+            assert!(m.invertible());
+            assert!(m.determinant() > 0.0);
+        }
+        assert_eq!(interpolate(M4::IDENTITY, mirror, 0.5),
+                   translation(1.0, 0.0, 0.0) * scaling(1.5, 1.5, 1.5));
+    }
+}
@@ -1,9 +1,11 @@
 use crate::prelude::*;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub struct Ray {
     pub origin: T4,
     pub direction: T4,
+    // Moment within the camera shutter interval at which the ray is cast
+    pub time: f64,
 }
 
 impl std::ops::Mul<Ray> for M4 {
@@ -11,13 +13,17 @@ impl std::ops::Mul<Ray> for M4 {
     fn mul(self, ray: Ray) -> Self::Output {
         let origin = self * ray.origin;
         let direction = self * ray.direction;
-        Self::Output { origin, direction }
+        Self::Output { origin, direction, time: ray.time }
     }
 }
 
 impl Ray {
     pub fn new(origin: T4, direction: T4) -> Self {
-        Self { origin, direction }
+        Self::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: T4, direction: T4, time: f64) -> Self {
+        Self { origin, direction, time }
     }
 
     pub fn at(self, t: f64) -> T4 {
@@ -36,6 +42,13 @@ mod test {
         let r = Ray::new(origin, direction);
         assert_eq!(r.origin, origin);
         assert_eq!(r.direction, direction);
+        assert_eq!(r.time, 0.0);
+    }
+
+    #[test]
+    fn ray_create_with_time() {
+        let r = Ray::with_time(point(1.0, 2.0, 3.0), vector(4.0, 5.0, 6.0), 0.25);
+        assert_eq!(r.time, 0.25);
     }
 
     #[test]
@@ -55,6 +68,13 @@ mod test {
         assert_eq!(r.direction, vector(0.0, 1.0, 0.0));
     }
 
+    #[test]
+    fn ray_transform_keeps_time() {
+        let r = translation(3.0, 4.0, 5.0)
+            * Ray::with_time(point(1.0, 2.0, 3.0), vector(0.0, 1.0, 0.0), 0.75);
+        assert_eq!(r.time, 0.75);
+    }
+
     #[test]
     fn ray_scale() {
         let r = scaling(2.0, 3.0, 4.0)
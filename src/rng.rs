@@ -0,0 +1,72 @@
+use crate::prelude::*;
+
+// A small, fast pseudo random number generator (xorshift64*). Rendering only
+// needs cheap, reproducible noise, so there is no need for an external crate.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // A zero state would make xorshift output zero forever
+        let state = splitmix64(seed) | 1;
+        Self { state }
+    }
+
+    // A generator whose sequence only depends on the given pixel, so renders
+    // are reproducible regardless of the order pixels are visited in.
+    pub fn for_pixel(x: usize, y: usize) -> Self {
+        Self::new(((y as u64) << 32) ^ x as u64)
+    }
+
+    // A generator seeded from a position, for code that has to stay
+    // deterministic but still needs the odd random sample
+    pub fn for_point(p: T4) -> Self {
+        Self::new(p.x.to_bits() ^ p.y.to_bits().rotate_left(21) ^ p.z.to_bits().rotate_left(42))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Uniformly distributed in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn splitmix64(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        for _ in 0..10 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+    }
+
+    #[test]
+    fn next_f64_in_unit_interval() {
+        let mut rng = Rng::for_pixel(3, 7);
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+        }
+    }
+}
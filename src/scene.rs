@@ -0,0 +1,757 @@
+// Scene files in the YAML style used by The Ray Tracer Challenge. A scene is
+// a list of commands:
+//
+//   - add: camera            width, height, field-of-view, from, to, up,
+//                            samples, max-samples, noise-threshold,
+//                            shutter: [open, close], time-samples,
+//                            integrator: whitted, path or ao, max-depth
+//   - add: light             at, intensity
+//   - add: ambient-occlusion samples, max-distance
+//   - add: sphere            material, transform, end-transform
+//   - add: triangle          p1, p2, p3, material, transform
+//   - add: group             children (a list of add commands), material,
+//                            transform
+//   - add: obj               file, material, transform
+//   - define: name           value, and optionally extend: other-name
+//
+// Materials have a color, emission, ambient, and depending on their model
+// (phong, blinn-phong, oren-nayar or metallic-roughness) diffuse, specular,
+// shininess, sigma (Oren-Nayar's slope spread in radians, 0 to pi/2),
+// metallic and roughness (both 0 to 1).
+//
+// A define names a material (a mapping) or a transform (a list) that later
+// commands can refer to by name. Extending a material overrides the keys of
+// the original, extending a transform appends to it. Transforms are written
+// as lists of operations applied in order, e.g.
+//
+//   transform:
+//     - [ scale, 0.5, 0.5, 0.5 ]
+//     - [ translate, 0, 1, 0 ]
+//
+// Keys that are understood by the book but not by this renderer (reflective,
+// patterns, planes' shadow flags...) are skipped and reported as warnings.
+// Shapes the renderer does not have are an error.
+use crate::prelude::*;
+use crate::yaml::{self, Node, Value};
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "{}", e),
+            SceneError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> Self { SceneError::Io(e) }
+}
+
+impl From<yaml::YamlError> for SceneError {
+    fn from(e: yaml::YamlError) -> Self { SceneError::Parse { line: e.line, message: e.message } }
+}
+
+#[derive(Debug)]
+pub struct Scene {
+    pub world: World,
+    pub camera: Camera,
+    // Things that were skipped, e.g. "line 12: unknown material key 'reflective'"
+    pub warnings: Vec<String>,
+}
+
+impl Scene {
+    // Files referenced by the scene (OBJ models) are found relative to the
+    // scene file.
+    pub fn load(path: &str) -> Result<Self, SceneError> {
+        let src = std::fs::read_to_string(path)?;
+        let dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        Builder::new(dir).build(&src)
+    }
+
+    pub fn parse(src: &str) -> Result<Self, SceneError> {
+        Builder::new(PathBuf::new()).build(src)
+    }
+}
+
+struct Builder {
+    dir: PathBuf,
+    defines: HashMap<String, Node>,
+    world: World,
+    camera: Option<Camera>,
+    warnings: Vec<String>,
+}
+
+fn err<T>(line: usize, message: String) -> Result<T, SceneError> {
+    Err(SceneError::Parse { line, message })
+}
+
+impl Builder {
+    fn new(dir: PathBuf) -> Self {
+        Self { dir, defines: HashMap::new(), world: World::default(), camera: None, warnings: vec![] }
+    }
+
+    fn build(mut self, src: &str) -> Result<Scene, SceneError> {
+        let root = yaml::parse(src)?;
+        let commands = match root.as_list() {
+            Some(commands) => commands,
+            None => return err(root.line, "a scene must be a list of commands".to_string()),
+        };
+
+        for command in commands.iter() {
+            if let Some(name) = command.get("define") {
+                self.define(name, command)?;
+            } else if let Some(kind) = command.get("add") {
+                match kind.as_str() {
+                    Some("camera") => self.camera = Some(self.camera(command)?),
+                    Some("light") => {
+                        let light = self.light(command)?;
+                        self.world.lights.push(light);
+                    }
+                    Some("ambient-occlusion") => {
+                        self.world.ambient_occlusion = Some(self.ambient_occlusion(command)?);
+                    }
+                    _ => {
+                        let shape = self.shape(command, Material::default())?;
+                        self.world.objects.push(shape);
+                    }
+                }
+            } else {
+                return err(command.line, "expected an 'add' or 'define' command".to_string());
+            }
+        }
+
+        let camera = match self.camera {
+            Some(camera) => camera,
+            None => return err(root.line, "the scene has no camera".to_string()),
+        };
+        Ok(Scene { world: self.world, camera, warnings: self.warnings })
+    }
+
+    fn define(&mut self, name: &Node, command: &Node) -> Result<(), SceneError> {
+        let name = match name.as_str() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => return err(name.line, "expected a name to define".to_string()),
+        };
+        let mut value = match command.get("value") {
+            Some(value) => value.clone(),
+            None => return err(command.line, format!("'{}' has no value", name)),
+        };
+
+        if let Some(base) = command.get("extend") {
+            let base = self.lookup(base)?;
+            value.value = match (base.value, value.value) {
+                (Value::Map(mut entries), Value::Map(overrides)) => {
+                    for (k, v) in overrides {
+                        entries.retain(|(key, _)| *key != k);
+                        entries.push((k, v));
+                    }
+                    Value::Map(entries)
+                }
+                (Value::List(mut items), Value::List(more)) => {
+                    items.extend(more);
+                    Value::List(items)
+                }
+                _ => return err(value.line, format!("'{}' cannot extend a definition of a different kind", name)),
+            };
+        }
+
+        self.defines.insert(name, value);
+        Ok(())
+    }
+
+    fn lookup(&self, name: &Node) -> Result<Node, SceneError> {
+        let s = name.as_str().unwrap_or("");
+        match self.defines.get(s) {
+            Some(node) => Ok(node.clone()),
+            None => err(name.line, format!("'{}' has not been defined", s)),
+        }
+    }
+
+    fn warn(&mut self, line: usize, message: String) {
+        self.warnings.push(format!("line {}: {}", line, message));
+    }
+
+    fn camera(&mut self, node: &Node) -> Result<Camera, SceneError> {
+        let width = usize_field(node, "width")?;
+        let height = usize_field(node, "height")?;
+        let fov_node = required(node, "field-of-view")?;
+        let fov = number(fov_node)?;
+        if !(fov > 0.0 && fov < PI) {
+            return err(fov_node.line, "'field-of-view' must be between 0 and pi radians".to_string());
+        }
+        let from = point_from(required(node, "from")?)?;
+        let to = point_from(required(node, "to")?)?;
+        let up = vector_from(required(node, "up")?)?;
+
+        let mut camera = match Camera::try_new(width, height, fov, view_transform(from, to, up)) {
+            Ok(camera) => camera,
+            Err(_) => return err(node.line,
+                                 "camera 'from' and 'to' must differ and 'up' must not point along the view".to_string()),
+        };
+        if let Some(shutter) = node.get("shutter") {
+            let [open, close] = numbers::<2>(shutter)?;
+            camera.shutter_open = open;
+            camera.shutter_close = close;
+        }
+        if node.get("time-samples").is_some() {
+            camera.time_samples = usize_field(node, "time-samples")?;
+        }
+        if node.get("samples").is_some() {
+            camera.samples = usize_field(node, "samples")?;
+        }
+        if node.get("max-samples").is_some() {
+            camera.max_samples = usize_field(node, "max-samples")?;
+        }
+        if let Some(threshold) = node.get("noise-threshold") {
+            camera.noise_threshold = number(threshold)?;
+            if camera.noise_threshold < 0.0 {
+                return err(threshold.line, "'noise-threshold' must not be negative".to_string());
+            }
+        }
+        if let Some(integrator) = node.get("integrator") {
+            camera.integrator = match integrator.as_str() {
+                Some("whitted") => Integrator::Whitted,
+                Some("path") => Integrator::PATH_TRACER,
+                Some("ao") => Integrator::AmbientOcclusion,
+                _ => return err(integrator.line, "integrator must be 'whitted', 'path' or 'ao'".to_string()),
+            };
+        }
+        if node.get("max-depth").is_some() {
+            let max_depth = usize_field(node, "max-depth")?;
+            match camera.integrator {
+                Integrator::PathTracer { .. } => camera.integrator = Integrator::PathTracer { max_depth },
+                _ => self.warn(node.line, "max-depth only applies to the path integrator".to_string()),
+            }
+        }
+        self.check_keys(node, &["add", "width", "height", "field-of-view", "from", "to", "up",
+                                "samples", "max-samples", "noise-threshold", "shutter", "time-samples",
+                                "integrator", "max-depth"]);
+        Ok(camera)
+    }
+
+    fn light(&mut self, node: &Node) -> Result<Light, SceneError> {
+        let at = point_from(required(node, "at")?)?;
+        let intensity = color_from(required(node, "intensity")?)?;
+        self.check_keys(node, &["add", "at", "intensity"]);
+        Ok(Light::new(at, intensity))
+    }
+
+    fn ambient_occlusion(&mut self, node: &Node) -> Result<AmbientOcclusion, SceneError> {
+        let mut ao = AmbientOcclusion::default();
+        if node.get("samples").is_some() {
+            ao.samples = usize_field(node, "samples")?;
+        }
+        if let Some(distance) = node.get("max-distance") {
+            ao.max_distance = number(distance)?;
+            if ao.max_distance <= 0.0 {
+                return err(distance.line, "'max-distance' must be positive".to_string());
+            }
+        }
+        self.check_keys(node, &["add", "samples", "max-distance"]);
+        Ok(ao)
+    }
+
+    // Children of a group inherit its material unless they set their own
+    fn shape(&mut self, node: &Node, inherited: Material) -> Result<Box<dyn Shape>, SceneError> {
+        let kind = required(node, "add")?;
+        let material = match node.get("material") {
+            Some(m) => self.material(m)?,
+            None => inherited,
+        };
+        let transform = match node.get("transform") {
+            Some(t) => self.transform(t)?,
+            None => M4::IDENTITY,
+        };
+
+        let shape: Box<dyn Shape> = match kind.as_str().unwrap_or("") {
+            "sphere" => {
+                let mut s = Sphere::new(transform, material);
+                if let Some(t) = node.get("end-transform") {
+                    if let Err(e) = s.try_set_end_transform(self.transform(t)?) {
+                        return err(t.line, e.to_string());
+                    }
+                }
+                self.check_keys(node, &["add", "material", "transform", "end-transform"]);
+                Box::new(s)
+            }
+            "triangle" => {
+                let mut t = Triangle::new(
+                    point_from(required(node, "p1")?)?,
+                    point_from(required(node, "p2")?)?,
+                    point_from(required(node, "p3")?)?);
+                t.set_transform(transform).set_material(material);
+                self.check_keys(node, &["add", "material", "transform", "p1", "p2", "p3"]);
+                Box::new(t)
+            }
+            "group" => {
+                let mut g = Group::new();
+                g.set_transform(transform).set_material(material);
+                if let Some(children) = node.get("children") {
+                    let children = match children.as_list() {
+                        Some(children) => children,
+                        None => return err(children.line, "'children' must be a list".to_string()),
+                    };
+                    for child in children.iter() {
+                        let child = self.shape(child, material)?;
+                        g.add_child(child);
+                    }
+                }
+                self.check_keys(node, &["add", "material", "transform", "children"]);
+                Box::new(g)
+            }
+            "obj" => {
+                let file = required(node, "file")?;
+                let path = self.dir.join(file.as_str().unwrap_or(""));
+                let obj = match ObjFile::load(&path.to_string_lossy()) {
+                    Ok(obj) => obj,
+                    Err(ObjError::Io(e)) => return Err(SceneError::Io(e)),
+                    Err(ObjError::Parse { line, message }) => return err(file.line,
+                        format!("{}: line {}: {}", path.display(), line, message)),
+                };
+                let mut g = obj.to_group(material);
+                g.set_transform(transform);
+                self.check_keys(node, &["add", "material", "transform", "file"]);
+                Box::new(g)
+            }
+            other => return err(kind.line, format!("unsupported shape '{}'", other)),
+        };
+        Ok(shape)
+    }
+
+    // Either the name of a defined material or a mapping of its properties
+    fn material(&mut self, node: &Node) -> Result<Material, SceneError> {
+        let node = match node.value {
+            Value::Scalar(_) => self.lookup(node)?,
+            _ => node.clone(),
+        };
+        let entries = match node.as_map() {
+            Some(entries) => entries,
+            None => return err(node.line, "expected a material".to_string()),
+        };
+
+        let mut material = Material::default();
+        for (key, value) in entries.iter() {
+            match key.as_str() {
+                "color" => material.color = color_from(value)?,
+                "ambient" => material.ambient = number(value)?,
+                "diffuse" => material.diffuse = number(value)?,
+                "specular" => material.specular = number(value)?,
+                "shininess" => material.shininess = number(value)?,
+                "emission" => material.emission = color_from(value)?,
+                "model" => material.model = match value.as_str() {
+                    Some("phong") => ShadingModel::Phong,
+                    Some("blinn-phong") => ShadingModel::BlinnPhong,
+                    Some("oren-nayar") => ShadingModel::OrenNayar,
+                    Some("metallic-roughness") => ShadingModel::MetallicRoughness,
+                    _ => return err(value.line, "model must be 'phong', 'blinn-phong', 'oren-nayar' or \
+                                                 'metallic-roughness'".to_string()),
+                },
+                "metallic" => material.metallic = fraction(value, key)?,
+                "roughness" => material.roughness = fraction(value, key)?,
+                "sigma" => {
+                    material.sigma = number(value)?;
+                    if !(0.0..=FRAC_PI_2).contains(&material.sigma) {
+                        return err(value.line, "'sigma' must be between 0 and pi/2 radians".to_string());
+                    }
+                }
+                _ => self.warn(value.line, format!("unknown material key '{}'", key)),
+            }
+        }
+        Ok(material)
+    }
+
+    // A list of operations, or of names of defined transforms, applied in
+    // order
+    fn transform(&self, node: &Node) -> Result<M4, SceneError> {
+        self.transform_of(node, &mut vec![])
+    }
+
+    // `resolving` holds the names being expanded, so a define that refers
+    // back to itself is an error rather than endless recursion
+    fn transform_of(&self, node: &Node, resolving: &mut Vec<String>) -> Result<M4, SceneError> {
+        let ops = match node.as_list() {
+            Some(ops) => ops,
+            None => return err(node.line, "expected a list of transforms".to_string()),
+        };
+
+        let mut m = M4::IDENTITY;
+        for op in ops.iter() {
+            let step = match op.value {
+                Value::Scalar(ref name) => {
+                    if resolving.contains(name) {
+                        return err(op.line, format!("'{}' is defined in terms of itself", name));
+                    }
+                    let value = self.lookup(op)?;
+                    resolving.push(name.clone());
+                    let step = self.transform_of(&value, resolving)?;
+                    resolving.pop();
+                    step
+                }
+                _ => transform_op(op)?,
+            };
+            m = step * m;
+        }
+        if let Err(e) = Transform::try_new(m) {
+            return err(node.line, e.to_string());
+        }
+        Ok(m)
+    }
+
+    fn check_keys(&mut self, node: &Node, known: &[&str]) {
+        for (key, value) in node.as_map().unwrap_or(&[]).iter() {
+            if !known.contains(&key.as_str()) {
+                self.warn(value.line, format!("unknown key '{}'", key));
+            }
+        }
+    }
+}
+
+fn transform_op(op: &Node) -> Result<M4, SceneError> {
+    let items = op.as_list().unwrap_or(&[]);
+    let name = items.first().and_then(Node::as_str).unwrap_or("");
+    let args = items.iter().skip(1).map(number).collect::<Result<Vec<f64>, _>>()?;
+
+    let expected = match name {
+        "translate" | "scale" => 3,
+        "rotate-x" | "rotate-y" | "rotate-z" => 1,
+        "shear" => 6,
+        _ => return err(op.line, format!("unknown transform '{}'", name)),
+    };
+    if args.len() != expected {
+        return err(op.line, format!("'{}' takes {} arguments, found {}", name, expected, args.len()));
+    }
+
+    Ok(match name {
+        "translate" => translation(args[0], args[1], args[2]),
+        "scale" => scaling(args[0], args[1], args[2]),
+        "rotate-x" => rotation_x(args[0]),
+        "rotate-y" => rotation_y(args[0]),
+        "rotate-z" => rotation_z(args[0]),
+        _ => shearing(args[0], args[1], args[2], args[3], args[4], args[5]),
+    })
+}
+
+fn required<'a>(node: &'a Node, key: &str) -> Result<&'a Node, SceneError> {
+    match node.get(key) {
+        Some(value) => Ok(value),
+        None => err(node.line, format!("missing '{}'", key)),
+    }
+}
+
+fn number(node: &Node) -> Result<f64, SceneError> {
+    match node.as_f64() {
+        Some(x) => Ok(x),
+        None => err(node.line, "expected a number".to_string()),
+    }
+}
+
+fn fraction(node: &Node, key: &str) -> Result<f64, SceneError> {
+    let x = number(node)?;
+    if !(0.0..=1.0).contains(&x) {
+        return err(node.line, format!("'{}' must be between 0 and 1", key));
+    }
+    Ok(x)
+}
+
+fn usize_field(node: &Node, key: &str) -> Result<usize, SceneError> {
+    let value = required(node, key)?;
+    match value.as_str().and_then(|s| s.parse().ok()) {
+        Some(n) if n > 0 => Ok(n),
+        _ => err(value.line, format!("'{}' must be a positive integer", key)),
+    }
+}
+
+fn numbers<const N: usize>(node: &Node) -> Result<[f64; N], SceneError> {
+    let items = node.as_list().unwrap_or(&[]);
+    if items.len() != N {
+        return err(node.line, format!("expected a list of {} numbers", N));
+    }
+    let mut out = [0.0; N];
+    for (o, item) in out.iter_mut().zip(items.iter()) {
+        *o = number(item)?;
+    }
+    Ok(out)
+}
+
+fn point_from(node: &Node) -> Result<T4, SceneError> {
+    let [x, y, z] = numbers::<3>(node)?;
+    Ok(point(x, y, z))
+}
+
+fn vector_from(node: &Node) -> Result<T4, SceneError> {
+    let [x, y, z] = numbers::<3>(node)?;
+    Ok(vector(x, y, z))
+}
+
+fn color_from(node: &Node) -> Result<Color, SceneError> {
+    let [r, g, b] = numbers::<3>(node)?;
+    Ok(color(r, g, b))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    const SCENE: &str = "
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 0.785
+  from: [ 0, 1.5, -5 ]
+  to: [ 0, 1, 0 ]
+  up: [ 0, 1, 0 ]
+
+- add: light
+  at: [ -10, 10, -10 ]
+  intensity: [ 1, 1, 1 ]
+
+- define: white-material
+  value:
+    color: [ 1, 1, 1 ]
+    diffuse: 0.7
+    ambient: 0.1
+
+- define: blue-material
+  extend: white-material
+  value:
+    color: [ 0.537, 0.831, 0.914 ]
+
+- define: standard-transform
+  value:
+    - [ translate, 1, -1, 1 ]
+    - [ scale, 0.5, 0.5, 0.5 ]
+
+- define: large-object
+  extend: standard-transform
+  value:
+    - [ scale, 3.5, 3.5, 3.5 ]
+
+- add: sphere
+  material: blue-material
+  transform:
+    - large-object
+    - [ translate, 0, 0, 2 ]
+";
+
+    #[test]
+    fn parsing_scene() {
+        let scene = Scene::parse(SCENE).unwrap();
+        assert_eq!(scene.camera.hsize, 100);
+        assert_eq!(scene.camera.vsize, 50);
+        assert_eq!(scene.camera.transform(),
+                   view_transform(point(0.0, 1.5, -5.0), point(0.0, 1.0, 0.0), vector(0.0, 1.0, 0.0)));
+        assert_eq!(scene.world.lights, vec![Light::new(point(-10.0, 10.0, -10.0), color(1.0, 1.0, 1.0))]);
+        assert_eq!(scene.world.objects.len(), 1);
+        assert!(scene.warnings.is_empty());
+    }
+
+    #[test]
+    fn extending_definitions() {
+        let scene = Scene::parse(SCENE).unwrap();
+        let s = &scene.world.objects[0];
+        assert_eq!(s.material(), Material {
+            color: color(0.537, 0.831, 0.914), diffuse: 0.7, ambient: 0.1, ..Material::default()
+        });
+        assert_eq!(s.transform(),
+                   translation(0.0, 0.0, 2.0) * scaling(3.5, 3.5, 3.5)
+                   * scaling(0.5, 0.5, 0.5) * translation(1.0, -1.0, 1.0));
+    }
+
+    #[test]
+    fn path_tracing_and_emission() {
+        let src = "
+- add: camera
+  width: 10
+  height: 10
+  field-of-view: 1
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+  integrator: path
+  max-depth: 5
+  samples: 8
+  max-samples: 256
+  noise-threshold: 0.005
+- add: sphere
+  material:
+    emission: [ 4, 4, 2 ]
+";
+        let scene = Scene::parse(src).unwrap();
+        assert_eq!(scene.camera.integrator, Integrator::PathTracer { max_depth: 5 });
+        assert_eq!((scene.camera.samples, scene.camera.max_samples), (8, 256));
+        assert_eq!(scene.camera.noise_threshold, 0.005);
+        assert_eq!(scene.world.objects[0].material().emission, color(4.0, 4.0, 2.0));
+        assert!(scene.warnings.is_empty());
+    }
+
+    #[test]
+    fn shading_models() {
+        let src = "
+- add: camera
+  width: 10
+  height: 10
+  field-of-view: 1
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+- add: sphere
+  material:
+    model: metallic-roughness
+    color: [ 1, 0.8, 0.3 ]
+    metallic: 1
+    roughness: 0.25
+- add: sphere
+  material:
+    model: oren-nayar
+    sigma: 0.8
+- add: sphere
+  material:
+    model: blinn-phong
+";
+        let scene = Scene::parse(src).unwrap();
+        let m = scene.world.objects[0].material();
+        assert_eq!(m.model, ShadingModel::MetallicRoughness);
+        assert_eq!((m.metallic, m.roughness), (1.0, 0.25));
+        assert_eq!(scene.world.objects[1].material().model, ShadingModel::OrenNayar);
+        assert_eq!(scene.world.objects[1].material().sigma, 0.8);
+        assert_eq!(scene.world.objects[2].material().model, ShadingModel::BlinnPhong);
+        assert!(scene.warnings.is_empty());
+    }
+
+    #[test]
+    fn ambient_occlusion() {
+        let src = "
+- add: camera
+  width: 10
+  height: 10
+  field-of-view: 1
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+  integrator: ao
+- add: ambient-occlusion
+  samples: 8
+  max-distance: 2.5
+";
+        let scene = Scene::parse(src).unwrap();
+        assert_eq!(scene.camera.integrator, Integrator::AmbientOcclusion);
+        assert_eq!(scene.world.ambient_occlusion, Some(AmbientOcclusion::new(8, 2.5)));
+        assert!(Scene::parse(SCENE).unwrap().world.ambient_occlusion.is_none());
+    }
+
+    #[test]
+    fn groups_pass_material_and_transform_to_children() {
+        let src = "
+- add: camera
+  width: 10
+  height: 10
+  field-of-view: 1
+  from: [ 0, 0, -5 ]
+  to: [ 0, 0, 0 ]
+  up: [ 0, 1, 0 ]
+- add: group
+  material:
+    color: [ 1, 0, 0 ]
+  transform:
+    - [ rotate-y, 1.5707963267948966 ]
+  children:
+    - add: sphere
+      transform:
+        - [ translate, 0, 0, 1 ]
+    - add: triangle
+      p1: [ 0, 1, 0 ]
+      p2: [ -1, 0, 0 ]
+      p3: [ 1, 0, 0 ]
+      material:
+        ambient: 1
+";
+        let scene = Scene::parse(src).unwrap();
+        let g = &scene.world.objects[0];
+        assert_eq!(g.transform(), rotation_y(FRAC_PI_2));
+        assert!(float_eq!(g.bounds().min.x, 0.0));
+        let mut xs = Intersections::empty();
+        g.intersect(Ray::new(point(5.0, 0.0, 0.0), vector(-1.0, 0.0, 0.0)), &mut xs);
+        let hit = xs.hit().unwrap();
+        assert!(float_eq!(hit.t, 3.0));
+        assert_eq!(hit.object.material().color, color(1.0, 0.0, 0.0));
+        assert_eq!(xs.len(), 3);
+        assert!((0..xs.len()).any(|i| xs[i].object.material().ambient == 1.0));
+    }
+
+    #[test]
+    fn unknown_keys_are_warnings() {
+        let src = format!("{}
+- add: sphere
+  shadow: false
+  material:
+    reflective: 0.5
+", SCENE);
+        let scene = Scene::parse(&src).unwrap();
+        assert_eq!(scene.warnings.len(), 2);
+        assert!(scene.warnings[0].contains("reflective"));
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let line_of = |src: &str| match Scene::parse(src) {
+            Err(SceneError::Parse { line, .. }) => line,
+            other => panic!("expected a parse error, got {:?}", other.map(|s| s.warnings)),
+        };
+        assert_eq!(line_of("- add: cube\n"), 1);
+        assert_eq!(line_of("- add: sphere\n  material: nope\n"), 2);
+        assert_eq!(line_of("- add: sphere\n  material:\n    model: pbr\n"), 3);
+        assert_eq!(line_of("- add: sphere\n  transform:\n    - [ scale, 1 ]\n"), 3);
+        assert_eq!(line_of("- add: light\n  at: [ 1, 2 ]\n  intensity: [ 1, 1, 1 ]"), 2);
+        assert_eq!(line_of("- add: light\n  at: [ 1, 2, 3 ]\n  intensity: [ 1, 1, 1 ]"), 1);
+        assert_eq!(line_of("- add: sphere\n  transform:\n    - [ scale, 1, 0, 1 ]\n"), 3);
+        assert_eq!(line_of("- add: sphere\n  end-transform:\n    - [ rotate-z, 0.2 ]\n    \
+                            - [ scale, 1, 0, 1 ]\n    - [ rotate-x, 0.3 ]\n"), 3);
+        assert_eq!(line_of("- add: camera\n  width: 10\n  height: 10\n  field-of-view: 1\n  \
+                            from: [ 0, 0, 0 ]\n  to: [ 0, 0, 0 ]\n  up: [ 0, 1, 0 ]\n"), 1);
+        assert_eq!(line_of("- add: camera\n  width: 10\n  height: 10\n  field-of-view: 1\n  \
+                            from: [ 0, 0, -5 ]\n  to: [ 0, 0, 0 ]\n  up: [ 0, 1, 0 ]\n  \
+                            noise-threshold: -1\n"), 8);
+        assert_eq!(line_of("- add: camera\n  width: 10\n  height: 10\n  field-of-view: 0\n  \
+                            from: [ 0, 0, -5 ]\n  to: [ 0, 0, 0 ]\n  up: [ 0, 1, 0 ]\n"), 4);
+        assert_eq!(line_of("- add: camera\n  width: 10\n  height: 10\n  field-of-view: 3.2\n  \
+                            from: [ 0, 0, -5 ]\n  to: [ 0, 0, 0 ]\n  up: [ 0, 1, 0 ]\n"), 4);
+        assert_eq!(line_of("- add: sphere\n  end-transform:\n    - [ scale, -1, 1, 1 ]\n"), 3);
+        assert_eq!(line_of("- add: sphere\n  material:\n    roughness: 1.5\n"), 3);
+        assert_eq!(line_of("- add: sphere\n  material:\n    model: oren-nayar\n    sigma: 2\n"), 4);
+    }
+
+    #[test]
+    fn self_referencing_defines_are_errors() {
+        let src = "- define: a\n  value:\n    - [ scale, 2, 2, 2 ]\n    - a\n\
+                   - add: sphere\n  transform: [ a ]\n";
+        match Scene::parse(src) {
+            Err(SceneError::Parse { line, message }) => {
+                assert_eq!(line, 4);
+                assert!(message.contains("'a' is defined in terms of itself"));
+            }
+            other => panic!("expected a parse error, got {:?}", other.map(|s| s.warnings)),
+        }
+
+        let src = "- define: a\n  value: [ b ]\n- define: b\n  value: [ a ]\n\
+                   - add: sphere\n  transform: [ a ]\n";
+        assert!(matches!(Scene::parse(src), Err(SceneError::Parse { line: 4, .. })));
+
+        // The same define used twice in one list is not a cycle
+        let src = format!("{}- define: a\n  value: [ [ translate, 1, 0, 0 ] ]\n\
+                           - add: sphere\n  transform: [ a, a ]\n", SCENE);
+        assert!(Scene::parse(&src).is_ok());
+    }
+}
@@ -1,55 +1,141 @@
-use std::cell::Cell;
+use std::sync::Mutex;
+use std::fmt::Debug;
 
 use crate::prelude::*;
 
-pub trait Shape: Default {
+// Shapes are shared between render threads, so they must be Send + Sync
+pub trait Shape: Debug + Send + Sync {
     fn local_intersect<'a>(&'a self, local_ray: Ray, xs: &mut Intersections<'a>);
     fn local_normal_at(&self, local_point: T4) -> T4;
 
+    // Smooth shapes interpolate their normal from where on the surface the
+    // hit was, everything else ignores the u/v coordinates.
+    fn local_normal_at_uv(&self, local_point: T4, _u: f64, _v: f64) -> T4 {
+        self.local_normal_at(local_point)
+    }
+
     fn material(&self) -> Material;
-    fn set_material(&mut self, material: Material) -> &mut Self;
+    fn set_material(&mut self, material: Material) -> &mut Self where Self: Sized;
 
+    // The whole transform from object to world space, with the transforms
+    // of any groups the shape is in applied
     fn transform(&self) -> M4;
-    fn set_transform(&mut self, transform: M4) -> &mut Self;
+    // The shape's own transform, within its group
+    fn set_transform(&mut self, transform: M4) -> &mut Self where Self: Sized;
+
+    // `set_transform` for transforms that come from outside the program.
+    // Singular transforms are an error and leave the shape unchanged.
+    fn try_set_transform(&mut self, transform: M4) -> Result<&mut Self, TransformError> where Self: Sized {
+        Transform::try_new(transform)?;
+        return Ok(self.set_transform(transform));
+    }
+
+    // The transform of the groups the shape is in, all the way to world
+    // space. This is how groups pass their own transform down to their
+    // children. Shapes keep it apart from their own transform and compose
+    // the two, so a group can change its transform any number of times
+    // without round-off building up in its children.
+    fn set_parent_transform(&mut self, parent: M4);
+
+    // The world space bounding box of the shape
+    fn bounds(&self) -> Bounds {
+        Bounds::INFINITE
+    }
+
+    // Groups hand out their children, every other shape has none
+    fn children(&self) -> &[Box<dyn Shape>] {
+        &[]
+    }
+
+    // Whether the shape, or for a group any of its children, gives off light
+    fn is_emissive(&self) -> bool {
+        self.material().emission != Color::BLACK
+    }
+
+    // A random point on the surface, so that emissive shapes can be used as
+    // area lights. Shapes that return None still glow where they are seen
+    // but don't light anything else.
+    fn sample_surface(&self, _time: f64, _rng: &mut Rng) -> Option<SurfaceSample> {
+        None
+    }
+
+    // The transform at a moment in the shutter interval, given as the
+    // fraction of the exposure gone by: 0.0 is the start of the motion when
+    // the shutter opens and 1.0 the end when it closes. Shapes that don't move always
+    // use their static transform.
+    fn transform_at(&self, _time: f64) -> M4 {
+        self.transform()
+    }
+
+    // The inverse and inverse transpose of `transform_at`. Shapes that keep
+    // their transform as a `Transform` return the cached matrices instead of
+    // inverting for every ray.
+    fn inverse_transform_at(&self, time: f64) -> M4 {
+        self.transform_at(time).inverse()
+    }
+    fn normal_transform_at(&self, time: f64) -> M4 {
+        self.inverse_transform_at(time).transpose()
+    }
 
     fn intersect<'a>(&'a self, ray: Ray, xs: &mut Intersections<'a>) {
-        let local_ray = self.transform().inverse() * ray;
+        let local_ray = self.inverse_transform_at(ray.time) * ray;
         self.local_intersect(local_ray, xs);
     }
     fn normal_at(&self, point: T4) -> T4 {
-        let tf_inv = self.transform().inverse();
-        let local_point = tf_inv * point;
-        let local_normal = self.local_normal_at(local_point);
+        self.normal_at_uv(point, 0.0, 0.0, 0.0)
+    }
+    fn normal_at_uv(&self, point: T4, time: f64, u: f64, v: f64) -> T4 {
+        let local_point = self.inverse_transform_at(time) * point;
+        let n = self.local_normal_at_uv(local_point, u, v);
+
+        // Normals go back to world space with the inverse transpose. A
+        // Normal3 only picks up its 3x3 part, so no translation leaks into w.
+        let local_normal = Normal3::new(n.x, n.y, n.z);
+        return local_normal.transform_by(self.normal_transform_at(time)).into();
+    }
+}
+
+// A point on a shape's surface in world space, picked at random
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SurfaceSample {
+    pub point: T4,
+    pub normal: T4,
+    // Probability density per unit of world space area
+    pub pdf: f64,
+}
 
-        // Technically we should find the world normal by finding
-        // the transpose inverse of a 3x3 matrix, but instead we
-        // use the 4x4 and then manually set w to 0.
-        let mut world_normal = tf_inv.transpose() * local_normal;
-        world_normal.w = 0.0;
-        return world_normal.normalize();
+// Intersections refer to shapes by reference, and two references are the
+// same shape only if they point at the same object.
+impl<'a> PartialEq for dyn Shape + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Debug)]
 pub struct TestShape {
-    pub transform: M4,
     pub material: Material,
-    pub saved_ray: Cell<Ray>,
+    pub saved_ray: Mutex<Ray>,
+    local: M4,
+    parent: M4,
+    transform: Transform,
 }
 
 impl Default for TestShape {
     fn default() -> Self {
         TestShape {
-            transform: M4::IDENTITY,
+            local: M4::IDENTITY,
+            parent: M4::IDENTITY,
+            transform: Transform::IDENTITY,
             material: Material::default(),
-            saved_ray: Cell::new(Ray::new(T4::ZERO, T4::ZERO)),
+            saved_ray: Mutex::new(Ray::new(T4::ZERO, T4::ZERO)),
         }
     }
 }
 
 impl Shape for TestShape {
     fn local_intersect<'a>(&self, local_ray: Ray, _xs: &mut Intersections<'a>) {
-        self.saved_ray.set(local_ray);
+        *self.saved_ray.lock().unwrap() = local_ray;
     }
     fn local_normal_at(&self, local_point: T4) -> T4 {
         vector(local_point.x, local_point.y, local_point.z)
@@ -61,11 +147,19 @@ impl Shape for TestShape {
         return self;
     }
 
-    fn transform(&self) -> M4 { self.transform }
+    fn transform(&self) -> M4 { self.transform.matrix() }
     fn set_transform(&mut self, transform: M4) -> &mut Self {
-        self.transform = transform;
+        self.transform = Transform::new(self.parent * transform);
+        self.local = transform;
         return self;
     }
+    fn set_parent_transform(&mut self, parent: M4) {
+        self.transform = Transform::new(parent * self.local);
+        self.parent = parent;
+    }
+
+    fn inverse_transform_at(&self, _time: f64) -> M4 { self.transform.inverse() }
+    fn normal_transform_at(&self, _time: f64) -> M4 { self.transform.inverse_transpose() }
 }
 
 #[cfg(test)]
@@ -85,6 +179,15 @@ mod test {
         assert_eq!(TestShape::default().set_transform(t).transform(), t);
     }
 
+    #[test]
+    fn assigning_singular_transformation() {
+        let mut s = TestShape::default();
+        s.set_transform(translation(1.0, 0.0, 0.0));
+        let flat = scaling(1.0, 0.0, 1.0);
+        assert_eq!(s.try_set_transform(flat).err(), Some(TransformError::NotInvertible));
+        assert_eq!(s.transform(), translation(1.0, 0.0, 0.0));
+    }
+
     #[test]
     fn default_material() {
         assert_eq!(TestShape::default().material(), Material::default());
@@ -105,8 +208,8 @@ mod test {
         s.set_transform(scaling(2.0, 2.0, 2.0)); 
         s.intersect(Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)), &mut Intersections::empty());
 
-        assert_eq!(s.saved_ray.get().origin, point(0.0, 0.0, -2.5));
-        assert_eq!(s.saved_ray.get().direction, vector(0.0, 0.0, 0.5));
+        assert_eq!(s.saved_ray.lock().unwrap().origin, point(0.0, 0.0, -2.5));
+        assert_eq!(s.saved_ray.lock().unwrap().direction, vector(0.0, 0.0, 0.5));
     }
 
     #[test]
@@ -115,15 +218,15 @@ mod test {
         s.set_transform(translation(5.0, 0.0, 0.0)); 
         s.intersect(Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)), &mut Intersections::empty());
 
-        assert_eq!(s.saved_ray.get().origin, point(-5.0, 0.0, -5.0));
-        assert_eq!(s.saved_ray.get().direction, vector(0.0, 0.0, 1.0));
+        assert_eq!(s.saved_ray.lock().unwrap().origin, point(-5.0, 0.0, -5.0));
+        assert_eq!(s.saved_ray.lock().unwrap().direction, vector(0.0, 0.0, 1.0));
     }
 
     #[test]
     fn computing_normal_on_translated_shape() {
         let mut s = TestShape::default();
         s.set_transform(translation(0.0, 1.0, 0.0));
-        assert_eq!(s.normal_at(point(0.0, 1.70711, -0.70711)), vector(0.0, 0.70711, -0.70711));
+        assert_eq!(s.normal_at(point(0.0, 1.0 + S2O2, -S2O2)), vector(0.0, S2O2, -S2O2));
     }
 
     #[test]
@@ -133,47 +236,3 @@ mod test {
         assert_eq!(s.normal_at(point(0.0, S2O2, -S2O2)), vector(0.0, 0.97014, -0.24254));
     }
 }
-
-/*
-Feature: Abstract Shapes
-
-Scenario: A shape has a parent attribute
-  Given s â† test_shape()
-  Then s.parent is nothing
-
-Scenario: Converting a point from world to object space
-  Given g1 â† group()
-    And set_transform(g1, rotation_y(Ï€/2))
-    And g2 â† group()
-    And set_transform(g2, scaling(2, 2, 2))
-    And add_child(g1, g2)
-    And s â† sphere()
-    And set_transform(s, translation(5, 0, 0))
-    And add_child(g2, s)
-  When p â† world_to_object(s, point(-2, 0, -10))
-  Then p = point(0, 0, -1)
-
-Scenario: Converting a normal from object to world space
-  Given g1 â† group()
-    And set_transform(g1, rotation_y(Ï€/2))
-    And g2 â† group()
-    And set_transform(g2, scaling(1, 2, 3))
-    And add_child(g1, g2)
-    And s â† sphere()
-    And set_transform(s, translation(5, 0, 0))
-    And add_child(g2, s)
-  When n â† normal_to_world(s, vector(âˆš3/3, âˆš3/3, âˆš3/3))
-  Then n = vector(0.2857, 0.4286, -0.8571)
-
-Scenario: Finding the normal on a child object
-  Given g1 â† group()
-    And set_transform(g1, rotation_y(Ï€/2))
-    And g2 â† group()
-    And set_transform(g2, scaling(1, 2, 3))
-    And add_child(g1, g2)
-    And s â† sphere()
-    And set_transform(s, translation(5, 0, 0))
-    And add_child(g2, s)
-  When n â† normal_at(s, point(1.7321, 1.1547, -5.5774))
-  Then n = vector(0.2857, 0.4286, -0.8571)
-*/
\ No newline at end of file
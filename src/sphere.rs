@@ -1,25 +1,67 @@
 use crate::prelude::*;
+use std::f64::consts::PI;
 
 #[derive(Clone, Debug, Copy, PartialEq)]
 pub struct Sphere {
-    pub transform: M4,
     pub material: Material,
+    // The sphere's own transforms and that of its groups
+    local: M4,
+    end_local: Option<M4>,
+    parent: M4,
+    // `parent * local`. When set the sphere moves from `transform` to
+    // `end_transform` over the shutter interval, producing motion blur.
+    transform: Transform,
+    end_transform: Option<Transform>,
 }
 
 impl Default for Sphere {
     fn default() -> Self {
-        Sphere { transform: M4::IDENTITY,
-                 material: Material::default() }
+        Self::new(M4::IDENTITY, Material::default())
     }
 }
 
 impl Sphere {
     pub fn new(transform: M4, material: Material) -> Self {
-        Self { transform, material }
+        Self {
+            material,
+            local: transform,
+            end_local: None,
+            parent: M4::IDENTITY,
+            transform: Transform::new(transform),
+            end_transform: None,
+        }
     }
 
     pub fn from_transform(transform: M4) -> Self {
-        Self { transform, ..Self::default() }
+        Self::new(transform, Material::default())
+    }
+
+    pub fn from_material(material: Material) -> Self {
+        Self::new(M4::IDENTITY, material)
+    }
+
+    pub fn end_transform(&self) -> Option<M4> {
+        self.end_transform.map(|end| end.matrix())
+    }
+
+    pub fn set_end_transform(&mut self, transform: M4) -> &mut Self {
+        match self.try_set_end_transform(transform) {
+            Ok(sphere) => sphere,
+            Err(e) => panic!("{}: {:?}", e, transform),
+        }
+    }
+
+    // Like `try_set_transform`: a singular end transform is an error and
+    // leaves the sphere unchanged. So is one that mirrors the start, as the
+    // determinant would have to pass through zero on the way.
+    pub fn try_set_end_transform(&mut self, transform: M4) -> Result<&mut Self, TransformError> {
+        let end = Transform::try_new(self.parent * transform)?;
+        if end.matrix().determinant() * self.transform.matrix().determinant() < 0.0 {
+            return Err(TransformError::FlipsHandedness);
+        }
+        self.end_transform = Some(end);
+        self.end_local = Some(transform);
+        return Ok(self);
     }
 }
 
@@ -53,11 +95,69 @@ impl Shape for Sphere {
         return self;
     }
 
-    fn transform(&self) -> M4 { self.transform }
+    fn transform(&self) -> M4 { self.transform.matrix() }
     fn set_transform(&mut self, transform: M4) -> &mut Self {
-        self.transform = transform;
+        self.transform = Transform::new(self.parent * transform);
+        self.local = transform;
         return self;
     }
+
+    fn set_parent_transform(&mut self, parent: M4) {
+        self.transform = Transform::new(parent * self.local);
+        self.end_transform = self.end_local.map(|end| Transform::new(parent * end));
+        self.parent = parent;
+    }
+
+    fn bounds(&self) -> Bounds {
+        let unit = Bounds::new(point(-1.0, -1.0, -1.0), point(1.0, 1.0, 1.0));
+        let start = unit.transform(self.transform.matrix());
+        match self.end_transform {
+            None => start,
+            Some(end) => start.union(unit.transform(end.matrix())),
+        }
+    }
+
+    fn transform_at(&self, time: f64) -> M4 {
+        match self.end_transform {
+            None => self.transform.matrix(),
+            Some(end) => crate::quaternion::interpolate(self.transform.matrix(), end.matrix(), time.clamp(0.0, 1.0)),
+        }
+    }
+
+    // Uniform on the unit sphere, then transformed. A non-uniform scaling
+    // stretches some of the surface more than the rest: an area element
+    // around normal n grows by |det M| * |M^-T n| (Nanson's formula), and
+    // the density shrinks by the same factor.
+    fn sample_surface(&self, time: f64, rng: &mut Rng) -> Option<SurfaceSample> {
+        let z = 1.0 - 2.0 * rng.next_f64();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.next_f64();
+        let (x, y) = (r * phi.cos(), r * phi.sin());
+
+        let m = self.transform_at(time);
+        let normal = self.normal_transform_at(time) * Vector3::new(x, y, z);
+        let stretch = m.determinant().abs() * normal.mag();
+        return Some(SurfaceSample {
+            point: m * point(x, y, z),
+            normal: normal.normalize().into(),
+            pdf: 1.0 / (4.0 * PI * stretch),
+        });
+    }
+
+    // A moving sphere has a different transform at every moment, so only a
+    // static one can use the cached inverse
+    fn inverse_transform_at(&self, time: f64) -> M4 {
+        match self.end_transform {
+            None => self.transform.inverse(),
+            Some(_) => self.transform_at(time).inverse(),
+        }
+    }
+    fn normal_transform_at(&self, time: f64) -> M4 {
+        match self.end_transform {
+            None => self.transform.inverse_transpose(),
+            Some(_) => self.inverse_transform_at(time).transpose(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -124,8 +224,8 @@ mod test {
         s.local_intersect(
             Ray::new(point(0.0, 0.0, 5.0), vector(0.0, 0.0, 1.0)), &mut xs);
         assert_eq!(xs.len(), 2);
-        assert_eq!(*xs[0].object, s);
-        assert_eq!(*xs[1].object, s);
+        assert_eq!(xs[0].object, &s as &dyn Shape);
+        assert_eq!(xs[1].object, &s as &dyn Shape);
     }
 
     #[test]
@@ -139,14 +239,98 @@ mod test {
         assert_eq!(n, vector(s3_o3, s3_o3, s3_o3));
         assert_eq!(n, n.normalize());
     }
-}
 
-/*
-Feature: Spheres
+    #[test]
+    fn moving_sphere_transform_at() {
+        let mut s = Sphere::default();
+        s.set_end_transform(translation(2.0, 0.0, 0.0));
+        assert_eq!(s.transform_at(0.0), M4::IDENTITY);
+        assert_eq!(s.transform_at(0.5), translation(1.0, 0.0, 0.0));
+        assert_eq!(s.transform_at(1.0), translation(2.0, 0.0, 0.0));
+        assert_eq!(s.transform_at(2.0), translation(2.0, 0.0, 0.0));
+    }
 
-Scenario: A helper for producing a sphere with a glassy material
-  Given s â† glass_sphere()
-  Then s.transform = identity_matrix
-    And s.material.transparency = 1.0
-    And s.material.refractive_index = 1.5
-*/
+    #[test]
+    fn moving_mirrored_sphere_intersects_midway() {
+        let mut s = Sphere::from_transform(scaling(-1.0, 1.0, 1.0));
+        s.set_end_transform(translation(0.0, 2.0, 0.0) * scaling(-1.0, 1.0, 1.0));
+        let mut xs = Intersections::empty();
+        s.intersect(Ray::with_time(point(0.0, 1.0, -5.0), vector(0.0, 0.0, 1.0), 0.5), &mut xs);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(xs[1].t, 6.0);
+
+        // A sphere turning into its mirror image would flatten on the way
+        let mut s = Sphere::default();
+        assert!(s.try_set_end_transform(scaling(-1.0, 1.0, 1.0)).is_err());
+        assert_eq!(s.end_transform(), None);
+    }
+
+    #[test]
+    fn rotating_sphere_stays_rigid() {
+        let mut s = Sphere::default();
+        s.set_end_transform(rotation_y(PI * 0.9) * scaling(2.0, 2.0, 2.0));
+        assert_eq!(s.transform_at(0.5), rotation_y(PI * 0.45) * scaling(1.5, 1.5, 1.5));
+    }
+
+    #[test]
+    fn moving_sphere_intersect_uses_ray_time() {
+        let mut s = Sphere::default();
+        s.set_end_transform(translation(0.0, 2.0, 0.0));
+        let mut xs = Intersections::empty();
+        s.intersect(Ray::with_time(point(0.0, 2.0, -5.0), vector(0.0, 0.0, 1.0), 0.0), &mut xs);
+        assert_eq!(xs.len(), 0);
+        s.intersect(Ray::with_time(point(0.0, 2.0, -5.0), vector(0.0, 0.0, 1.0), 1.0), &mut xs);
+        assert_eq!(xs.len(), 2);
+        assert_eq!(xs[0].t, 4.0);
+        assert_eq!(xs[1].t, 6.0);
+    }
+
+    #[test]
+    fn singular_end_transform_is_rejected() {
+        let mut s = Sphere::default();
+        s.set_end_transform(translation(1.0, 0.0, 0.0));
+        let flat = rotation_y(0.7) * rotation_x(0.3) * scaling(1.0, 0.0, 1.0) * rotation_z(0.2);
+        assert_eq!(s.try_set_end_transform(flat).err(), Some(TransformError::NotInvertible));
+        let mirror = scaling(-1.0, 1.0, 1.0);
+        assert_eq!(s.try_set_end_transform(mirror).err(), Some(TransformError::FlipsHandedness));
+        assert_eq!(s.end_transform(), Some(translation(1.0, 0.0, 0.0)));
+        assert!(s.try_set_end_transform(scaling(2.0, 2.0, 2.0)).is_ok());
+        assert_eq!(s.end_transform(), Some(scaling(2.0, 2.0, 2.0)));
+    }
+
+    #[test]
+    fn changing_transform_updates_cached_inverse() {
+        let mut s = Sphere::default();
+        s.set_transform(scaling(2.0, 2.0, 2.0));
+        assert_eq!(s.inverse_transform_at(0.0), scaling(0.5, 0.5, 0.5));
+        s.set_parent_transform(translation(0.0, 0.0, 5.0));
+        let m = translation(0.0, 0.0, 5.0) * scaling(2.0, 2.0, 2.0);
+        assert_eq!(s.inverse_transform_at(0.0), m.inverse());
+        assert_eq!(s.normal_transform_at(0.0), m.inverse().transpose());
+
+        let mut xs = Intersections::empty();
+        s.intersect(Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0)), &mut xs);
+        assert_eq!(xs[0].t, 8.0);
+        assert_eq!(xs[1].t, 12.0);
+    }
+
+    // On average 1 / pdf is the surface area, here of a prolate spheroid
+    #[test]
+    fn sampling_stretched_sphere() {
+        let s = Sphere::from_transform(translation(0.0, 1.0, 0.0) * scaling(2.0, 1.0, 1.0));
+        let mut rng = Rng::new(5);
+        let n = 20000;
+        let mut total = 0.0;
+        for _ in 0..n {
+            let sample = s.sample_surface(0.0, &mut rng).unwrap();
+            let local = s.inverse_transform_at(0.0) * sample.point;
+            assert!(float_eq!((local - point(0.0, 0.0, 0.0)).mag(), 1.0));
+            assert_eq!(sample.normal, s.normal_at(sample.point));
+            total += 1.0 / sample.pdf;
+        }
+        let e = 3f64.sqrt() / 2.0;
+        let area = 2.0 * PI + 2.0 * PI * 2.0 * e.asin() / e;
+        assert!((total / n as f64 - area).abs() < 0.01 * area);
+    }
+}
@@ -0,0 +1,148 @@
+// Conversion of linear, unbounded render colours to display values in 0..=1.
+// Applied by the low dynamic range encoders on Canvas; the HDR formats always
+// store the raw colours.
+use crate::prelude::*;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ToneMap {
+    // Values above 1.0 are simply cut off
+    #[default]
+    Clamp,
+    // x / (1 + x)
+    Reinhard,
+    // Reinhard where `white` and above map to 1.0. A white point that isn't
+    // positive has nothing to stretch to and gives plain Reinhard.
+    ExtendedReinhard { white: f64 },
+    // Narkowicz's fit of the ACES filmic curve
+    AcesFilmic,
+    // 1 - e^(-exposure * x)
+    Exposure(f64),
+}
+
+impl ToneMap {
+    pub fn map(self, x: f64) -> f64 {
+        let x = x.max(0.0);
+        let y = match self {
+            ToneMap::Clamp => x,
+            ToneMap::ExtendedReinhard { white } if white > 0.0 => x * (1.0 + x / (white * white)) / (1.0 + x),
+            ToneMap::Reinhard | ToneMap::ExtendedReinhard { .. } => x / (1.0 + x),
+            ToneMap::AcesFilmic => {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                (x * (a * x + b)) / (x * (c * x + d) + e)
+            }
+            ToneMap::Exposure(exposure) => 1.0 - (-exposure * x).exp(),
+        };
+        y.clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Transfer {
+    // Write the tone mapped values as is
+    #[default]
+    Linear,
+    // The sRGB opto-electronic transfer function (roughly gamma 2.2)
+    Srgb,
+}
+
+impl Transfer {
+    pub fn encode(self, x: f64) -> f64 {
+        match self {
+            Transfer::Linear => x,
+            Transfer::Srgb => if x <= 0.0031308 {
+                12.92 * x
+            } else {
+                1.055 * x.powf(1.0 / 2.4) - 0.055
+            },
+        }
+    }
+}
+
+// How a canvas is turned into display values when exported. The default
+// reproduces the plain clamp used by the original PPM output.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Encoding {
+    pub tone_map: ToneMap,
+    pub transfer: Transfer,
+}
+
+impl Encoding {
+    pub fn new(tone_map: ToneMap, transfer: Transfer) -> Self {
+        Self { tone_map, transfer }
+    }
+
+    // Tone mapping with sRGB output, suitable for viewing on a monitor
+    pub fn display(tone_map: ToneMap) -> Self {
+        Self::new(tone_map, Transfer::Srgb)
+    }
+
+    pub fn apply(self, c: Color) -> Color {
+        let f = |x: f64| self.transfer.encode(self.tone_map.map(x));
+        color(f(c.red), f(c.green), f(c.blue))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clamp_cuts_off() {
+        assert_eq!(ToneMap::Clamp.map(1.5), 1.0);
+        assert_eq!(ToneMap::Clamp.map(-0.5), 0.0);
+        assert_eq!(ToneMap::Clamp.map(0.25), 0.25);
+    }
+
+    #[test]
+    fn reinhard() {
+        assert!(float_eq!(ToneMap::Reinhard.map(1.0), 0.5));
+        assert!(float_eq!(ToneMap::Reinhard.map(3.0), 0.75));
+    }
+
+    #[test]
+    fn extended_reinhard_white_point_maps_to_one() {
+        let op = ToneMap::ExtendedReinhard { white: 4.0 };
+        assert!(float_eq!(op.map(4.0), 1.0));
+        assert!(op.map(1.0) > ToneMap::Reinhard.map(1.0));
+    }
+
+    #[test]
+    fn extended_reinhard_without_white_point_is_reinhard() {
+        for &white in [0.0, -2.0, f64::NAN].iter() {
+            let op = ToneMap::ExtendedReinhard { white };
+            for &x in [0.0, 0.5, 1.0, 10.0].iter() {
+                assert_eq!(op.map(x), ToneMap::Reinhard.map(x));
+            }
+        }
+    }
+
+    #[test]
+    fn aces_is_monotonic_and_bounded() {
+        let mut last = 0.0;
+        for i in 1..100 {
+            let y = ToneMap::AcesFilmic.map(i as f64 * 0.2);
+            assert!(y >= last && y <= 1.0);
+            last = y;
+        }
+    }
+
+    #[test]
+    fn exposure() {
+        assert!(float_eq!(ToneMap::Exposure(1.0).map(1.0), 1.0 - (-1f64).exp()));
+        assert_eq!(ToneMap::Exposure(2.0).map(0.0), 0.0);
+    }
+
+    #[test]
+    fn srgb_transfer() {
+        assert_eq!(Transfer::Srgb.encode(0.0), 0.0);
+        assert!(float_eq!(Transfer::Srgb.encode(1.0), 1.0));
+        assert!(float_eq!(Transfer::Srgb.encode(0.001), 0.01292));
+        assert!((Transfer::Srgb.encode(0.5) - 0.73536).abs() < 0.0001);
+    }
+
+    #[test]
+    fn default_encoding_is_clamp() {
+        let c = color(1.5, 0.5, -0.5);
+        assert_eq!(Encoding::default().apply(c), color(1.0, 0.5, 0.0));
+    }
+}
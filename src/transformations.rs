@@ -1,4 +1,5 @@
 use crate::prelude::*;
+use std::fmt;
 
 pub fn translation(x: f64, y: f64, z: f64) -> M4 {
     matrix4(1.0, 0.0, 0.0, x,
@@ -45,6 +46,65 @@ pub fn shearing(xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> M4 {
             0.0, 0.0, 0.0, 1.0,)
 }
 
+// A rotation of `theta` radians about an axis through the origin. Looking
+// down the axis towards the origin the rotation is counterclockwise, as for
+// `rotation_x` and friends.
+pub fn rotation(axis: Vector3, theta: f64) -> M4 {
+    Quat::from_axis_angle(axis, theta).to_m4()
+}
+
+// A rotation about an axis through `pivot` instead of the origin
+pub fn rotation_about(pivot: Point3, axis: Vector3, theta: f64) -> M4 {
+    translation(pivot.x, pivot.y, pivot.z)
+        * rotation(axis, theta)
+        * translation(-pivot.x, -pivot.y, -pivot.z)
+}
+
+// Chaining transforms in reading order. Each step is applied after the ones
+// before it, so
+//
+//   M4::IDENTITY.scale(0.5, 0.5, 0.5).rotate_y(PI / 4.0).translate(0.0, 1.0, 0.0)
+//
+// is `translation(0.0, 1.0, 0.0) * rotation_y(PI / 4.0) * scaling(0.5, 0.5, 0.5)`.
+impl M4 {
+    pub fn translate(self, x: f64, y: f64, z: f64) -> Self {
+        translation(x, y, z) * self
+    }
+
+    pub fn scale(self, x: f64, y: f64, z: f64) -> Self {
+        scaling(x, y, z) * self
+    }
+
+    pub fn rotate_x(self, theta: f64) -> Self {
+        rotation_x(theta) * self
+    }
+
+    pub fn rotate_y(self, theta: f64) -> Self {
+        rotation_y(theta) * self
+    }
+
+    pub fn rotate_z(self, theta: f64) -> Self {
+        rotation_z(theta) * self
+    }
+
+    pub fn rotate(self, axis: Vector3, theta: f64) -> Self {
+        rotation(axis, theta) * self
+    }
+
+    pub fn rotate_about(self, pivot: Point3, axis: Vector3, theta: f64) -> Self {
+        rotation_about(pivot, axis, theta) * self
+    }
+
+    pub fn shear(self, xy: f64, xz: f64, yx: f64, yz: f64, zx: f64, zy: f64) -> Self {
+        shearing(xy, xz, yx, yz, zx, zy) * self
+    }
+
+    // Any other transform, e.g. a defined one
+    pub fn then(self, transform: M4) -> Self {
+        transform * self
+    }
+}
+
 pub fn view_transform(from: T4, to: T4, up: T4) -> M4 {
     let forward = (to - from).normalize();
     let left = forward.cross(up.normalize());
@@ -59,11 +119,87 @@ pub fn view_transform(from: T4, to: T4, up: T4) -> M4 {
     return orientation * translation(-from.x, -from.y, -from.z);
 }
 
+// A transform stored with its inverse and inverse transpose. Rays are taken
+// to object space with the inverse and normals back to world space with the
+// inverse transpose, so computing both once when the transform is set saves
+// a matrix inversion for every ray.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: M4,
+    inverse: M4,
+    inverse_transpose: M4,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        matrix: M4::IDENTITY,
+        inverse: M4::IDENTITY,
+        inverse_transpose: M4::IDENTITY,
+    };
+
+    // Panics with the `TransformError` message for transforms that can't be
+    // used, see `try_new`.
+    pub fn new(matrix: M4) -> Self {
+        match Self::try_new(matrix) {
+            Ok(transform) => transform,
+            Err(e) => panic!("{}: {:?}", e, matrix),
+        }
+    }
+
+    // Rendering with a singular transform would take every ray to NaNs, so
+    // such transforms are rejected up front.
+    pub fn try_new(matrix: M4) -> Result<Self, TransformError> {
+        if !matrix.is_finite() {
+            return Err(TransformError::NotFinite);
+        }
+        let inverse = match matrix.try_inverse() {
+            Some(inverse) => inverse,
+            None => return Err(TransformError::NotInvertible),
+        };
+        return Ok(Self { matrix, inverse, inverse_transpose: inverse.transpose() });
+    }
+
+    pub fn matrix(&self) -> M4 { self.matrix }
+    pub fn inverse(&self) -> M4 { self.inverse }
+    pub fn inverse_transpose(&self) -> M4 { self.inverse_transpose }
+}
+
+impl Default for Transform {
+    fn default() -> Self { Self::IDENTITY }
+}
+
+impl From<M4> for Transform {
+    fn from(matrix: M4) -> Self { Self::new(matrix) }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TransformError {
+    // E.g. a scaling by zero along some axis, which flattens the object
+    NotInvertible,
+    // E.g. a view transform looking from a point to itself
+    NotFinite,
+    // A motion from a transform to its mirror image, which has to flatten
+    // the object somewhere along the way
+    FlipsHandedness,
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::NotInvertible => write!(f, "transform is not invertible (a scaling by zero?)"),
+            TransformError::NotFinite => write!(f, "transform contains NaN or infinite values"),
+            TransformError::FlipsHandedness => write!(f, "end transform mirrors the start transform"),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::test_prelude::*;
-    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4};
+    use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4, PI};
 
     //macro_rules! assert_approx_eq {
     //    ($x:expr, $y:expr)  => (assert!(approx_eq!(f64, $x, $y, epsilon = 0.00001)))
@@ -191,38 +327,52 @@ mod test {
                        |  0.00000 | 0.00000 |  0.00000 |  1.00000 |
                    "));
     }
+
+    #[test]
+    fn transform_caches_inverse() {
+        let m = translation(1.0, 2.0, 3.0) * rotation_x(FRAC_PI_4) * scaling(2.0, 2.0, 2.0);
+        let t = Transform::new(m);
+        assert_eq!(t.matrix(), m);
+        assert_eq!(t.inverse(), m.inverse());
+        assert_eq!(t.inverse_transpose(), m.inverse().transpose());
+        assert_eq!(Transform::default(), Transform::new(M4::IDENTITY));
+    }
+
+    #[test]
+    fn singular_transforms_are_rejected() {
+        let flat = scaling(1.0, 0.0, 1.0);
+        assert_eq!(Transform::try_new(flat), Err(TransformError::NotInvertible));
+
+        let nowhere = view_transform(point(0.0, 0.0, 0.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0));
+        assert_eq!(Transform::try_new(nowhere), Err(TransformError::NotFinite));
+    }
+
+    #[test]
+    fn fluent_transforms_apply_in_reading_order() {
+        let p = point(1.0, 0.0, 1.0);
+        let t = M4::IDENTITY.rotate_x(FRAC_PI_2).scale(5.0, 5.0, 5.0).translate(10.0, 5.0, 7.0);
+        assert_eq!(t, translation(10.0, 5.0, 7.0) * scaling(5.0, 5.0, 5.0) * rotation_x(FRAC_PI_2));
+        assert_eq!(t * p, point(15.0, 0.0, 7.0));
+        assert_eq!(M4::IDENTITY.shear(1.0, 0.0, 0.0, 0.0, 0.0, 0.0).then(translation(1.0, 0.0, 0.0)),
+                   translation(1.0, 0.0, 0.0) * shearing(1.0, 0.0, 0.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn rotation_about_arbitrary_axis() {
+        assert_eq!(rotation(Vector3::new(2.0, 0.0, 0.0), FRAC_PI_4), rotation_x(FRAC_PI_4));
+        // A third of a turn about the diagonal cycles the axes
+        let diagonal = Vector3::new(1.0, 1.0, 1.0);
+        assert_eq!(rotation(diagonal, 2.0 * PI / 3.0) * point(1.0, 0.0, 0.0), point(0.0, 1.0, 0.0));
+        assert_eq!(M4::IDENTITY.rotate(diagonal, 2.0 * PI / 3.0) * vector(0.0, 1.0, 0.0), vector(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn rotation_about_pivot() {
+        let pivot = Point3::new(1.0, 2.0, 0.0);
+        let r = rotation_about(pivot, Vector3::EZ, FRAC_PI_2);
+        assert_eq!(r * Point3::new(1.0, 2.0, 0.0), pivot);
+        assert_eq!(r * Point3::new(2.0, 2.0, 0.0), Point3::new(1.0, 3.0, 0.0));
+        assert_eq!(M4::IDENTITY.translate(1.0, 0.0, 0.0).rotate_about(pivot, Vector3::EZ, FRAC_PI_2) * point(1.0, 2.0, 0.0),
+                   point(1.0, 3.0, 0.0));
+    }
 }
-/*
-TODO
-Scenario: The transformation matrix for the default orientation
-  Given from â† point(0, 0, 0)
-    And to â† point(0, 0, -1)
-    And up â† vector(0, 1, 0)
-  When t â† view_transform(from, to, up)
-  Then t = identity_matrix
-
-Scenario: A view transformation matrix looking in positive z direction
-  Given from â† point(0, 0, 0)
-    And to â† point(0, 0, 1)
-    And up â† vector(0, 1, 0)
-  When t â† view_transform(from, to, up)
-  Then t = scaling(-1, 1, -1)
-
-Scenario: The view transformation moves the world
-  Given from â† point(0, 0, 8)
-    And to â† point(0, 0, 0)
-    And up â† vector(0, 1, 0)
-  When t â† view_transform(from, to, up)
-  Then t = translation(0, 0, -8)
-
-Scenario: An arbitrary view transformation
-  Given from â† point(1, 3, 2)
-    And to â† point(4, -2, 8)
-    And up â† vector(1, 1, 0)
-  When t â† view_transform(from, to, up)
-  Then t is the following 4x4 matrix:
-      | -0.50709 | 0.50709 |  0.67612 | -2.36643 |
-      |  0.76772 | 0.60609 |  0.12122 | -2.82843 |
-      | -0.35857 | 0.59761 | -0.71714 |  0.00000 |
-      |  0.00000 | 0.00000 |  0.00000 |  1.00000 |
-*/
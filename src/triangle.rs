@@ -0,0 +1,218 @@
+use crate::prelude::*;
+
+#[derive(Clone, Debug, Copy, PartialEq)]
+pub struct Triangle {
+    pub p1: T4,
+    pub p2: T4,
+    pub p3: T4,
+    // Per vertex normals make a smooth triangle, interpolated across the face
+    pub normals: Option<[T4; 3]>,
+    // Texture coordinates for each vertex, if the model supplied them
+    pub texcoords: Option<[(f64, f64); 3]>,
+    pub material: Material,
+
+    // The triangle's own transform and that of its groups
+    local: M4,
+    parent: M4,
+
+    // Cached calculations
+    transform: Transform,
+    e1: T4,
+    e2: T4,
+    normal: T4,
+}
+
+impl Triangle {
+    pub fn new(p1: T4, p2: T4, p3: T4) -> Self {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        let normal = e2.cross(e1).normalize();
+        Self {
+            p1, p2, p3,
+            normals: None,
+            texcoords: None,
+            local: M4::IDENTITY,
+            parent: M4::IDENTITY,
+            transform: Transform::IDENTITY,
+            material: Material::default(),
+            e1, e2, normal,
+        }
+    }
+
+    pub fn smooth(p1: T4, p2: T4, p3: T4, n1: T4, n2: T4, n3: T4) -> Self {
+        Self { normals: Some([n1, n2, n3]), ..Self::new(p1, p2, p3) }
+    }
+
+    pub fn e1(&self) -> T4 { self.e1 }
+    pub fn e2(&self) -> T4 { self.e2 }
+}
+
+impl Shape for Triangle {
+    // Möller–Trumbore
+    fn local_intersect<'a>(&'a self, ray: Ray, xs: &mut Intersections<'a>) {
+        let dir_cross_e2 = ray.direction.cross(self.e2);
+        let det = self.e1 * dir_cross_e2;
+        if det.abs() < crate::consts::EPSILON { return; }
+
+        let f = 1.0 / det;
+        let p1_to_origin = ray.origin - self.p1;
+        let u = f * (p1_to_origin * dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) { return; }
+
+        let origin_cross_e1 = p1_to_origin.cross(self.e1);
+        let v = f * (ray.direction * origin_cross_e1);
+        if v < 0.0 || u + v > 1.0 { return; }
+
+        let t = f * (self.e2 * origin_cross_e1);
+        xs.extend(std::iter::once(Intersection::with_uv(t, self, u, v)));
+    }
+
+    fn local_normal_at(&self, _local_point: T4) -> T4 {
+        self.normal
+    }
+
+    fn local_normal_at_uv(&self, _local_point: T4, u: f64, v: f64) -> T4 {
+        match self.normals {
+            None => self.normal,
+            Some([n1, n2, n3]) => n2 * u + n3 * v + n1 * (1.0 - u - v),
+        }
+    }
+
+    fn material(&self) -> Material { self.material }
+    fn set_material(&mut self, material: Material) -> &mut Self {
+        self.material = material;
+        return self;
+    }
+
+    fn transform(&self) -> M4 { self.transform.matrix() }
+    fn set_transform(&mut self, transform: M4) -> &mut Self {
+        self.transform = Transform::new(self.parent * transform);
+        self.local = transform;
+        return self;
+    }
+    fn set_parent_transform(&mut self, parent: M4) {
+        self.transform = Transform::new(parent * self.local);
+        self.parent = parent;
+    }
+
+    fn inverse_transform_at(&self, _time: f64) -> M4 { self.transform.inverse() }
+    fn normal_transform_at(&self, _time: f64) -> M4 { self.transform.inverse_transpose() }
+
+    fn bounds(&self) -> Bounds {
+        let m = self.transform.matrix();
+        Bounds::from_points([self.p1, self.p2, self.p3].iter().map(|&p| m * p))
+    }
+
+    // Uniform over the area
+    fn sample_surface(&self, _time: f64, rng: &mut Rng) -> Option<SurfaceSample> {
+        let m = self.transform.matrix();
+        let (p1, p2, p3) = (m * self.p1, m * self.p2, m * self.p3);
+        let cross = (p3 - p1).cross(p2 - p1);
+        let area = cross.mag() / 2.0;
+        if area == 0.0 { return None; }
+
+        // Points past the diagonal of the unit square fold back into the
+        // triangle
+        let (mut u, mut v) = (rng.next_f64(), rng.next_f64());
+        if u + v > 1.0 {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        return Some(SurfaceSample {
+            point: p1 + (p2 - p1) * u + (p3 - p1) * v,
+            normal: cross.normalize(),
+            pdf: 1.0 / area,
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn default_triangle() -> Triangle {
+        Triangle::new(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0))
+    }
+
+    fn default_smooth_triangle() -> Triangle {
+        Triangle::smooth(point(0.0, 1.0, 0.0), point(-1.0, 0.0, 0.0), point(1.0, 0.0, 0.0),
+                         vector(0.0, 1.0, 0.0), vector(-1.0, 0.0, 0.0), vector(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn constructing_triangle() {
+        let t = default_triangle();
+        assert_eq!(t.e1(), vector(-1.0, -1.0, 0.0));
+        assert_eq!(t.e2(), vector(1.0, -1.0, 0.0));
+        assert_eq!(t.local_normal_at(point(0.0, 0.5, 0.0)), vector(0.0, 0.0, -1.0));
+        assert_eq!(t.local_normal_at(point(-0.5, 0.75, 0.0)), vector(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn ray_parallel_to_triangle() {
+        let t = default_triangle();
+        let mut xs = Intersections::empty();
+        t.local_intersect(
+            Ray::new(point(0.0, -1.0, -2.0), vector(0.0, 1.0, 0.0)), &mut xs);
+        assert!(xs.is_empty());
+    }
+
+    #[test]
+    fn ray_misses_edges() {
+        let t = default_triangle();
+        for origin in [point(1.0, 1.0, -2.0), point(-1.0, 1.0, -2.0), point(0.0, -1.0, -2.0)] {
+            let mut xs = Intersections::empty();
+            t.local_intersect(Ray::new(origin, vector(0.0, 0.0, 1.0)), &mut xs);
+            assert!(xs.is_empty());
+        }
+    }
+
+    #[test]
+    fn ray_strikes_triangle() {
+        let t = default_triangle();
+        let mut xs = Intersections::empty();
+        t.local_intersect(Ray::new(point(0.0, 0.5, -2.0), vector(0.0, 0.0, 1.0)), &mut xs);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+    }
+
+    #[test]
+    fn smooth_triangle_intersection_stores_uv() {
+        let t = default_smooth_triangle();
+        let mut xs = Intersections::empty();
+        t.local_intersect(Ray::new(point(-0.2, 0.3, -2.0), vector(0.0, 0.0, 1.0)), &mut xs);
+        assert!(float_eq!(xs[0].u, 0.45));
+        assert!(float_eq!(xs[0].v, 0.25));
+    }
+
+    #[test]
+    fn smooth_triangle_interpolates_normal() {
+        let t = default_smooth_triangle();
+        let n = t.normal_at_uv(point(0.0, 0.0, 0.0), 0.0, 0.45, 0.25);
+        assert_eq!(n, vector(-0.5547, 0.83205, 0.0));
+    }
+
+    #[test]
+    fn smooth_triangle_normal_in_computations() {
+        let t = default_smooth_triangle();
+        let i = Intersection::with_uv(1.0, &t, 0.45, 0.25);
+        let comps = i.prepare_computations(Ray::new(point(-0.2, 0.3, -2.0), vector(0.0, 0.0, 1.0)));
+        assert_eq!(comps.normalv, vector(-0.5547, 0.83205, 0.0));
+    }
+
+    #[test]
+    fn sampling_triangle() {
+        let mut t = default_triangle();
+        t.set_transform(scaling(2.0, 2.0, 2.0));
+        let mut rng = Rng::new(9);
+        for _ in 0..100 {
+            let s = t.sample_surface(0.0, &mut rng).unwrap();
+            assert!(float_eq!(s.pdf, 0.25));
+            assert_eq!(s.normal, vector(0.0, 0.0, -1.0));
+            // Inside: the ray through the sample towards the face hits it
+            let mut xs = Intersections::empty();
+            t.intersect(Ray::new(s.point - vector(0.0, 0.0, 1.0), vector(0.0, 0.0, 1.0)), &mut xs);
+            assert_eq!(xs.len(), 1);
+        }
+    }
+}
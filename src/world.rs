@@ -1,54 +1,77 @@
 use crate::prelude::*;
+use std::f64::consts::PI;
 
-#[derive(Clone, Default, Debug)]
+#[derive(Default, Debug)]
 pub struct World {
-    pub objects: Vec<Sphere>,
+    pub objects: Vec<Box<dyn Shape>>,
     pub lights: Vec<Light>,
+    // Darkens the ambient term of Whitted shading where little of the sky
+    // is visible
+    pub ambient_occlusion: Option<AmbientOcclusion>,
 }
 
 impl World {
-    pub fn new(objects: Vec<Sphere>, lights: Vec<Light>) -> Self {
-        Self { objects, lights }
+    pub fn new(objects: Vec<Box<dyn Shape>>, lights: Vec<Light>) -> Self {
+        Self { objects, lights, ambient_occlusion: None }
+    }
+
+    pub fn add_object<S: Shape + 'static>(&mut self, shape: S) -> &mut Self {
+        self.objects.push(Box::new(shape));
+        return self;
     }
 
     // A world with 1 light source and 2 concentric spheres centered at the
     // origin.
     pub fn simple() -> Self {
-        let objects = vec![
-            Sphere {
-                material: Material {
-                    color: color_rgb!(0.8, 1.0, 0.6),
-                    diffuse: 0.7,
-                    specular: 0.2,
-                    ..Material::default()
-                },
-                ..Sphere::default()
-            },
-            Sphere {
-                transform: scaling(0.5, 0.5, 0.5),
-                ..Sphere::default()
-            },
+        let objects: Vec<Box<dyn Shape>> = vec![
+            Box::new(Sphere::from_material(Material {
+                color: color_rgb!(0.8, 1.0, 0.6),
+                diffuse: 0.7,
+                specular: 0.2,
+                ..Material::default()
+            })),
+            Box::new(Sphere::from_transform(scaling(0.5, 0.5, 0.5))),
         ];
         let lights = vec![Light::new(point(-10.0, 10.0, -10.0), Color::WHITE)];
-        Self { objects, lights }
+        Self::new(objects, lights)
     }
 
-    fn intersect<'a>(&'a self, ray: Ray, xs: &mut Intersections<'a>) {
+    pub fn intersect<'a>(&'a self, ray: Ray, xs: &mut Intersections<'a>) {
         for obj in self.objects.iter() {
             obj.intersect(ray, xs);
         }
     }
 
-    // Given world and intersection computations calculate colour
-    fn shade_hit<'a>(&'a self, comps: Computations<'a>, xs: &mut Intersections<'a>) -> Color {
-        let shadow = self.is_shadowed(comps.over_point, xs);
-        comps.object.material.lighting(
-            self.lights[0],
-            comps.over_point,
-            comps.eyev,
-            comps.normalv,
-            shadow,
-        )
+    // Given world and intersection computations calculate colour. Emissive
+    // shapes light the hit through one random point on one of them, and
+    // ambient occlusion casts a few random rays, with the random numbers
+    // seeded from the hit so the image stays the same from one render to the
+    // next. Antialiasing samples average out the noise.
+    pub fn shade_hit<'a>(&'a self, comps: Computations<'a>, xs: &mut Intersections<'a>) -> Color {
+        let mut material = comps.object.material();
+        let mut color = material.emission;
+        let mut rng = Rng::for_point(comps.point);
+
+        if let Some(light) = self.sample_area_light(comps.over_point, comps.time, &mut rng, xs) {
+            color = color + material.direct_lighting(light, comps.over_point, comps.eyev, comps.normalv);
+        }
+
+        if let Some(ao) = self.ambient_occlusion {
+            material.ambient *= ao.visibility(self, comps.over_point, comps.normalv, comps.time, &mut rng, xs);
+        }
+
+        if let Some(&light) = self.lights.first() {
+            xs.clear();
+            let shadow = self.is_shadowed(comps.over_point, comps.time, xs);
+            color = color + material.lighting(
+                light,
+                comps.over_point,
+                comps.eyev,
+                comps.normalv,
+                shadow,
+            );
+        }
+        return color;
     }
 
     pub fn color_at<'a>(&'a self, ray: Ray, xs1: &mut Intersections<'a>, xs2: &mut Intersections<'a>) -> Color {
@@ -66,12 +89,72 @@ impl World {
         return self.shade_hit(comps, xs2);
     }
 
-    pub fn is_shadowed<'a>(&'a self, point: T4, xs: &mut Intersections<'a>) -> bool {
-      let v = self.lights[0].pos - point;
+    // Calls `f` with every emissive shape, including those inside groups
+    pub fn for_each_emitter<'a, F: FnMut(&'a dyn Shape)>(&'a self, mut f: F) {
+        fn visit<'a, F: FnMut(&'a dyn Shape)>(shape: &'a dyn Shape, f: &mut F) {
+            if !shape.is_emissive() { return; }
+            let children = shape.children();
+            if children.is_empty() {
+                f(shape);
+            }
+            for child in children.iter() {
+                visit(child.as_ref(), f);
+            }
+        }
+        for object in self.objects.iter() {
+            visit(object.as_ref(), &mut f);
+        }
+    }
+
+    // Light from the emissive shapes reaching `from`, estimated with one
+    // random point on one of them. The estimate comes back as a point light
+    // at that point, bright enough that its diffuse lighting (intensity *
+    // albedo * cos, see `Material::lighting`) has the right average. None if
+    // there are no emissive shapes or the point is in shadow.
+    pub fn sample_area_light<'a>(&'a self, from: T4, time: f64, rng: &mut Rng,
+                                 xs: &mut Intersections<'a>) -> Option<Light> {
+        // Pick one of the emitters uniformly in a single pass (reservoir
+        // sampling): the k-th replaces the choice so far with probability 1/k
+        let mut picked = None;
+        let mut count = 0;
+        self.for_each_emitter(|shape| {
+            count += 1;
+            if count == 1 || rng.next_f64() * (count as f64) < 1.0 {
+                picked = Some(shape);
+            }
+        });
+        let emitter = picked?;
+        let sample = emitter.sample_surface(time, rng)?;
+
+        let v = sample.point - from;
+        let distance = v.mag();
+        let direction = v / distance;
+        // Stop short of the sample so that the emitter doesn't shadow itself
+        let target = sample.point - direction * crate::consts::OVER_POINT_SHIFT_LENGTH;
+        xs.clear();
+        if distance == 0.0 || self.is_occluded(from, target, time, xs) {
+            return None;
+        }
+
+        // Emitters shine from both sides. The diffuse reflection of radiance
+        // L from an area A is albedo / π * L * cos * cos_light / distance² * A,
+        // and 1 / (pdf * count) stands in for A.
+        let cos_light = (direction * sample.normal).abs();
+        let scale = cos_light * count as f64 / (PI * distance * distance * sample.pdf);
+        return Some(Light::new(sample.point, emitter.material().emission * scale));
+    }
+
+    pub fn is_shadowed<'a>(&'a self, point: T4, time: f64, xs: &mut Intersections<'a>) -> bool {
+        self.is_occluded(point, self.lights[0].pos, time, xs)
+    }
+
+    // Whether anything lies on the segment between two points
+    pub fn is_occluded<'a>(&'a self, from: T4, to: T4, time: f64, xs: &mut Intersections<'a>) -> bool {
+      let v = to - from;
       let distance = v.mag();
       let direction = v.normalize();
 
-      let r = Ray::new(point, direction);
+      let r = Ray::with_time(from, direction, time);
       self.intersect(r, xs);
 
       let h = xs.hit();
@@ -100,14 +183,14 @@ mod test {
         let w = World::simple();
         assert_eq!(w.objects.len(), 2);
         assert_eq!(w.lights.len(), 1);
-        let s1 = w.objects[0];
-        let s2 = w.objects[1];
+        let s1 = &w.objects[0];
+        let s2 = &w.objects[1];
         let l = w.lights[0];
 
-        assert_eq!(s1.material.color, color_rgb!(0.8, 1.0, 0.6));
-        assert_eq!(s1.material.diffuse, 0.7);
-        assert_eq!(s1.material.specular, 0.2);
-        assert_eq!(s2.transform, scaling(0.5, 0.5, 0.5));
+        assert_eq!(s1.material().color, color_rgb!(0.8, 1.0, 0.6));
+        assert_eq!(s1.material().diffuse, 0.7);
+        assert_eq!(s1.material().specular, 0.2);
+        assert_eq!(s2.transform(), scaling(0.5, 0.5, 0.5));
         assert_eq!(l, Light::new(point(-10.0, 10.0, -10.0), color_rgb!(1.0, 1.0, 1.0)));
     }
 
@@ -129,8 +212,8 @@ mod test {
     fn shade_intersection() {
         let w = World::simple();
         let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
-        let shape = w.objects[0];
-        let i = Intersection::new(4.0, &shape);
+        let shape = w.objects[0].as_ref();
+        let i = Intersection::new(4.0, shape);
         let comps = i.prepare_computations(r);
         assert_eq!(w.shade_hit(comps, &mut Intersections::empty()), color_rgb!(0.38066, 0.47583, 0.2855));
     }
@@ -140,12 +223,10 @@ mod test {
         let mut w = World::simple();
         w.lights[0] = Light::new(point(0.0, 0.25, 0.0), color_rgb!(1.0, 1.0, 1.0));
         let r = Ray::new(point(0.0, 0.0, 0.0), vector(0.0, 0.0, 1.0));
-        let shape = w.objects[1];
-        let i = Intersection::new(0.5, &shape);
+        let shape = w.objects[1].as_ref();
+        let i = Intersection::new(0.5, shape);
         let comps = i.prepare_computations(r);
-        //assert_eq!(w.shade_hit(comps, &mut Intersections::empty()), color_rgb!(0.90498, 0.90498, 0.90498));
-        // Changed to 0.1 because shadows are now implemented
-        assert_eq!(w.shade_hit(comps, &mut Intersections::empty()), color_rgb!(0.1, 0.1, 0.1));
+        assert_eq!(w.shade_hit(comps, &mut Intersections::empty()), color_rgb!(0.90498, 0.90498, 0.90498));
     }
     
     #[test]
@@ -166,216 +247,116 @@ mod test {
 
     #[test]
     fn color_with_intersection_behind_ray() {
-        let mut w = World::simple();
-        for obj in w.objects.iter_mut() {
-            obj.material.ambient = 1.0;
+        let simple = World::simple();
+        let mut w = World::new(vec![], simple.lights.clone());
+        for obj in simple.objects.iter() {
+            let mut s = Sphere::from_transform(obj.transform());
+            s.set_material(Material { ambient: 1.0, ..obj.material() });
+            w.add_object(s);
         }
         let mut xs = Intersections::empty();
         let r = Ray::new(point(0.0, 0.0, 0.75), vector(0.0, 0.0, -1.0));
-        assert_eq!(w.color_at(r, &mut xs, &mut Intersections::empty()), w.objects[1].material.color);
+        assert_eq!(w.color_at(r, &mut xs, &mut Intersections::empty()), w.objects[1].material().color);
     }
 
     #[test]
     fn no_shadow_when_nothing_collinear_with_point_and_light() {
-        assert!(!World::simple().is_shadowed(point(0.0, 10.0, 0.0), &mut Intersections::empty()));
+        assert!(!World::simple().is_shadowed(point(0.0, 10.0, 0.0), 0.0, &mut Intersections::empty()));
     }
 
     #[test]
     fn shadow_when_object_between_point_and_light() {
-        assert!(!World::simple().is_shadowed(point(10.0, -10.0, 0.0), &mut Intersections::empty()));
+        assert!(!World::simple().is_shadowed(point(10.0, -10.0, 0.0), 0.0, &mut Intersections::empty()));
     }
 
     #[test]
     fn no_shadow_when_object_behind_light() {
-        assert!(!World::simple().is_shadowed(point(-20.0, 20.0, -20.0), &mut Intersections::empty()));
+        assert!(!World::simple().is_shadowed(point(-20.0, 20.0, -20.0), 0.0, &mut Intersections::empty()));
     }
 
     #[test]
     fn no_shadow_when_object_behind_point() {
-        assert!(!World::simple().is_shadowed(point(-2.0, 2.0, -2.0), &mut Intersections::empty()));
+        assert!(!World::simple().is_shadowed(point(-2.0, 2.0, -2.0), 0.0, &mut Intersections::empty()));
     }
 
     #[test]
     fn shade_hit_is_given_intersection_in_shadow() {
         let w = World::new(
             vec![
-                Sphere::default(),
-                Sphere {
-                    transform: translation(0.0, 0.0, 10.0),
-                    ..Sphere::default()
-                }
+                Box::new(Sphere::default()),
+                Box::new(Sphere::from_transform(translation(0.0, 0.0, 10.0)))
             ],
             vec![Light::new(point(0.0, 0.0, -10.0), color_rgb!(1.0, 1.0, 1.0))]
         );
         let r = Ray::new(point(0.0, 0.0, 5.0), vector(0.0, 0.0, 1.0));
-        let i = Intersection::new(4.0, &w.objects[1]);
+        let i = Intersection::new(4.0, w.objects[1].as_ref());
         let comps = i.prepare_computations(r);
         let c = w.shade_hit(comps, &mut Intersections::empty());
         assert_eq!(c, color_rgb!(0.1, 0.1, 0.1));
     }
-}
 
-/* Feature: World
-
-Scenario: shade_hit() is given an intersection in shadow
-  Given w â† world()
-    And w.light â† point_light(point(0, 0, -10), color(1, 1, 1))
-    And s1 â† sphere()
-    And s1 is added to w
-    And s2 â† sphere() with:
-      | transform | translation(0, 0, 10) |
-    And s2 is added to w
-    And r â† ray(point(0, 0, 5), vector(0, 0, 1))
-    And i â† intersection(4, s2)
-  When comps â† prepare_computations(i, r)
-    And c â† shade_hit(w, comps)
-  Then c = color(0.1, 0.1, 0.1)
-
-Scenario: The reflected color for a nonreflective material
-  Given w â† default_world()
-    And r â† ray(point(0, 0, 0), vector(0, 0, 1))
-    And shape â† the second object in w
-    And shape.material.ambient â† 1
-    And i â† intersection(1, shape)
-  When comps â† prepare_computations(i, r)
-    And color â† reflected_color(w, comps)
-  Then color = color(0, 0, 0)
-
-Scenario: The reflected color for a reflective material
-  Given w â† default_world()
-    And shape â† plane() with:                 
-      | material.reflective | 0.5                   |
-      | transform           | translation(0, -1, 0) |   
-    And shape is added to w
-    And r â† ray(point(0, 0, -3), vector(0, -âˆš2/2, âˆš2/2))
-    And i â† intersection(âˆš2, shape)
-  When comps â† prepare_computations(i, r)
-    And color â† reflected_color(w, comps)
-  Then color = color(0.19032, 0.2379, 0.14274)
-
-Scenario: shade_hit() with a reflective material
-  Given w â† default_world()
-    And shape â† plane() with:
-      | material.reflective | 0.5                   |
-      | transform           | translation(0, -1, 0) |
-    And shape is added to w
-    And r â† ray(point(0, 0, -3), vector(0, -âˆš2/2, âˆš2/2))
-    And i â† intersection(âˆš2, shape)
-  When comps â† prepare_computations(i, r)
-    And color â† shade_hit(w, comps)
-  Then color = color(0.87677, 0.92436, 0.82918)
-
-Scenario: color_at() with mutually reflective surfaces
-  Given w â† world()
-    And w.light â† point_light(point(0, 0, 0), color(1, 1, 1))
-    And lower â† plane() with:
-      | material.reflective | 1                     |
-      | transform           | translation(0, -1, 0) |
-    And lower is added to w
-    And upper â† plane() with:
-      | material.reflective | 1                    |
-      | transform           | translation(0, 1, 0) |
-    And upper is added to w
-    And r â† ray(point(0, 0, 0), vector(0, 1, 0))
-  Then color_at(w, r) should terminate successfully
-
-Scenario: The reflected color at the maximum recursive depth
-  Given w â† default_world()
-    And shape â† plane() with:
-      | material.reflective | 0.5                   |
-      | transform           | translation(0, -1, 0) |
-    And shape is added to w
-    And r â† ray(point(0, 0, -3), vector(0, -âˆš2/2, âˆš2/2))
-    And i â† intersection(âˆš2, shape)
-  When comps â† prepare_computations(i, r)
-    And color â† reflected_color(w, comps, 0)    
-  Then color = color(0, 0, 0)
-
-Scenario: The refracted color with an opaque surface
-  Given w â† default_world()
-    And shape â† the first object in w
-    And r â† ray(point(0, 0, -5), vector(0, 0, 1))
-    And xs â† intersections(4:shape, 6:shape)
-  When comps â† prepare_computations(xs[0], r, xs)
-    And c â† refracted_color(w, comps, 5)
-  Then c = color(0, 0, 0)
-
-Scenario: The refracted color at the maximum recursive depth
-  Given w â† default_world()
-    And shape â† the first object in w
-    And shape has:
-      | material.transparency     | 1.0 |
-      | material.refractive_index | 1.5 |
-    And r â† ray(point(0, 0, -5), vector(0, 0, 1))
-    And xs â† intersections(4:shape, 6:shape)
-  When comps â† prepare_computations(xs[0], r, xs)
-    And c â† refracted_color(w, comps, 0)
-  Then c = color(0, 0, 0)
-
-Scenario: The refracted color under total internal reflection
-  Given w â† default_world()
-    And shape â† the first object in w
-    And shape has:
-      | material.transparency     | 1.0 |
-      | material.refractive_index | 1.5 |
-    And r â† ray(point(0, 0, âˆš2/2), vector(0, 1, 0))
-    And xs â† intersections(-âˆš2/2:shape, âˆš2/2:shape)
-  # NOTE: this time you're inside the sphere, so you need
-  # to look at the second intersection, xs[1], not xs[0]
-  When comps â† prepare_computations(xs[1], r, xs)
-    And c â† refracted_color(w, comps, 5)
-  Then c = color(0, 0, 0)
-
-Scenario: The refracted color with a refracted ray
-  Given w â† default_world()
-    And A â† the first object in w
-    And A has:
-      | material.ambient | 1.0            |
-      | material.pattern | test_pattern() |
-    And B â† the second object in w
-    And B has:
-      | material.transparency     | 1.0 |
-      | material.refractive_index | 1.5 |
-    And r â† ray(point(0, 0, 0.1), vector(0, 1, 0))
-    And xs â† intersections(-0.9899:A, -0.4899:B, 0.4899:B, 0.9899:A)
-  When comps â† prepare_computations(xs[2], r, xs)
-    And c â† refracted_color(w, comps, 5)
-  Then c = color(0, 0.99888, 0.04725)
-
-Scenario: shade_hit() with a transparent material
-  Given w â† default_world()
-    And floor â† plane() with:
-      | transform                 | translation(0, -1, 0) |
-      | material.transparency     | 0.5                   |
-      | material.refractive_index | 1.5                   |
-    And floor is added to w
-    And ball â† sphere() with:
-      | material.color     | (1, 0, 0)                  |
-      | material.ambient   | 0.5                        |
-      | transform          | translation(0, -3.5, -0.5) |
-    And ball is added to w
-    And r â† ray(point(0, 0, -3), vector(0, -âˆš2/2, âˆš2/2))
-    And xs â† intersections(âˆš2:floor)
-  When comps â† prepare_computations(xs[0], r, xs)
-    And color â† shade_hit(w, comps, 5)
-  Then color = color(0.93642, 0.68642, 0.68642)
-
-Scenario: shade_hit() with a reflective, transparent material
-  Given w â† default_world()
-    And r â† ray(point(0, 0, -3), vector(0, -âˆš2/2, âˆš2/2))
-    And floor â† plane() with:
-      | transform                 | translation(0, -1, 0) |
-      | material.reflective       | 0.5                   |
-      | material.transparency     | 0.5                   |
-      | material.refractive_index | 1.5                   |
-    And floor is added to w
-    And ball â† sphere() with:
-      | material.color     | (1, 0, 0)                  |
-      | material.ambient   | 0.5                        |
-      | transform          | translation(0, -3.5, -0.5) |
-    And ball is added to w
-    And xs â† intersections(âˆš2:floor)
-  When comps â† prepare_computations(xs[0], r, xs)
-    And color â† shade_hit(w, comps, 5)
-  Then color = color(0.93391, 0.69643, 0.69243)
-*/
+    #[test]
+    fn finding_emitters_in_groups() {
+        let glowing = Material { emission: color(1.0, 1.0, 1.0), ..Material::default() };
+        let mut g = Group::new();
+        g.add_child(Box::new(Sphere::default()));
+        g.add_child(Box::new(Sphere::from_material(glowing)));
+        let mut w = World::simple();
+        w.add_object(g).add_object(Sphere::from_material(glowing));
+        let mut count = 0;
+        w.for_each_emitter(|s| {
+            assert!(s.is_emissive());
+            count += 1;
+        });
+        assert_eq!(count, 2);
+    }
+
+    // Far away a small panel is nearly a point light: irradiance L * A / d²,
+    // which is π times the intensity of the equivalent point light
+    #[test]
+    fn small_emitter_acts_like_point_light() {
+        let mut panel = Triangle::new(point(0.0, 0.0, 0.0), point(0.01, 0.0, 0.0), point(0.0, 0.0, 0.01));
+        panel.set_transform(translation(0.0, 10.0, 0.0)).set_material(Material {
+            emission: color(2.0, 1.0, 0.0), ..Material::default()
+        });
+        let mut w = World::default();
+        w.add_object(panel);
+        let light = w.sample_area_light(point(0.0, 0.0, 0.0), 0.0, &mut Rng::new(1), &mut Intersections::empty())
+            .unwrap();
+        let area = 0.00005;
+        assert!((light.intensity.red - 2.0 * area / (PI * 100.0)).abs() < 1e-9);
+        assert!(float_eq!(light.pos.y, 10.0));
+
+        // Blocked
+        w.add_object(Sphere::from_transform(translation(0.0, 5.0, 0.0)));
+        assert!(w.sample_area_light(point(0.0, 0.0, 0.0), 0.0, &mut Rng::new(1), &mut Intersections::empty())
+                .is_none());
+    }
+
+    #[test]
+    fn emissive_shapes_glow_without_lights() {
+        let mut w = World::default();
+        w.add_object(Sphere::from_material(Material { emission: color(0.5, 0.5, 0.5), ..Material::default() }));
+        let r = Ray::new(point(0.0, 0.0, -5.0), vector(0.0, 0.0, 1.0));
+        let c = w.color_at(r, &mut Intersections::empty(), &mut Intersections::empty());
+        // The sphere can't see itself from outside, so it gets no light from
+        // its own emission
+        assert_eq!(c, color(0.5, 0.5, 0.5));
+    }
+
+    // Inside the outer sphere of the default world every ray is blocked,
+    // so only the ambient term changes, and it goes
+    #[test]
+    fn ambient_occlusion_scales_ambient() {
+        let mut w = World::simple();
+        w.lights = vec![Light::new(point(0.0, 0.25, 0.0), color(1.0, 1.0, 1.0))];
+        let r = Ray::new(point(0.0, 0.0, 0.75), vector(0.0, 0.0, -1.0));
+        let plain = w.color_at(r, &mut Intersections::empty(), &mut Intersections::empty());
+        w.ambient_occlusion = Some(AmbientOcclusion::new(8, f64::INFINITY));
+        let occluded = w.color_at(r, &mut Intersections::empty(), &mut Intersections::empty());
+        assert_eq!(plain - occluded, color(0.1, 0.1, 0.1));
+        // Beyond the max distance nothing occludes
+        w.ambient_occlusion = Some(AmbientOcclusion::new(8, 0.1));
+        assert_eq!(w.color_at(r, &mut Intersections::empty(), &mut Intersections::empty()), plain);
+    }
+}
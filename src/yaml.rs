@@ -0,0 +1,335 @@
+// Just enough YAML for scene files: block sequences and mappings nested by
+// indentation, flow sequences (`[1, 2, [3]]`), plain or quoted scalars and
+// `#` comments. Anchors, multi-line strings, flow mappings and the rest of
+// YAML are not supported. Every node remembers the line it came from so that
+// errors can point at the right place.
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Scalar(String),
+    List(Vec<Node>),
+    Map(Vec<(String, Node)>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub value: Value,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct YamlError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for YamlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for YamlError {}
+
+impl Node {
+    fn new(value: Value, line: usize) -> Self {
+        Self { value, line }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            Value::Scalar(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        self.as_str().and_then(|s| s.parse().ok())
+    }
+
+    pub fn as_list(&self) -> Option<&[Node]> {
+        match &self.value {
+            Value::List(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn as_map(&self) -> Option<&[(String, Node)]> {
+        match &self.value {
+            Value::Map(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    // Look up a key if this node is a mapping
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        self.as_map()?.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug)]
+struct Line<'a> {
+    indent: usize,
+    text: &'a str,
+    number: usize,
+}
+
+pub fn parse(src: &str) -> Result<Node, YamlError> {
+    let mut lines = Vec::new();
+    for (i, raw) in src.lines().enumerate() {
+        let text = strip_comment(raw).trim_end();
+        if text.trim().is_empty() || text.trim() == "---" { continue; }
+        if text.contains('\t') {
+            return Err(YamlError { line: i + 1, message: "tabs are not allowed in indentation".to_string() });
+        }
+        let indent = text.len() - text.trim_start().len();
+        lines.push(Line { indent, text: text.trim_start(), number: i + 1 });
+    }
+
+    if lines.is_empty() {
+        return Ok(Node::new(Value::List(vec![]), 1));
+    }
+
+    let mut pos = 0;
+    let indent = lines[0].indent;
+    let node = parse_block(&mut lines, &mut pos, indent)?;
+    if pos < lines.len() {
+        return Err(YamlError { line: lines[pos].number, message: "unexpected indentation".to_string() });
+    }
+    return Ok(node);
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quote = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match in_quote {
+            Some(q) if c == q => in_quote = None,
+            Some(_) => (),
+            None if c == '"' || c == '\'' => in_quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return &line[..i],
+            None => (),
+        }
+        prev = c;
+    }
+    return line;
+}
+
+fn parse_block(lines: &mut Vec<Line>, pos: &mut usize, indent: usize) -> Result<Node, YamlError> {
+    if is_sequence_item(lines[*pos].text) {
+        parse_sequence(lines, pos, indent)
+    } else {
+        parse_mapping(lines, pos, indent)
+    }
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+fn parse_sequence(lines: &mut Vec<Line>, pos: &mut usize, indent: usize) -> Result<Node, YamlError> {
+    let start = lines[*pos].number;
+    let mut items = Vec::new();
+
+    while *pos < lines.len() && lines[*pos].indent == indent && is_sequence_item(lines[*pos].text) {
+        let line = &lines[*pos];
+        let rest = line.text[1..].trim_start();
+        let number = line.number;
+
+        if rest.is_empty() {
+            // The item is a block on the following lines
+            *pos += 1;
+            if *pos >= lines.len() || lines[*pos].indent <= indent {
+                items.push(Node::new(Value::Scalar(String::new()), number));
+                continue;
+            }
+            let child_indent = lines[*pos].indent;
+            items.push(parse_block(lines, pos, child_indent)?);
+        } else if rest.starts_with('[') || !is_mapping_entry(rest) {
+            items.push(parse_flow(rest, number)?);
+            *pos += 1;
+        } else {
+            // `- key: value` starts a mapping whose other entries are
+            // indented to line up with `key`
+            let column = indent + (line.text.len() - rest.len());
+            lines[*pos] = Line { indent: column, text: rest, number };
+            items.push(parse_mapping(lines, pos, column)?);
+        }
+    }
+
+    return Ok(Node::new(Value::List(items), start));
+}
+
+fn is_mapping_entry(text: &str) -> bool {
+    split_key(text).is_some()
+}
+
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with('"') || text.starts_with('\'') || text.starts_with('[') {
+        return None;
+    }
+    if let Some(key) = text.strip_suffix(':') {
+        return Some((key.trim(), ""));
+    }
+    let i = text.find(": ")?;
+    Some((text[..i].trim(), text[i + 2..].trim()))
+}
+
+fn parse_mapping(lines: &mut Vec<Line>, pos: &mut usize, indent: usize) -> Result<Node, YamlError> {
+    let start = lines[*pos].number;
+    let mut entries: Vec<(String, Node)> = Vec::new();
+
+    while *pos < lines.len() && lines[*pos].indent == indent && !is_sequence_item(lines[*pos].text) {
+        let number = lines[*pos].number;
+        let (key, value) = match split_key(lines[*pos].text) {
+            Some((k, v)) => (k.to_string(), v.to_string()),
+            None => return Err(YamlError { line: number, message: format!("expected 'key: value', found '{}'", lines[*pos].text) }),
+        };
+        if entries.iter().any(|(k, _)| *k == key) {
+            return Err(YamlError { line: number, message: format!("duplicate key '{}'", key) });
+        }
+        *pos += 1;
+
+        let node = if !value.is_empty() {
+            parse_flow(&value, number)?
+        } else if *pos < lines.len()
+            && (lines[*pos].indent > indent
+                || (lines[*pos].indent == indent && is_sequence_item(lines[*pos].text))) {
+            let child_indent = lines[*pos].indent;
+            parse_block(lines, pos, child_indent)?
+        } else {
+            Node::new(Value::Scalar(String::new()), number)
+        };
+        entries.push((key, node));
+    }
+
+    return Ok(Node::new(Value::Map(entries), start));
+}
+
+// A scalar or a flow sequence on a single line
+fn parse_flow(text: &str, line: usize) -> Result<Node, YamlError> {
+    let mut chars = text.trim().chars().peekable();
+    let node = parse_flow_value(&mut chars, line)?;
+    if let Some(c) = chars.find(|c| !c.is_whitespace()) {
+        return Err(YamlError { line, message: format!("unexpected '{}' after value", c) });
+    }
+    return Ok(node);
+}
+
+fn parse_flow_value<I>(chars: &mut std::iter::Peekable<I>, line: usize) -> Result<Node, YamlError>
+    where I: Iterator<Item = char> {
+    skip_whitespace(chars);
+
+    match chars.peek() {
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    break;
+                }
+                items.push(parse_flow_value(chars, line)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => break,
+                    _ => return Err(YamlError { line, message: "unterminated '['".to_string() }),
+                }
+            }
+            Ok(Node::new(Value::List(items), line))
+        }
+        Some(&q) if q == '"' || q == '\'' => {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err(YamlError { line, message: "unterminated string".to_string() }),
+                    Some(c) if c == q => break,
+                    Some(c) => s.push(c),
+                }
+            }
+            Ok(Node::new(Value::Scalar(s), line))
+        }
+        _ => {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == ',' || c == ']' { break; }
+                s.push(c);
+                chars.next();
+            }
+            Ok(Node::new(Value::Scalar(s.trim().to_string()), line))
+        }
+    }
+}
+
+fn skip_whitespace<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scalars_and_flow_lists() {
+        let n = parse("a: 1.5\nb: [1, -2, [x, \"y z\"]]\nc: hello world").unwrap();
+        assert_eq!(n.get("a").unwrap().as_f64(), Some(1.5));
+        let b = n.get("b").unwrap().as_list().unwrap();
+        assert_eq!(b.len(), 3);
+        assert_eq!(b[1].as_f64(), Some(-2.0));
+        assert_eq!(b[2].as_list().unwrap()[1].as_str(), Some("y z"));
+        assert_eq!(n.get("c").unwrap().as_str(), Some("hello world"));
+    }
+
+    #[test]
+    fn sequence_of_mappings() {
+        let src = "
+# A comment
+- add: camera
+  width: 100   # trailing comment
+  from: [ -6, 6, -10 ]
+
+- define: white
+  value:
+    color: [ 1, 1, 1 ]
+    diffuse: 0.7
+";
+        let n = parse(src).unwrap();
+        let items = n.as_list().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].get("add").unwrap().as_str(), Some("camera"));
+        assert_eq!(items[0].get("width").unwrap().as_f64(), Some(100.0));
+        assert_eq!(items[0].line, 3);
+        let value = items[1].get("value").unwrap();
+        assert_eq!(value.get("diffuse").unwrap().as_f64(), Some(0.7));
+        assert_eq!(value.get("diffuse").unwrap().line, 10);
+    }
+
+    #[test]
+    fn nested_sequences() {
+        let src = "
+transform:
+  - [ translate, 1, 2, 3 ]
+  - standard
+other:
+- [ scale, 2, 2, 2 ]
+";
+        let n = parse(src).unwrap();
+        let t = n.get("transform").unwrap().as_list().unwrap();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].as_list().unwrap()[0].as_str(), Some("translate"));
+        assert_eq!(t[1].as_str(), Some("standard"));
+        assert_eq!(n.get("other").unwrap().as_list().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        assert_eq!(parse("a: 1\n  b: 2").unwrap_err().line, 2);
+        assert_eq!(parse("a: [1, 2\n").unwrap_err().line, 1);
+        assert_eq!(parse("a: 1\na: 2").unwrap_err().line, 2);
+        assert_eq!(parse("- a: 1\n  oops\n").unwrap_err().line, 2);
+    }
+}
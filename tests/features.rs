@@ -0,0 +1,16 @@
+// Runs the Gherkin scenarios in tests/features, one test per feature file.
+mod gherkin;
+
+macro_rules! features {
+    ( $( $name:ident ),* ) => {
+        $(
+            #[test]
+            fn $name() {
+                gherkin::run(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/features/", stringify!($name), ".feature"));
+            }
+        )*
+    }
+}
+
+features!(tuples, matrices, transformations, rays, spheres, intersections,
+          materials, lights, world, camera, shapes, groups, triangles);
@@ -0,0 +1,137 @@
+// Expressions as written in the scenarios: numbers (with `π` and `√`),
+// variables, calls, field access, indexing, arithmetic, `(r, g, b)` colours
+// and `t:object` intersections.
+use nom::{
+    IResult,
+    branch::alt,
+    bytes::complete::{tag, take_while1},
+    character::complete::{char, multispace0},
+    combinator::{all_consuming, map, map_res, opt, recognize},
+    multi::{fold_many0, many0, separated_list0},
+    sequence::{delimited, pair, preceded, tuple},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Num(f64),
+    Var(String),
+    Call(String, Vec<Expr>),
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, Vec<Expr>),
+    Neg(Box<Expr>),
+    Sqrt(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Tuple(Vec<Expr>),
+    // `4:shape` in `intersections(4:shape, 6:shape)`
+    Pair(Box<Expr>, Box<Expr>),
+}
+
+pub fn parse(s: &str) -> Result<Expr, String> {
+    all_consuming(ws(expr))(s)
+        .map(|(_, e)| e)
+        .map_err(|_| format!("cannot parse expression '{}'", s))
+}
+
+fn ws<'a, O, F>(f: F) -> impl FnMut(&'a str) -> IResult<&'a str, O>
+    where F: FnMut(&'a str) -> IResult<&'a str, O> {
+    delimited(multispace0, f, multispace0)
+}
+
+fn expr(i: &str) -> IResult<&str, Expr> {
+    let (i, first) = term(i)?;
+    fold_many0(pair(ws(alt((char('+'), char('-')))), term), first,
+               |lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))(i)
+}
+
+fn term(i: &str) -> IResult<&str, Expr> {
+    let (i, first) = unary(i)?;
+    fold_many0(pair(ws(alt((char('*'), char('/')))), unary), first,
+               |lhs, (op, rhs)| Expr::Binary(op, Box::new(lhs), Box::new(rhs)))(i)
+}
+
+fn unary(i: &str) -> IResult<&str, Expr> {
+    alt((
+        map(preceded(ws(char('-')), unary), |e| Expr::Neg(Box::new(e))),
+        map(preceded(ws(tag("√")), unary), |e| Expr::Sqrt(Box::new(e))),
+        postfix,
+    ))(i)
+}
+
+enum Suffix {
+    Field(String),
+    Index(Vec<Expr>),
+}
+
+fn postfix(i: &str) -> IResult<&str, Expr> {
+    let (i, base) = primary(i)?;
+    let (i, suffixes) = many0(alt((
+        map(preceded(char('.'), ident), |f| Suffix::Field(f.to_string())),
+        map(delimited(char('['), separated_list0(char(','), ws(expr)), char(']')), Suffix::Index),
+    )))(i)?;
+    let e = suffixes.into_iter().fold(base, |e, s| match s {
+        Suffix::Field(f) => Expr::Field(Box::new(e), f),
+        Suffix::Index(ix) => Expr::Index(Box::new(e), ix),
+    });
+    Ok((i, e))
+}
+
+fn primary(i: &str) -> IResult<&str, Expr> {
+    alt((
+        map(number, Expr::Num),
+        map(tag("π"), |_| Expr::Num(std::f64::consts::PI)),
+        map(tuple((ident, ws(char('(')), separated_list0(char(','), ws(argument)), char(')'))),
+            |(name, _, args, _)| Expr::Call(name.to_string(), args)),
+        map(ident, |name| Expr::Var(name.to_string())),
+        map(delimited(char('('), separated_list0(char(','), ws(expr)), char(')')),
+            |mut items| if items.len() == 1 { items.remove(0) } else { Expr::Tuple(items) }),
+    ))(i)
+}
+
+fn argument(i: &str) -> IResult<&str, Expr> {
+    let (i, e) = expr(i)?;
+    let (i, object) = opt(preceded(ws(char(':')), expr))(i)?;
+    Ok((i, match object {
+        Some(object) => Expr::Pair(Box::new(e), Box::new(object)),
+        None => e,
+    }))
+}
+
+fn number(i: &str) -> IResult<&str, f64> {
+    map_res(recognize(pair(take_while1(|c: char| c.is_ascii_digit()),
+                           opt(pair(char('.'), take_while1(|c: char| c.is_ascii_digit()))))),
+            |s: &str| s.parse())(i)
+}
+
+fn ident(i: &str) -> IResult<&str, &str> {
+    recognize(pair(take_while1(|c: char| c.is_ascii_alphabetic() || c == '_'),
+                   opt(take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'))))(i)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn num(x: f64) -> Box<Expr> { Box::new(Expr::Num(x)) }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("1 + 2 * 3").unwrap(),
+                   Expr::Binary('+', num(1.0), Box::new(Expr::Binary('*', num(2.0), num(3.0)))));
+        assert_eq!(parse("-√2/2").unwrap(),
+                   Expr::Binary('/', Box::new(Expr::Neg(Box::new(Expr::Sqrt(num(2.0))))), num(2.0)));
+    }
+
+    #[test]
+    fn calls_fields_and_indexing() {
+        assert_eq!(parse("xs[0].object").unwrap(),
+                   Expr::Field(Box::new(Expr::Index(Box::new(Expr::Var("xs".into())), vec![Expr::Num(0.0)])),
+                               "object".into()));
+        assert_eq!(parse("intersections(4:s, 6:s)").unwrap(),
+                   Expr::Call("intersections".into(), vec![
+                       Expr::Pair(num(4.0), Box::new(Expr::Var("s".into()))),
+                       Expr::Pair(num(6.0), Box::new(Expr::Var("s".into()))),
+                   ]));
+        assert_eq!(parse("(1, 0, 0)").unwrap(), Expr::Tuple(vec![Expr::Num(1.0), Expr::Num(0.0), Expr::Num(0.0)]));
+        assert!(parse("point(1, 2").is_err());
+    }
+}
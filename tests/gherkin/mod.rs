@@ -0,0 +1,190 @@
+// A small Gherkin runner for the reference scenarios in `tests/features`.
+//
+// Supported syntax: Feature, Background, Scenario, Scenario Outline with
+// Examples, Given/When/Then/And/But steps, data tables, `#` comments and tags.
+// Scenarios tagged `@pending` describe behaviour the renderer doesn't have
+// yet; they are parsed but not run.
+//
+// Steps are matched against the definitions in `steps.rs`, whose arguments
+// are expressions in the book's notation (`point(1, 2, 3)`, `√2/2`,
+// `xs[0].t`...) evaluated by `expr.rs`.
+pub mod expr;
+pub mod steps;
+
+use std::panic::{self, AssertUnwindSafe};
+
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub text: String,
+    pub table: Vec<Vec<String>>,
+    pub line: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    pub name: String,
+    pub line: usize,
+    pub tags: Vec<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Feature {
+    pub name: String,
+    pub background: Vec<Step>,
+    pub scenarios: Vec<Scenario>,
+}
+
+const STEP_KEYWORDS: [&str; 6] = ["Given ", "When ", "Then ", "And ", "But ", "* "];
+
+#[derive(PartialEq)]
+enum Section {
+    Header,
+    Background,
+    Scenario,
+    Examples,
+}
+
+pub fn parse(src: &str) -> Result<Feature, String> {
+    let mut feature = Feature::default();
+    let mut section = Section::Header;
+    let mut tags: Vec<String> = vec![];
+    // An outline waiting for its examples, and the examples table
+    let mut outline: Option<Scenario> = None;
+    let mut examples: Vec<Vec<String>> = vec![];
+
+    for (i, raw) in src.lines().enumerate() {
+        let line_no = i + 1;
+        let line = raw.trim();
+        let err = |message: &str| Err(format!("line {}: {}", line_no, message));
+
+        if line.is_empty() || line.starts_with('#') { continue; }
+
+        if line.starts_with('@') {
+            tags.extend(line.split_whitespace().map(String::from));
+        } else if let Some(name) = line.strip_prefix("Feature:") {
+            feature.name = name.trim().to_string();
+        } else if line.starts_with("Background:") {
+            section = Section::Background;
+        } else if let Some(name) = line.strip_prefix("Scenario Outline:") {
+            expand_outline(&mut feature, outline.take(), &mut examples)?;
+            outline = Some(Scenario { name: name.trim().to_string(), line: line_no, tags: tags.split_off(0), steps: vec![] });
+            section = Section::Scenario;
+        } else if let Some(name) = line.strip_prefix("Scenario:").or_else(|| line.strip_prefix("Example:")) {
+            expand_outline(&mut feature, outline.take(), &mut examples)?;
+            feature.scenarios.push(Scenario { name: name.trim().to_string(), line: line_no, tags: tags.split_off(0), steps: vec![] });
+            section = Section::Scenario;
+        } else if line.starts_with("Examples:") {
+            if outline.is_none() { return err("Examples outside a Scenario Outline"); }
+            section = Section::Examples;
+        } else if line.starts_with('|') {
+            let row = line.trim_matches('|').split('|').map(|c| c.trim().to_string()).collect();
+            if section == Section::Examples {
+                examples.push(row);
+                continue;
+            }
+            let steps = match section {
+                Section::Background => &mut feature.background,
+                Section::Scenario => match outline.as_mut() {
+                    Some(o) => &mut o.steps,
+                    None => &mut feature.scenarios.last_mut().unwrap().steps,
+                },
+                _ => return err("table outside a step"),
+            };
+            match steps.last_mut() {
+                Some(step) => step.table.push(row),
+                None => return err("table outside a step"),
+            }
+        } else if let Some(keyword) = STEP_KEYWORDS.iter().find(|k| line.starts_with(*k)) {
+            let step = Step { text: line[keyword.len()..].trim().to_string(), table: vec![], line: line_no };
+            match section {
+                Section::Background => feature.background.push(step),
+                Section::Scenario => match outline.as_mut() {
+                    Some(o) => o.steps.push(step),
+                    None => feature.scenarios.last_mut().unwrap().steps.push(step),
+                },
+                _ => return err("step outside a scenario"),
+            }
+        } else if section != Section::Header {
+            return err(&format!("unexpected '{}'", line));
+        }
+        // Anything else in the header is the feature's description
+    }
+
+    expand_outline(&mut feature, outline, &mut examples)?;
+    Ok(feature)
+}
+
+// One scenario per example row, with `<name>` replaced by the row's values
+fn expand_outline(feature: &mut Feature, outline: Option<Scenario>,
+                  examples: &mut Vec<Vec<String>>) -> Result<(), String> {
+    let outline = match outline {
+        Some(outline) => outline,
+        None => return Ok(()),
+    };
+    if examples.len() < 2 {
+        return Err(format!("line {}: Scenario Outline without examples", outline.line));
+    }
+
+    let header = examples[0].clone();
+    for (n, row) in examples.drain(..).enumerate().skip(1) {
+        let fill = |s: &str| header.iter().zip(row.iter())
+            .fold(s.to_string(), |s, (k, v)| s.replace(&format!("<{}>", k), v));
+        feature.scenarios.push(Scenario {
+            name: format!("{} (example {})", outline.name, n),
+            line: outline.line,
+            tags: outline.tags.clone(),
+            steps: outline.steps.iter().map(|step| Step {
+                text: fill(&step.text),
+                table: step.table.iter().map(|r| r.iter().map(|c| fill(c)).collect()).collect(),
+                line: step.line,
+            }).collect(),
+        });
+    }
+    Ok(())
+}
+
+// Run every scenario of a feature file, panicking with a list of the
+// failures if there are any.
+pub fn run(path: &str) {
+    let src = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+    let feature = parse(&src).unwrap_or_else(|e| panic!("{}: {}", path, e));
+
+    let mut failures = vec![];
+    let mut pending = 0;
+    for scenario in feature.scenarios.iter() {
+        if scenario.tags.iter().any(|t| t == "@pending") {
+            pending += 1;
+            continue;
+        }
+        if let Err((line, message)) = run_scenario(&feature.background, scenario) {
+            failures.push(format!("{}:{}: {}\n    {}", path, line, scenario.name, message));
+        }
+    }
+
+    println!("{}: {} scenarios ({} passed, {} failed, {} pending)", feature.name,
+             feature.scenarios.len(), feature.scenarios.len() - failures.len() - pending,
+             failures.len(), pending);
+    if !failures.is_empty() {
+        panic!("{} failing scenario(s):\n{}", failures.len(), failures.join("\n"));
+    }
+}
+
+fn run_scenario(background: &[Step], scenario: &Scenario) -> Result<(), (usize, String)> {
+    let mut ctx = steps::Ctx::default();
+    for step in background.iter().chain(scenario.steps.iter()) {
+        // Crate code asserts with panics, report those as step failures too
+        let result = panic::catch_unwind(AssertUnwindSafe(|| steps::execute(&mut ctx, step)))
+            .unwrap_or_else(|e| Err(format!("panicked: {}", panic_message(&e))));
+        if let Err(message) = result {
+            return Err((step.line, format!("{}\n    {}", step.text, message)));
+        }
+    }
+    Ok(())
+}
+
+fn panic_message(e: &Box<dyn std::any::Any + Send>) -> String {
+    e.downcast_ref::<String>().cloned()
+        .or_else(|| e.downcast_ref::<&str>().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
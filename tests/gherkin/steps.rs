@@ -0,0 +1,974 @@
+// Step definitions and the values they work with.
+//
+// Shapes live in an arena and variables refer to them by id, so scenarios
+// can keep using a shape after adding it to a group or world as the book
+// does. Whenever the crate needs a real shape it is built from the arena,
+// in world space, and intersections are converted back to arena ids.
+use super::expr::{self, Expr};
+use super::Step;
+use raytracer::prelude::*;
+use raytracer::shape::TestShape;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+type Result<T> = std::result::Result<T, String>;
+
+pub type ShapeId = usize;
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Sphere,
+    Test,
+    Triangle(T4, T4, T4),
+    SmoothTriangle([T4; 6]),
+    Group(Vec<ShapeId>),
+}
+
+#[derive(Clone, Debug)]
+struct ShapeSpec {
+    kind: Kind,
+    transform: M4,
+    material: Material,
+    parent: Option<ShapeId>,
+    // The last ray a test shape was intersected with
+    saved_ray: Option<Ray>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub t: f64,
+    pub object: ShapeId,
+    pub u: f64,
+    pub v: f64,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Comps {
+    t: f64,
+    object: ShapeId,
+    point: T4,
+    over_point: T4,
+    eyev: T4,
+    normalv: T4,
+    inside: bool,
+    time: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WorldSpec {
+    objects: Vec<ShapeId>,
+    lights: Vec<Light>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nothing,
+    Bool(bool),
+    Num(f64),
+    Tuple(T4),
+    Color(Color),
+    M2(M2),
+    M3(M3),
+    M4(M4),
+    Ray(Ray),
+    Light(Light),
+    Material(Material),
+    Shape(ShapeId),
+    Hit(Hit),
+    Hits(Vec<Hit>),
+    Comps(Comps),
+    World(Rc<RefCell<WorldSpec>>),
+    Camera(Box<Camera>),
+    Canvas(Rc<RefCell<Canvas>>),
+}
+
+#[derive(Default)]
+pub struct Ctx {
+    vars: HashMap<String, Value>,
+    shapes: Vec<ShapeSpec>,
+}
+
+type StepFn = fn(&mut Ctx, &[String], &[Vec<String>]) -> Result<()>;
+
+// Tried in order, so more specific patterns come first. `{}` captures an
+// argument, usually an expression.
+fn definitions() -> Vec<(&'static str, StepFn)> {
+    vec![
+        ("the following {}x{} matrix {}:", |ctx, a, table| {
+            let m = matrix_from_table(ctx, table, Some((&a[0], &a[1])))?;
+            ctx.set(&a[2], m);
+            Ok(())
+        }),
+        ("the following matrix {}:", |ctx, a, table| {
+            let m = matrix_from_table(ctx, table, None)?;
+            ctx.set(&a[0], m);
+            Ok(())
+        }),
+        ("{} is the following {}x{} matrix:", |ctx, a, table| {
+            let expected = matrix_from_table(ctx, table, Some((&a[1], &a[2])))?;
+            check_eq(ctx.eval_str(&a[0])?, expected)
+        }),
+        ("{} is the following matrix:", |ctx, a, table| {
+            let expected = matrix_from_table(ctx, table, None)?;
+            check_eq(ctx.eval_str(&a[0])?, expected)
+        }),
+        ("{} ← {} with:", |ctx, a, table| {
+            let value = ctx.eval_str(&a[1])?;
+            ctx.set(&a[0], value);
+            ctx.apply_table(&a[0], table)
+        }),
+        ("{} has:", |ctx, a, table| ctx.apply_table(&a[0], table)),
+        ("{} ← the {} object in {}", |ctx, a, _| {
+            let index = match a[1].as_str() {
+                "first" => 0,
+                "second" => 1,
+                "third" => 2,
+                other => return Err(format!("unknown ordinal '{}'", other)),
+            };
+            let world = ctx.world(&a[2])?;
+            let id = world.borrow().objects.get(index).copied()
+                .ok_or(format!("{} has no {} object", a[2], a[1]))?;
+            ctx.set(&a[0], Value::Shape(id));
+            Ok(())
+        }),
+        ("{} is added to {}", |ctx, a, _| {
+            let id = ctx.shape(&a[0])?;
+            ctx.world(&a[1])?.borrow_mut().objects.push(id);
+            Ok(())
+        }),
+        ("{} contains no objects", |ctx, a, _| {
+            check(ctx.world(&a[0])?.borrow().objects.is_empty(), "the world has objects")
+        }),
+        ("{} has no light source", |ctx, a, _| {
+            check(ctx.world(&a[0])?.borrow().lights.is_empty(), "the world has a light")
+        }),
+        ("{} contains {}", |ctx, a, _| {
+            let id = ctx.shape(&a[1])?;
+            let world = ctx.world(&a[0])?;
+            let found = world.borrow().objects.iter().any(|&o| ctx.same_shape(o, id));
+            check(found, "no such object in the world")
+        }),
+        ("{} includes {}", |ctx, a, _| {
+            let (g, s) = (ctx.shape(&a[0])?, ctx.shape(&a[1])?);
+            check(ctx.children(g)?.contains(&s), "not a child of the group")
+        }),
+        ("{} is not invertible", |ctx, a, _| check(!ctx.eval_m4(&a[0])?.invertible(), "matrix is invertible")),
+        ("{} is invertible", |ctx, a, _| check(ctx.eval_m4(&a[0])?.invertible(), "matrix is not invertible")),
+        ("{} is a point", |ctx, a, _| check(ctx.eval_tuple(&a[0])?.is_point(), "not a point")),
+        ("{} is not a point", |ctx, a, _| check(!ctx.eval_tuple(&a[0])?.is_point(), "is a point")),
+        ("{} is a vector", |ctx, a, _| check(ctx.eval_tuple(&a[0])?.is_vector(), "not a vector")),
+        ("{} is not a vector", |ctx, a, _| check(!ctx.eval_tuple(&a[0])?.is_vector(), "is a vector")),
+        ("{} is true", |ctx, a, _| check_eq(ctx.eval_str(&a[0])?, Value::Bool(true))),
+        ("{} is false", |ctx, a, _| check_eq(ctx.eval_str(&a[0])?, Value::Bool(false))),
+        ("{} is nothing", |ctx, a, _| check_eq(ctx.eval_str(&a[0])?, Value::Nothing)),
+        ("{} is not empty", |ctx, a, _| check(!ctx.is_empty(&a[0])?, "is empty")),
+        ("{} is empty", |ctx, a, _| check(ctx.is_empty(&a[0])?, "is not empty")),
+        ("every pixel of {} is {}", |ctx, a, _| {
+            let canvas = match ctx.eval_str(&a[0])? {
+                Value::Canvas(c) => c,
+                other => return Err(format!("expected a canvas, found {:?}", other)),
+            };
+            let expected = ctx.eval_str(&a[1])?;
+            let canvas = canvas.borrow();
+            for y in 0..canvas.get_height() {
+                for x in 0..canvas.get_width() {
+                    check_eq(Value::Color(canvas.pixel_at(x, y)), expected.clone())?;
+                }
+            }
+            Ok(())
+        }),
+        ("{} ← {}", |ctx, a, _| {
+            let value = ctx.eval_str(&a[1])?;
+            let target = expr::parse(&a[0])?;
+            ctx.assign(&target, value)
+        }),
+        ("{} != {}", |ctx, a, _| {
+            let (lhs, rhs) = (ctx.eval_str(&a[0])?, ctx.eval_str(&a[1])?);
+            check(!approx_eq(&lhs, &rhs), &format!("both are {:?}", lhs))
+        }),
+        ("{} = {}", |ctx, a, _| check_eq(ctx.eval_str(&a[0])?, ctx.eval_str(&a[1])?)),
+        ("{} < {}", |ctx, a, _| {
+            let (lhs, rhs) = (ctx.eval_num(&a[0])?, ctx.eval_num(&a[1])?);
+            check(lhs < rhs, &format!("{} is not less than {}", lhs, rhs))
+        }),
+        ("{} > {}", |ctx, a, _| {
+            let (lhs, rhs) = (ctx.eval_num(&a[0])?, ctx.eval_num(&a[1])?);
+            check(lhs > rhs, &format!("{} is not greater than {}", lhs, rhs))
+        }),
+        // Calls made for their side effects, e.g. `set_transform(s, m)`
+        ("{}", |ctx, a, _| match expr::parse(&a[0])? {
+            e @ Expr::Call(..) => ctx.eval(&e).map(|_| ()),
+            _ => Err("undefined step".to_string()),
+        }),
+    ]
+}
+
+pub fn execute(ctx: &mut Ctx, step: &Step) -> Result<()> {
+    for (pattern, f) in definitions() {
+        if let Some(args) = capture(pattern, &step.text) {
+            return f(ctx, &args, &step.table);
+        }
+    }
+    Err("undefined step".to_string())
+}
+
+// Match `text` against a pattern with `{}` placeholders. Captured text must
+// have balanced brackets so that e.g. `{} = {}` splits `f(a = b) = c`
+// correctly.
+fn capture(pattern: &str, text: &str) -> Option<Vec<String>> {
+    let pieces: Vec<&str> = pattern.split("{}").collect();
+    let mut out = vec![];
+    if capture_from(&pieces, text, &mut out) { Some(out) } else { None }
+}
+
+fn capture_from(pieces: &[&str], text: &str, out: &mut Vec<String>) -> bool {
+    let text = match text.strip_prefix(pieces[0]) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    if pieces.len() == 1 {
+        return text.is_empty();
+    }
+
+    let next = pieces[1];
+    let ends: Vec<usize> = if next.is_empty() && pieces.len() == 2 {
+        vec![text.len()]
+    } else {
+        text.match_indices(next).map(|(i, _)| i).collect()
+    };
+    for end in ends {
+        let arg = text[..end].trim();
+        if arg.is_empty() || !balanced(arg) { continue; }
+        out.push(arg.to_string());
+        if capture_from(&pieces[1..], &text[end..], out) {
+            return true;
+        }
+        out.pop();
+    }
+    false
+}
+
+fn balanced(s: &str) -> bool {
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ => (),
+        }
+        if depth < 0 { return false; }
+    }
+    depth == 0
+}
+
+fn check(ok: bool, message: &str) -> Result<()> {
+    if ok { Ok(()) } else { Err(message.to_string()) }
+}
+
+fn check_eq(actual: Value, expected: Value) -> Result<()> {
+    check(approx_eq(&actual, &expected), &format!("expected {:?}\n    but got  {:?}", expected, actual))
+}
+
+fn approx_eq(a: &Value, b: &Value) -> bool {
+    use Value::*;
+    match (a, b) {
+        (Nothing, Nothing) => true,
+        (Bool(a), Bool(b)) => a == b,
+        (Num(a), Num(b)) => float_eq!(a, b),
+        (Tuple(a), Tuple(b)) => a == b,
+        (Color(a), Color(b)) => a == b,
+        (M2(a), M2(b)) => a == b,
+        (M3(a), M3(b)) => a == b,
+        (M4(a), M4(b)) => a == b,
+        (Ray(a), Ray(b)) => a.origin == b.origin && a.direction == b.direction,
+        (Light(a), Light(b)) => a == b,
+        (Material(a), Material(b)) => a == b,
+        (Shape(a), Shape(b)) => a == b,
+        (Hit(a), Hit(b)) => float_eq!(a.t, b.t) && a.object == b.object,
+        // The book compares intersections to their t in early chapters
+        (Hit(a), Num(t)) => float_eq!(a.t, t),
+        (Hits(a), Hits(b)) => a.len() == b.len()
+            && a.iter().zip(b.iter()).all(|(a, b)| approx_eq(&Hit(*a), &Hit(*b))),
+        _ => false,
+    }
+}
+
+fn matrix_from_table(ctx: &mut Ctx, table: &[Vec<String>], dims: Option<(&str, &str)>) -> Result<Value> {
+    let n = table.len();
+    if let Some((rows, cols)) = dims {
+        if rows.parse() != Ok(n) || cols.parse() != Ok(n) {
+            return Err(format!("expected a {}x{} table", rows, cols));
+        }
+    }
+    let mut cells = vec![];
+    for row in table.iter() {
+        if row.len() != n {
+            return Err("matrix tables must be square".to_string());
+        }
+        for cell in row.iter() {
+            cells.push(ctx.eval_num(cell)?);
+        }
+    }
+    let c = &cells;
+    Ok(match n {
+        2 => Value::M2(matrix2(c[0], c[1], c[2], c[3])),
+        3 => Value::M3(matrix3(c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7], c[8])),
+        4 => Value::M4(matrix4(c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7],
+                               c[8], c[9], c[10], c[11], c[12], c[13], c[14], c[15])),
+        _ => return Err(format!("unsupported matrix size {}", n)),
+    })
+}
+
+fn addr(s: &dyn Shape) -> *const () {
+    s as *const dyn Shape as *const ()
+}
+
+// Built shapes, recorded by address so intersections can be traced back to
+// the arena
+type Built = Vec<(*const (), ShapeId)>;
+
+fn id_of(built: &Built, s: &dyn Shape) -> Result<ShapeId> {
+    built.iter().find(|(p, _)| *p == addr(s)).map(|(_, id)| *id)
+        .ok_or_else(|| "intersection with an unknown shape".to_string())
+}
+
+impl Ctx {
+    fn set(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    fn eval_str(&mut self, s: &str) -> Result<Value> {
+        let e = expr::parse(s)?;
+        self.eval(&e)
+    }
+
+    fn eval_num(&mut self, s: &str) -> Result<f64> {
+        num(&self.eval_str(s)?)
+    }
+
+    fn eval_m4(&mut self, s: &str) -> Result<M4> {
+        m4(&self.eval_str(s)?)
+    }
+
+    fn eval_tuple(&mut self, s: &str) -> Result<T4> {
+        tuple4(&self.eval_str(s)?)
+    }
+
+    fn shape(&mut self, s: &str) -> Result<ShapeId> {
+        match self.eval_str(s)? {
+            Value::Shape(id) => Ok(id),
+            other => Err(format!("expected a shape, found {:?}", other)),
+        }
+    }
+
+    fn world(&mut self, s: &str) -> Result<Rc<RefCell<WorldSpec>>> {
+        match self.eval_str(s)? {
+            Value::World(w) => Ok(w),
+            other => Err(format!("expected a world, found {:?}", other)),
+        }
+    }
+
+    fn children(&self, id: ShapeId) -> Result<&[ShapeId]> {
+        match &self.shapes[id].kind {
+            Kind::Group(children) => Ok(children),
+            _ => Err("expected a group".to_string()),
+        }
+    }
+
+    fn is_empty(&mut self, s: &str) -> Result<bool> {
+        match self.eval_str(s)? {
+            Value::Hits(xs) => Ok(xs.is_empty()),
+            Value::Shape(id) => Ok(self.children(id)?.is_empty()),
+            other => Err(format!("{:?} cannot be empty", other)),
+        }
+    }
+
+    fn same_shape(&self, a: ShapeId, b: ShapeId) -> bool {
+        let (a, b) = (&self.shapes[a], &self.shapes[b]);
+        std::mem::discriminant(&a.kind) == std::mem::discriminant(&b.kind)
+            && a.transform == b.transform && a.material == b.material
+    }
+
+    // Set `name.key ← value` for each row of the table
+    fn apply_table(&mut self, name: &str, table: &[Vec<String>]) -> Result<()> {
+        for row in table.iter() {
+            if row.len() != 2 {
+                return Err("expected | property | value | rows".to_string());
+            }
+            let target = expr::parse(&format!("{}.{}", name, row[0]))?;
+            let value = self.eval_str(&row[1])?;
+            self.assign(&target, value)?;
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value) -> Result<()> {
+        match target {
+            Expr::Var(name) => {
+                self.set(name, value);
+                Ok(())
+            }
+            // Update the field and store the updated value back, which
+            // changes the shape/world for handles and the variable otherwise
+            Expr::Field(base, field) => {
+                let mut current = self.eval(base)?;
+                self.set_field(&mut current, field, value)?;
+                self.assign(base, current)
+            }
+            _ => Err(format!("cannot assign to {:?}", target)),
+        }
+    }
+
+    fn add_shape(&mut self, kind: Kind) -> Value {
+        self.shapes.push(ShapeSpec {
+            kind,
+            transform: M4::IDENTITY,
+            material: Material::default(),
+            parent: None,
+            saved_ray: None,
+        });
+        Value::Shape(self.shapes.len() - 1)
+    }
+
+    // The shape on its own, ignoring any groups it is in
+    fn build_local(&self, id: ShapeId, built: &mut Built) -> Box<dyn Shape> {
+        let spec = &self.shapes[id];
+        let shape: Box<dyn Shape> = match &spec.kind {
+            Kind::Sphere => Box::new(Sphere::new(spec.transform, spec.material)),
+            Kind::Test => {
+                let mut s = TestShape::default();
+                s.set_transform(spec.transform).set_material(spec.material);
+                Box::new(s)
+            }
+            Kind::Triangle(p1, p2, p3) => {
+                let mut t = Triangle::new(*p1, *p2, *p3);
+                t.set_transform(spec.transform).set_material(spec.material);
+                Box::new(t)
+            }
+            Kind::SmoothTriangle([p1, p2, p3, n1, n2, n3]) => {
+                let mut t = Triangle::smooth(*p1, *p2, *p3, *n1, *n2, *n3);
+                t.set_transform(spec.transform).set_material(spec.material);
+                Box::new(t)
+            }
+            Kind::Group(children) => {
+                let mut g = Group::new();
+                g.set_transform(spec.transform).set_material(spec.material);
+                for &child in children.iter() {
+                    g.add_child(self.build_local(child, built));
+                }
+                Box::new(g)
+            }
+        };
+        built.push((addr(shape.as_ref()), id));
+        shape
+    }
+
+    // The shape in world space, with the transforms of its groups applied
+    fn build(&self, id: ShapeId, built: &mut Built) -> Box<dyn Shape> {
+        let mut shape = self.build_local(id, built);
+        let mut transform = M4::IDENTITY;
+        let mut parent = self.shapes[id].parent;
+        while let Some(p) = parent {
+            transform = self.shapes[p].transform * transform;
+            parent = self.shapes[p].parent;
+        }
+        shape.set_parent_transform(transform);
+        shape
+    }
+
+    fn build_world(&self, spec: &WorldSpec, built: &mut Built) -> World {
+        let objects = spec.objects.iter().map(|&id| self.build(id, built)).collect();
+        World::new(objects, spec.lights.clone())
+    }
+
+    fn to_hits(&self, xs: &Intersections, built: &Built) -> Result<Vec<Hit>> {
+        (0..xs.len()).map(|i| {
+            let x = xs[i];
+            Ok(Hit { t: x.t, object: id_of(built, x.object)?, u: x.u, v: x.v })
+        }).collect()
+    }
+
+    // Sort the intersections, and find the hit, the way the crate does
+    fn sorted_hits(&self, hits: &[Hit]) -> Result<(Vec<Hit>, Option<Hit>)> {
+        let mut built = vec![];
+        let shapes: Vec<(ShapeId, Box<dyn Shape>)> = hits.iter()
+            .map(|h| (h.object, self.build(h.object, &mut built)))
+            .collect();
+        let xs: Vec<Intersection> = hits.iter().zip(shapes.iter())
+            .map(|(h, (_, s))| Intersection::with_uv(h.t, s.as_ref(), h.u, h.v))
+            .collect();
+        let xs = Intersections::new(&xs);
+        let hit = match xs.hit() {
+            Some(x) => Some(Hit { t: x.t, object: id_of(&built, x.object)?, u: x.u, v: x.v }),
+            None => None,
+        };
+        Ok((self.to_hits(&xs, &built)?, hit))
+    }
+
+    fn eval(&mut self, e: &Expr) -> Result<Value> {
+        Ok(match e {
+            Expr::Num(x) => Value::Num(*x),
+            Expr::Var(name) => match self.vars.get(name) {
+                Some(v) => v.clone(),
+                None => match name.as_str() {
+                    "identity_matrix" => Value::M4(M4::IDENTITY),
+                    "EPSILON" => Value::Num(raytracer::consts::EPSILON),
+                    "true" => Value::Bool(true),
+                    "false" => Value::Bool(false),
+                    _ => return Err(format!("undefined variable '{}'", name)),
+                },
+            },
+            Expr::Neg(e) => match self.eval(e)? {
+                Value::Num(x) => Value::Num(-x),
+                Value::Tuple(t) => Value::Tuple(-t),
+                other => return Err(format!("cannot negate {:?}", other)),
+            },
+            Expr::Sqrt(e) => Value::Num(num(&self.eval(e)?)?.sqrt()),
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.eval(lhs)?, self.eval(rhs)?);
+                binary(*op, lhs, rhs)?
+            }
+            Expr::Tuple(items) if items.len() == 3 => {
+                Value::Color(color(num(&self.eval(&items[0])?)?, num(&self.eval(&items[1])?)?, num(&self.eval(&items[2])?)?))
+            }
+            Expr::Tuple(_) => return Err("only (r, g, b) tuples are supported".to_string()),
+            Expr::Pair(t, object) => {
+                let t = num(&self.eval(t)?)?;
+                let object = shape_id(&self.eval(object)?)?;
+                Value::Hit(Hit { t, object, u: 0.0, v: 0.0 })
+            }
+            Expr::Field(base, field) => {
+                let base = self.eval(base)?;
+                self.field(&base, field)?
+            }
+            Expr::Index(base, index) => {
+                let base = self.eval(base)?;
+                let index = index.iter().map(|i| self.eval(i).and_then(|v| num(&v)).map(|x| x as usize))
+                    .collect::<Result<Vec<usize>>>()?;
+                match (base, index.as_slice()) {
+                    (Value::Hits(xs), [i]) => Value::Hit(*xs.get(*i).ok_or(format!("no intersection {}", i))?),
+                    (Value::M2(m), [r, c]) => Value::Num(m[(*r, *c)]),
+                    (Value::M3(m), [r, c]) => Value::Num(m[(*r, *c)]),
+                    (Value::M4(m), [r, c]) => Value::Num(m[(*r, *c)]),
+                    (other, _) => return Err(format!("cannot index {:?}", other)),
+                }
+            }
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|a| self.eval(a)).collect::<Result<Vec<Value>>>()?;
+                self.call(name, &args)?
+            }
+        })
+    }
+
+    fn field(&self, value: &Value, field: &str) -> Result<Value> {
+        use Value::*;
+        let missing = || format!("{:?} has no field '{}'", value, field);
+        Ok(match (value, field) {
+            (Tuple(t), "x") => Num(t.x),
+            (Tuple(t), "y") => Num(t.y),
+            (Tuple(t), "z") => Num(t.z),
+            (Tuple(t), "w") => Num(t.w),
+            (Color(c), "red") => Num(c.red),
+            (Color(c), "green") => Num(c.green),
+            (Color(c), "blue") => Num(c.blue),
+            (Ray(r), "origin") => Tuple(r.origin),
+            (Ray(r), "direction") => Tuple(r.direction),
+            (Light(l), "position") => Tuple(l.pos),
+            (Light(l), "intensity") => Color(l.intensity),
+            (Material(m), "color") => Color(m.color),
+            (Material(m), "ambient") => Num(m.ambient),
+            (Material(m), "diffuse") => Num(m.diffuse),
+            (Material(m), "specular") => Num(m.specular),
+            (Material(m), "shininess") => Num(m.shininess),
+            (Shape(id), "transform") => M4(self.shapes[*id].transform),
+            (Shape(id), "material") => Material(self.shapes[*id].material),
+            (Shape(id), "saved_ray") => Ray(self.shapes[*id].saved_ray.ok_or("the shape has not been intersected")?),
+            (Shape(id), "p1" | "p2" | "p3" | "e1" | "e2" | "normal") => {
+                let t = match self.shapes[*id].kind {
+                    Kind::Triangle(p1, p2, p3) => Triangle::new(p1, p2, p3),
+                    Kind::SmoothTriangle([p1, p2, p3, ..]) => Triangle::new(p1, p2, p3),
+                    _ => return Err(missing()),
+                };
+                Tuple(match field {
+                    "p1" => t.p1,
+                    "p2" => t.p2,
+                    "p3" => t.p3,
+                    "e1" => t.e1(),
+                    "e2" => t.e2(),
+                    _ => t.local_normal_at(t.p1),
+                })
+            }
+            (Shape(id), "n1" | "n2" | "n3") => match self.shapes[*id].kind {
+                Kind::SmoothTriangle(p) => Tuple(p[3 + field[1..].parse::<usize>().unwrap() - 1]),
+                _ => return Err(missing()),
+            },
+            (Hit(h), "t") => Num(h.t),
+            (Hit(h), "object") => Shape(h.object),
+            (Hit(h), "u") => Num(h.u),
+            (Hit(h), "v") => Num(h.v),
+            (Hits(xs), "count") => Num(xs.len() as f64),
+            (Comps(c), "t") => Num(c.t),
+            (Comps(c), "object") => Shape(c.object),
+            (Comps(c), "point") => Tuple(c.point),
+            (Comps(c), "over_point") => Tuple(c.over_point),
+            (Comps(c), "eyev") => Tuple(c.eyev),
+            (Comps(c), "normalv") => Tuple(c.normalv),
+            (Comps(c), "inside") => Bool(c.inside),
+            (World(w), "light") => w.borrow().lights.first().map_or(Nothing, |l| Light(*l)),
+            (Camera(c), "hsize") => Num(c.hsize as f64),
+            (Camera(c), "vsize") => Num(c.vsize as f64),
+            (Camera(c), "field_of_view") => Num(c.field_of_view),
+            (Camera(c), "transform") => M4(c.transform()),
+            (Camera(c), "pixel_size") => Num(c.pixel_size()),
+            (Canvas(c), "width") => Num(c.borrow().get_width() as f64),
+            (Canvas(c), "height") => Num(c.borrow().get_height() as f64),
+            _ => return Err(missing()),
+        })
+    }
+
+    fn set_field(&mut self, target: &mut Value, field: &str, value: Value) -> Result<()> {
+        match (target, field) {
+            (Value::Shape(id), "transform") => self.shapes[*id].transform = m4(&value)?,
+            (Value::Shape(id), "material") => self.shapes[*id].material = material(&value)?,
+            (Value::Material(m), "color") => m.color = color_of(&value)?,
+            (Value::Material(m), "ambient") => m.ambient = num(&value)?,
+            (Value::Material(m), "diffuse") => m.diffuse = num(&value)?,
+            (Value::Material(m), "specular") => m.specular = num(&value)?,
+            (Value::Material(m), "shininess") => m.shininess = num(&value)?,
+            (Value::World(w), "light") => w.borrow_mut().lights = vec![light(&value)?],
+            (Value::Camera(c), "transform") => { c.set_transform(m4(&value)?); }
+            (target, _) => return Err(format!("cannot set '{}' on {:?}", field, target)),
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &str, args: &[Value]) -> Result<Value> {
+        use Value::*;
+        let arity = |n: usize| if args.len() == n { Ok(()) } else {
+            Err(format!("{}() takes {} arguments, found {}", name, n, args.len()))
+        };
+        let n = |i: usize| num(&args[i]);
+        let t = |i: usize| tuple4(&args[i]);
+
+        Ok(match name {
+            "tuple" => { arity(4)?; Tuple(tuple(n(0)?, n(1)?, n(2)?, n(3)?)) }
+            "point" => { arity(3)?; Tuple(point(n(0)?, n(1)?, n(2)?)) }
+            "vector" => { arity(3)?; Tuple(vector(n(0)?, n(1)?, n(2)?)) }
+            "color" => { arity(3)?; Color(color(n(0)?, n(1)?, n(2)?)) }
+            "magnitude" => { arity(1)?; Num(t(0)?.mag()) }
+            "normalize" => { arity(1)?; Tuple(t(0)?.normalize()) }
+            "dot" => { arity(2)?; Num(t(0)?.dot(t(1)?)) }
+            "cross" => { arity(2)?; Tuple(t(0)?.cross(t(1)?)) }
+            "reflect" => { arity(2)?; Tuple(t(0)?.reflect(t(1)?)) }
+
+            "transpose" => { arity(1)?; match &args[0] {
+                M2(m) => M2(m.transpose()),
+                M3(m) => M3(m.transpose()),
+                other => M4(m4(other)?.transpose()),
+            }}
+            "determinant" => { arity(1)?; Num(match &args[0] {
+                M2(m) => m.determinant(),
+                M3(m) => m.determinant(),
+                other => m4(other)?.determinant(),
+            })}
+            "submatrix" => { arity(3)?; let (r, c) = (n(1)? as usize, n(2)? as usize); match &args[0] {
+                M3(m) => M2(m.submatrix(r, c)),
+                other => M3(m4(other)?.submatrix(r, c)),
+            }}
+            "minor" => { arity(3)?; let (r, c) = (n(1)? as usize, n(2)? as usize); Num(match &args[0] {
+                M3(m) => m.minor(r, c),
+                other => m4(other)?.minor(r, c),
+            })}
+            "cofactor" => { arity(3)?; let (r, c) = (n(1)? as usize, n(2)? as usize); Num(match &args[0] {
+                M3(m) => m.cofactor(r, c),
+                other => m4(other)?.cofactor(r, c),
+            })}
+            "inverse" => { arity(1)?; M4(m4(&args[0])?.inverse()) }
+            "translation" => { arity(3)?; M4(translation(n(0)?, n(1)?, n(2)?)) }
+            "scaling" => { arity(3)?; M4(scaling(n(0)?, n(1)?, n(2)?)) }
+            "rotation_x" => { arity(1)?; M4(rotation_x(n(0)?)) }
+            "rotation_y" => { arity(1)?; M4(rotation_y(n(0)?)) }
+            "rotation_z" => { arity(1)?; M4(rotation_z(n(0)?)) }
+            "shearing" => { arity(6)?; M4(shearing(n(0)?, n(1)?, n(2)?, n(3)?, n(4)?, n(5)?)) }
+            "view_transform" => { arity(3)?; M4(view_transform(t(0)?, t(1)?, t(2)?)) }
+
+            "ray" => { arity(2)?; Ray(raytracer::ray::Ray::new(t(0)?, t(1)?)) }
+            "position" => { arity(2)?; Tuple(ray(&args[0])?.at(n(1)?)) }
+            "transform" => { arity(2)?; Ray(m4(&args[1])? * ray(&args[0])?) }
+
+            "sphere" => { arity(0)?; self.add_shape(Kind::Sphere) }
+            "test_shape" => { arity(0)?; self.add_shape(Kind::Test) }
+            "group" => { arity(0)?; self.add_shape(Kind::Group(vec![])) }
+            "triangle" => { arity(3)?; self.add_shape(Kind::Triangle(t(0)?, t(1)?, t(2)?)) }
+            "smooth_triangle" => {
+                arity(6)?;
+                self.add_shape(Kind::SmoothTriangle([t(0)?, t(1)?, t(2)?, t(3)?, t(4)?, t(5)?]))
+            }
+            "set_transform" => {
+                arity(2)?;
+                self.shapes[shape_id(&args[0])?].transform = m4(&args[1])?;
+                Nothing
+            }
+            "add_child" => {
+                arity(2)?;
+                let (g, child) = (shape_id(&args[0])?, shape_id(&args[1])?);
+                match &mut self.shapes[g].kind {
+                    Kind::Group(children) => children.push(child),
+                    _ => return Err("add_child() needs a group".to_string()),
+                }
+                self.shapes[child].parent = Some(g);
+                Nothing
+            }
+            "normal_at" => {
+                arity(2)?;
+                let s = self.build(shape_id(&args[0])?, &mut vec![]);
+                Tuple(s.normal_at(t(1)?))
+            }
+            "local_normal_at" => {
+                arity(2)?;
+                let s = self.build_local(shape_id(&args[0])?, &mut vec![]);
+                Tuple(s.local_normal_at(t(1)?))
+            }
+            "intersect" | "local_intersect" => {
+                arity(2)?;
+                let (id, r) = (shape_id(&args[0])?, ray(&args[1])?);
+                if let Kind::Test = self.shapes[id].kind {
+                    let spec = &self.shapes[id];
+                    let mut s = TestShape::default();
+                    s.set_transform(spec.transform).set_material(spec.material);
+                    s.intersect(r, &mut Intersections::empty());
+                    let saved = *s.saved_ray.lock().unwrap();
+                    self.shapes[id].saved_ray = Some(saved);
+                    return Ok(Hits(vec![]));
+                }
+                let mut built = vec![];
+                let s = if name == "intersect" { self.build(id, &mut built) } else { self.build_local(id, &mut built) };
+                let mut xs = Intersections::empty();
+                if name == "intersect" { s.intersect(r, &mut xs) } else { s.local_intersect(r, &mut xs) }
+                Hits(self.to_hits(&xs, &built)?)
+            }
+
+            "intersection" => { arity(2)?; Hit(self::Hit { t: n(0)?, object: shape_id(&args[1])?, u: 0.0, v: 0.0 }) }
+            "intersection_with_uv" => {
+                arity(4)?;
+                Hit(self::Hit { t: n(0)?, object: shape_id(&args[1])?, u: n(2)?, v: n(3)? })
+            }
+            "intersections" => {
+                let hits = args.iter().map(|a| match a {
+                    Hit(h) => Ok(*h),
+                    other => Err(format!("expected an intersection, found {:?}", other)),
+                }).collect::<Result<Vec<_>>>()?;
+                Hits(self.sorted_hits(&hits)?.0)
+            }
+            "hit" => {
+                arity(1)?;
+                match &args[0] {
+                    Hits(xs) => self.sorted_hits(xs)?.1.map_or(Nothing, Hit),
+                    other => return Err(format!("expected intersections, found {:?}", other)),
+                }
+            }
+            // The crate doesn't use the list of intersections (for
+            // refraction) so a third argument is accepted and ignored
+            "prepare_computations" => {
+                if args.len() != 2 && args.len() != 3 { arity(2)?; }
+                let h = match &args[0] {
+                    Hit(h) => *h,
+                    other => return Err(format!("expected an intersection, found {:?}", other)),
+                };
+                let s = self.build(h.object, &mut vec![]);
+                let c = Intersection::with_uv(h.t, s.as_ref(), h.u, h.v).prepare_computations(ray(&args[1])?);
+                Comps(self::Comps {
+                    t: c.t, object: h.object, point: c.point, over_point: c.over_point,
+                    eyev: c.eyev, normalv: c.normalv, inside: c.inside, time: c.time,
+                })
+            }
+
+            "point_light" => { arity(2)?; Light(raytracer::light::Light::new(t(0)?, color_of(&args[1])?)) }
+            "material" => { arity(0)?; Material(raytracer::material::Material::default()) }
+            "lighting" => {
+                arity(6)?;
+                let in_shadow = match args[5] { Bool(b) => b, _ => return Err("in_shadow must be a boolean".to_string()) };
+                Color(material(&args[0])?.lighting(light(&args[1])?, t(2)?, t(3)?, t(4)?, in_shadow))
+            }
+
+            "world" => { arity(0)?; World(Rc::new(RefCell::new(WorldSpec::default()))) }
+            "default_world" => {
+                arity(0)?;
+                let simple = raytracer::world::World::simple();
+                let mut spec = WorldSpec { objects: vec![], lights: simple.lights.clone() };
+                for o in simple.objects.iter() {
+                    let id = shape_id(&self.add_shape(Kind::Sphere))?;
+                    self.shapes[id].transform = o.transform();
+                    self.shapes[id].material = o.material();
+                    spec.objects.push(id);
+                }
+                World(Rc::new(RefCell::new(spec)))
+            }
+            "intersect_world" => {
+                arity(2)?;
+                let spec = world_spec(&args[0])?;
+                let mut built = vec![];
+                let w = self.build_world(&spec, &mut built);
+                let mut xs = Intersections::empty();
+                w.intersect(ray(&args[1])?, &mut xs);
+                Hits(self.to_hits(&xs, &built)?)
+            }
+            "shade_hit" => {
+                arity(2)?;
+                let c = match &args[1] {
+                    Comps(c) => *c,
+                    other => return Err(format!("expected computations, found {:?}", other)),
+                };
+                let spec = world_spec(&args[0])?;
+                let mut built = vec![];
+                let w = self.build_world(&spec, &mut built);
+                let alone;
+                let object = match w.objects.iter().find(|o| id_of(&built, o.as_ref()) == Ok(c.object)) {
+                    Some(o) => o.as_ref(),
+                    None => {
+                        alone = self.build(c.object, &mut vec![]);
+                        alone.as_ref()
+                    }
+                };
+                let comps = Computations {
+                    t: c.t, object, point: c.point, over_point: c.over_point,
+                    eyev: c.eyev, normalv: c.normalv, inside: c.inside, time: c.time,
+                };
+                Color(w.shade_hit(comps, &mut Intersections::empty()))
+            }
+            "color_at" => {
+                arity(2)?;
+                let w = self.build_world(&world_spec(&args[0])?, &mut vec![]);
+                Color(w.color_at(ray(&args[1])?, &mut Intersections::empty(), &mut Intersections::empty()))
+            }
+            "is_shadowed" => {
+                arity(2)?;
+                let w = self.build_world(&world_spec(&args[0])?, &mut vec![]);
+                Bool(w.is_shadowed(t(1)?, 0.0, &mut Intersections::empty()))
+            }
+
+            "camera" => { arity(3)?; Camera(Box::new(raytracer::camera::Camera::simple(n(0)? as usize, n(1)? as usize, n(2)?))) }
+            "ray_for_pixel" => {
+                arity(3)?;
+                Ray(camera(&args[0])?.ray_for_pixel(n(1)? as usize, n(2)? as usize))
+            }
+            "render" => {
+                arity(2)?;
+                let w = self.build_world(&world_spec(&args[1])?, &mut vec![]);
+                Canvas(Rc::new(RefCell::new(camera(&args[0])?.render(&w))))
+            }
+            "canvas" => { arity(2)?; Canvas(Rc::new(RefCell::new(raytracer::canvas::Canvas::new(n(0)? as usize, n(1)? as usize)))) }
+            "pixel_at" => { arity(3)?; Color(canvas(&args[0])?.borrow().pixel_at(n(1)? as i32, n(2)? as i32)) }
+            "write_pixel" => {
+                arity(4)?;
+                canvas(&args[0])?.borrow_mut().write_pixel(n(1)? as i32, n(2)? as i32, color_of(&args[3])?);
+                Nothing
+            }
+            _ => return Err(format!("unknown function {}()", name)),
+        })
+    }
+}
+
+fn binary(op: char, lhs: Value, rhs: Value) -> Result<Value> {
+    use Value::*;
+    Ok(match (op, &lhs, &rhs) {
+        ('+', Num(a), Num(b)) => Num(a + b),
+        ('-', Num(a), Num(b)) => Num(a - b),
+        ('*', Num(a), Num(b)) => Num(a * b),
+        ('/', Num(a), Num(b)) => Num(a / b),
+        ('+', Tuple(a), Tuple(b)) => Tuple(*a + *b),
+        ('-', Tuple(a), Tuple(b)) => Tuple(*a - *b),
+        ('*', Tuple(a), Num(b)) => Tuple(*a * *b),
+        ('*', Num(a), Tuple(b)) => Tuple(*a * *b),
+        ('/', Tuple(a), Num(b)) => Tuple(*a / *b),
+        ('+', Color(a), Color(b)) => Color(*a + *b),
+        ('-', Color(a), Color(b)) => Color(*a - *b),
+        ('*', Color(a), Color(b)) => Color(*a * *b),
+        ('*', Color(a), Num(b)) => Color(*a * *b),
+        ('*', Num(a), Color(b)) => Color(*a * *b),
+        ('/', Color(a), Num(b)) => Color(*a / *b),
+        ('*', M2(a), M2(b)) => M2(*a * *b),
+        ('*', M3(a), M3(b)) => M3(*a * *b),
+        ('*', M4(a), M4(b)) => M4(*a * *b),
+        ('*', M4(a), Tuple(b)) => Tuple(*a * *b),
+        _ => return Err(format!("cannot evaluate {:?} {} {:?}", lhs, op, rhs)),
+    })
+}
+
+fn num(v: &Value) -> Result<f64> {
+    match v {
+        Value::Num(x) => Ok(*x),
+        other => Err(format!("expected a number, found {:?}", other)),
+    }
+}
+
+fn tuple4(v: &Value) -> Result<T4> {
+    match v {
+        Value::Tuple(t) => Ok(*t),
+        other => Err(format!("expected a tuple, found {:?}", other)),
+    }
+}
+
+fn color_of(v: &Value) -> Result<Color> {
+    match v {
+        Value::Color(c) => Ok(*c),
+        other => Err(format!("expected a color, found {:?}", other)),
+    }
+}
+
+fn m4(v: &Value) -> Result<M4> {
+    match v {
+        Value::M4(m) => Ok(*m),
+        other => Err(format!("expected a 4x4 matrix, found {:?}", other)),
+    }
+}
+
+fn ray(v: &Value) -> Result<Ray> {
+    match v {
+        Value::Ray(r) => Ok(*r),
+        other => Err(format!("expected a ray, found {:?}", other)),
+    }
+}
+
+fn light(v: &Value) -> Result<Light> {
+    match v {
+        Value::Light(l) => Ok(*l),
+        other => Err(format!("expected a light, found {:?}", other)),
+    }
+}
+
+fn material(v: &Value) -> Result<Material> {
+    match v {
+        Value::Material(m) => Ok(*m),
+        other => Err(format!("expected a material, found {:?}", other)),
+    }
+}
+
+fn shape_id(v: &Value) -> Result<ShapeId> {
+    match v {
+        Value::Shape(id) => Ok(*id),
+        other => Err(format!("expected a shape, found {:?}", other)),
+    }
+}
+
+fn world_spec(v: &Value) -> Result<WorldSpec> {
+    match v {
+        Value::World(w) => Ok(w.borrow().clone()),
+        other => Err(format!("expected a world, found {:?}", other)),
+    }
+}
+
+fn camera(v: &Value) -> Result<Camera> {
+    match v {
+        Value::Camera(c) => Ok(**c),
+        other => Err(format!("expected a camera, found {:?}", other)),
+    }
+}
+
+fn canvas(v: &Value) -> Result<Rc<RefCell<Canvas>>> {
+    match v {
+        Value::Canvas(c) => Ok(c.clone()),
+        other => Err(format!("expected a canvas, found {:?}", other)),
+    }
+}
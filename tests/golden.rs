@@ -0,0 +1,131 @@
+// Image level regression tests. Each scene is rendered small and compared
+// with the reference in tests/golden; failures leave an expected | actual |
+// difference image in target/tmp/golden. After an intended change in
+// shading, regenerate the references with
+//   GOLDEN_UPDATE=1 cargo test --test golden
+mod regression;
+
+use regression::Golden;
+use raytracer::prelude::*;
+use std::f64::consts::PI;
+
+fn golden() -> Golden {
+    Golden::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"),
+                concat!(env!("CARGO_TARGET_TMPDIR"), "/golden"))
+}
+
+fn check(name: &str, camera: Camera, world: &World) {
+    check_with(&golden(), name, camera, world);
+}
+
+// A path traced pixel follows a long chain of random bounces, and a last bit
+// of difference in the maths library of another platform can send one of
+// them elsewhere. A few pixels may then differ noticeably while the image as
+// a whole hasn't changed.
+fn check_path_traced(name: &str, camera: Camera, world: &World) {
+    check_with(golden().tolerance(2.0).max_bad_pixels(8), name, camera, world);
+}
+
+fn check_with(golden: &Golden, name: &str, camera: Camera, world: &World) {
+    if let Err(e) = golden.check_render(name, camera, world) {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+fn default_world() {
+    let camera = Camera::new(48, 48, PI / 3.0,
+                             view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0)));
+    check("default_world", camera, &World::simple());
+}
+
+// The image version of `shade_intersection_from_inside`. There the light is
+// inside the inner sphere and everything is in shadow; here it is moved up
+// between the spheres so that both the inside of the outer sphere (with its
+// flipped normals) and the top of the inner sphere are lit
+#[test]
+fn default_world_lit_from_inside() {
+    let mut world = World::simple();
+    world.lights = vec![Light::new(point(0.0, 0.75, 0.0), color(1.0, 1.0, 1.0))];
+    let camera = Camera::new(48, 48, PI / 2.0,
+                             view_transform(point(0.0, 0.0, -0.9), point(0.0, 0.0, 0.0), vector(0.0, 1.0, 0.0)));
+    check("default_world_lit_from_inside", camera, &world);
+}
+
+#[test]
+fn spheres_scene() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/spheres.yml")).unwrap();
+    let camera = Camera::new(96, 54, scene.camera.field_of_view, scene.camera.transform());
+    check("spheres", camera, &scene.world);
+}
+
+#[test]
+fn antialiased_triangles() {
+    let mut world = World::default();
+    world.lights.push(Light::new(point(-5.0, 5.0, -5.0), color(1.0, 1.0, 1.0)));
+    let material = Material { color: color(0.3, 0.6, 0.9), ..Material::default() };
+    for (i, x) in [-1.2, 0.0, 1.2].iter().enumerate() {
+        let mut t = Triangle::new(point(*x, 1.0, i as f64 * 0.5), point(x - 0.6, -0.6, 0.0), point(x + 0.6, -0.6, 0.0));
+        t.material = material;
+        world.add_object(t);
+    }
+    let mut camera = Camera::new(48, 32, PI / 3.0,
+                                 view_transform(point(0.0, 0.0, -5.0), point(0.0, 0.2, 0.0), vector(0.0, 1.0, 0.0)));
+    camera.samples = 4;
+    check("antialiased_triangles", camera, &world);
+}
+
+// Every pixel seeds its own random numbers, so path traced images are
+// reproducible and can be compared like any other
+#[test]
+fn path_traced_room() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/room.yml")).unwrap();
+    let mut camera = Camera::new(32, 32, scene.camera.field_of_view, scene.camera.transform());
+    camera.integrator = scene.camera.integrator;
+    camera.samples = 16;
+    check_path_traced("path_traced_room", camera, &scene.world);
+}
+
+#[test]
+fn adaptive_room() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/room.yml")).unwrap();
+    let mut camera = Camera::new(32, 32, scene.camera.field_of_view, scene.camera.transform());
+    camera.integrator = scene.camera.integrator;
+    camera.samples = 8;
+    camera.max_samples = 64;
+    camera.noise_threshold = 0.02;
+    check_path_traced("adaptive_room", camera, &scene.world);
+}
+
+#[test]
+fn light_panel() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/light_panel.yml")).unwrap();
+    let mut camera = Camera::new(40, 30, scene.camera.field_of_view, scene.camera.transform());
+    camera.samples = 4;
+    check("light_panel", camera, &scene.world);
+}
+
+#[test]
+fn metallic_roughness() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/metallic_roughness.yml")).unwrap();
+    let mut camera = Camera::new(64, 32, scene.camera.field_of_view, scene.camera.transform());
+    camera.samples = 4;
+    check("metallic_roughness", camera, &scene.world);
+}
+
+#[test]
+fn shading_models() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/shading_models.yml")).unwrap();
+    let mut camera = Camera::new(72, 30, scene.camera.field_of_view, scene.camera.transform());
+    camera.samples = 4;
+    check("shading_models", camera, &scene.world);
+}
+
+#[test]
+fn ambient_occlusion() {
+    let scene = Scene::load(concat!(env!("CARGO_MANIFEST_DIR"), "/scenes/shading_models.yml")).unwrap();
+    let mut camera = Camera::new(72, 30, scene.camera.field_of_view, scene.camera.transform());
+    camera.integrator = Integrator::AmbientOcclusion;
+    camera.samples = 4;
+    check("ambient_occlusion", camera, &scene.world);
+}
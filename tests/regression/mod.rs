@@ -0,0 +1,396 @@
+// Golden image regression testing. A render is compared pixel by pixel with
+// a stored reference; when too many pixels differ, the expected image, the
+// actual image and a heatmap of the differences are written side by side so
+// the change can be inspected.
+//
+// References are stored as PFM so that no precision is lost. Running with
+// GOLDEN_UPDATE=1 (re)writes them from the current renders.
+#![allow(clippy::needless_return)]
+use raytracer::prelude::*;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Metric {
+    // Largest difference of any sRGB encoded channel, in 0..=1
+    MaxChannel,
+    // CIE76 distance in L*a*b*, where about 2.3 is just noticeable
+    DeltaE,
+}
+
+impl Metric {
+    // Both colours are linear and already tone mapped into 0..=1
+    pub fn difference(self, a: Color, b: Color) -> f64 {
+        match self {
+            Metric::MaxChannel => {
+                let d = |x: f64, y: f64| (Transfer::Srgb.encode(x) - Transfer::Srgb.encode(y)).abs();
+                d(a.red, b.red).max(d(a.green, b.green)).max(d(a.blue, b.blue))
+            }
+            Metric::DeltaE => {
+                let (a, b) = (to_lab(a), to_lab(b));
+                ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+            }
+        }
+    }
+}
+
+// Linear sRGB to CIE L*a*b* under a D65 white point
+fn to_lab(c: Color) -> [f64; 3] {
+    let x = (0.4124564 * c.red + 0.3575761 * c.green + 0.1804375 * c.blue) / 0.95047;
+    let y = 0.2126729 * c.red + 0.7151522 * c.green + 0.0721750 * c.blue;
+    let z = (0.0193339 * c.red + 0.1191920 * c.green + 0.9503041 * c.blue) / 1.08883;
+
+    let delta: f64 = 6.0 / 29.0;
+    let f = |t: f64| if t > delta.powi(3) {
+        t.cbrt()
+    } else {
+        t / (3.0 * delta * delta) + 4.0 / 29.0
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    return [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)];
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tolerance {
+    pub metric: Metric,
+    // The largest difference a pixel may have and still count as matching
+    pub per_pixel: f64,
+    // How many pixels may exceed `per_pixel` before the images differ
+    pub max_bad_pixels: usize,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self { metric: Metric::DeltaE, per_pixel: 1.0, max_bad_pixels: 0 }
+    }
+}
+
+// The per pixel differences between two images of the same size
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub width: usize,
+    pub height: usize,
+    pub differences: Vec<f64>,
+    pub max_difference: f64,
+}
+
+impl Comparison {
+    pub fn bad_pixels(&self, per_pixel: f64) -> usize {
+        self.differences.iter().filter(|&&d| d > per_pixel).count()
+    }
+
+    pub fn passes(&self, tolerance: Tolerance) -> bool {
+        self.bad_pixels(tolerance.per_pixel) <= tolerance.max_bad_pixels
+    }
+
+    // Black where the images agree, through blue and green (at the
+    // tolerance) to yellow and red for pixels well beyond it
+    pub fn heatmap(&self, per_pixel: f64) -> Canvas {
+        let scale = if per_pixel > 0.0 { 2.0 * per_pixel } else { self.max_difference.max(f64::MIN_POSITIVE) };
+        let mut canvas = Canvas::new(self.width, self.height);
+        for (i, &d) in self.differences.iter().enumerate() {
+            if d > 0.0 {
+                canvas.write_pixel((i % self.width) as i32, (i / self.width) as i32, heat(d / scale));
+            }
+        }
+        return canvas;
+    }
+}
+
+fn heat(t: f64) -> Color {
+    const STOPS: [(f64, f64, f64); 5] = [
+        (0.0, 0.0, 0.2),
+        (0.0, 0.0, 1.0),
+        (0.0, 1.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (1.0, 0.0, 0.0),
+    ];
+    let x = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (x.floor() as usize).min(STOPS.len() - 2);
+    let f = x - i as f64;
+    let (a, b) = (STOPS[i], STOPS[i + 1]);
+    return color(a.0 + (b.0 - a.0) * f, a.1 + (b.1 - a.1) * f, a.2 + (b.2 - a.2) * f);
+}
+
+// Compare two images of the same size after tone mapping both
+pub fn compare(expected: &Canvas, actual: &Canvas, tone_map: ToneMap, metric: Metric) -> Comparison {
+    assert_eq!((expected.get_width(), expected.get_height()), (actual.get_width(), actual.get_height()),
+               "images must have the same size");
+    let (width, height) = (expected.get_width(), expected.get_height());
+    let map = |c: Color| color(tone_map.map(c.red), tone_map.map(c.green), tone_map.map(c.blue));
+
+    let mut differences = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            differences.push(metric.difference(map(expected.pixel_at(x, y)), map(actual.pixel_at(x, y))));
+        }
+    }
+    let max_difference = differences.iter().copied().fold(0.0, f64::max);
+    return Comparison { width: width as usize, height: height as usize, differences, max_difference };
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(io::Error),
+    Missing { name: String, reference: PathBuf, report: PathBuf },
+    SizeMismatch { name: String, expected: (usize, usize), actual: (usize, usize), report: PathBuf },
+    Mismatch { name: String, bad_pixels: usize, per_pixel: f64, max_difference: f64, report: PathBuf },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Io(e) => write!(f, "{}", e),
+            GoldenError::Missing { name, reference, report } =>
+                write!(f, "golden '{}': no reference image at {}; the render was written to {} \
+                           (run with GOLDEN_UPDATE=1 to accept it)",
+                       name, reference.display(), report.display()),
+            GoldenError::SizeMismatch { name, expected, actual, report } =>
+                write!(f, "golden '{}': expected a {}x{} image but rendered {}x{}; the render was written to {}",
+                       name, expected.0, expected.1, actual.0, actual.1, report.display()),
+            GoldenError::Mismatch { name, bad_pixels, per_pixel, max_difference, report } =>
+                write!(f, "golden '{}': {} pixels differ by more than {} (max {:.4}); \
+                           expected, actual and difference written to {}",
+                       name, bad_pixels, per_pixel, max_difference, report.display()),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl From<io::Error> for GoldenError {
+    fn from(e: io::Error) -> Self { GoldenError::Io(e) }
+}
+
+// Checks renders against the references in one directory, writing failure
+// reports to another
+#[derive(Clone, Debug)]
+pub struct Golden {
+    references: PathBuf,
+    output: PathBuf,
+    tolerance: Tolerance,
+    update: bool,
+}
+
+impl Golden {
+    pub fn new(references: &str, output: &str) -> Self {
+        let update = std::env::var_os("GOLDEN_UPDATE").is_some_and(|v| v != "0" && !v.is_empty());
+        Self {
+            references: PathBuf::from(references),
+            output: PathBuf::from(output),
+            tolerance: Tolerance::default(),
+            update,
+        }
+    }
+
+    pub fn tolerance(&mut self, per_pixel: f64) -> &mut Self {
+        self.tolerance.per_pixel = per_pixel;
+        self
+    }
+
+    pub fn max_bad_pixels(&mut self, max_bad_pixels: usize) -> &mut Self {
+        self.tolerance.max_bad_pixels = max_bad_pixels;
+        self
+    }
+
+    // Overwrite the references instead of comparing against them
+    pub fn update(&mut self, update: bool) -> &mut Self {
+        self.update = update;
+        self
+    }
+
+    pub fn reference_path(&self, name: &str) -> PathBuf {
+        self.references.join(format!("{}.pfm", name))
+    }
+
+    pub fn check(&self, name: &str, actual: &Canvas) -> Result<Comparison, GoldenError> {
+        let reference = self.reference_path(name);
+        if self.update {
+            fs::create_dir_all(&self.references)?;
+            write_pfm(&reference, actual)?;
+        }
+
+        if !reference.exists() {
+            let report = self.write_actual(name, actual)?;
+            return Err(GoldenError::Missing { name: name.to_string(), reference, report });
+        }
+
+        let expected = Canvas::read_pfm(&mut BufReader::new(File::open(&reference)?))?;
+        let size = |c: &Canvas| (c.get_width() as usize, c.get_height() as usize);
+        if size(&expected) != size(actual) {
+            let report = self.write_actual(name, actual)?;
+            return Err(GoldenError::SizeMismatch {
+                name: name.to_string(), expected: size(&expected), actual: size(actual), report,
+            });
+        }
+
+        let comparison = compare(&expected, actual, ToneMap::Clamp, self.tolerance.metric);
+        if comparison.passes(self.tolerance) {
+            self.remove_report(name);
+            return Ok(comparison);
+        }
+
+        self.write_actual(name, actual)?;
+        let encoding = Encoding::display(ToneMap::Clamp);
+        let heatmap = comparison.heatmap(self.tolerance.per_pixel);
+        let report = self.output.join(format!("{}.png", name));
+        let panels = side_by_side(&[encode(&expected, encoding), encode(actual, encoding), heatmap]);
+        fs::write(&report, panels.to_png(BitDepth::Eight, Encoding::default()))?;
+
+        return Err(GoldenError::Mismatch {
+            name: name.to_string(),
+            bad_pixels: comparison.bad_pixels(self.tolerance.per_pixel),
+            per_pixel: self.tolerance.per_pixel,
+            max_difference: comparison.max_difference,
+            report,
+        });
+    }
+
+    pub fn check_render(&self, name: &str, camera: Camera, world: &World) -> Result<Comparison, GoldenError> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.check(name, &camera.render_with(world, threads, |_| ()))
+    }
+
+    // The render is kept both as PFM, to be copied over the reference if the
+    // change is intended, and as PNG for viewing
+    fn write_actual(&self, name: &str, actual: &Canvas) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.output)?;
+        write_pfm(&self.output.join(format!("{}.actual.pfm", name)), actual)?;
+        let png = self.output.join(format!("{}.actual.png", name));
+        fs::write(&png, actual.to_png(BitDepth::Eight, Encoding::display(ToneMap::Clamp)))?;
+        return Ok(png);
+    }
+
+    // Reports from an earlier failing run are stale once the image matches
+    fn remove_report(&self, name: &str) {
+        for suffix in [".png", ".actual.png", ".actual.pfm"] {
+            let _ = fs::remove_file(self.output.join(format!("{}{}", name, suffix)));
+        }
+    }
+}
+
+fn write_pfm(path: &Path, canvas: &Canvas) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    canvas.write_pfm(&mut out)?;
+    out.flush()
+}
+
+fn encode(canvas: &Canvas, encoding: Encoding) -> Canvas {
+    let mut encoded = canvas.clone();
+    for y in 0..canvas.get_height() {
+        for x in 0..canvas.get_width() {
+            encoded.write_pixel(x, y, encoding.apply(canvas.pixel_at(x, y)));
+        }
+    }
+    return encoded;
+}
+
+// Place images of the same height in a row, separated by a grey gap
+fn side_by_side(panels: &[Canvas]) -> Canvas {
+    const GAP: i32 = 4;
+    let height = panels.iter().map(|p| p.get_height()).max().unwrap_or(0);
+    let width = panels.iter().map(|p| p.get_width()).sum::<i32>() + GAP * (panels.len() as i32 - 1).max(0);
+
+    let mut canvas = Canvas::new_with_color(width as usize, height as usize, color(0.5, 0.5, 0.5));
+    let mut left = 0;
+    for panel in panels {
+        for y in 0..panel.get_height() {
+            for x in 0..panel.get_width() {
+                canvas.write_pixel(left + x, y, panel.pixel_at(x, y));
+            }
+        }
+        left += panel.get_width() + GAP;
+    }
+    return canvas;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("raytracer-golden-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        return dir;
+    }
+
+    fn golden(dir: &Path) -> Golden {
+        let mut g = Golden::new(dir.join("refs").to_str().unwrap(), dir.join("out").to_str().unwrap());
+        g.update(false);
+        return g;
+    }
+
+    #[test]
+    fn delta_e_black_and_white() {
+        assert!((Metric::DeltaE.difference(Color::BLACK, Color::WHITE) - 100.0).abs() < 0.01);
+        assert_eq!(Metric::DeltaE.difference(color(0.2, 0.4, 0.6), color(0.2, 0.4, 0.6)), 0.0);
+        // Equal linear steps are far more visible in the shadows
+        let dark = Metric::DeltaE.difference(color(0.01, 0.01, 0.01), color(0.02, 0.02, 0.02));
+        let bright = Metric::DeltaE.difference(color(0.8, 0.8, 0.8), color(0.81, 0.81, 0.81));
+        assert!(dark > 3.0 * bright);
+    }
+
+    #[test]
+    fn max_channel_compares_srgb_values() {
+        assert!(float_eq!(Metric::MaxChannel.difference(Color::BLACK, color(0.0, 1.0, 0.0)), 1.0));
+        assert!(float_eq!(Metric::MaxChannel.difference(color(0.5, 0.5, 0.5), color(0.5, 0.5, 0.5)), 0.0));
+    }
+
+    #[test]
+    fn compare_counts_pixels_over_tolerance() {
+        let a = Canvas::new_with_color(4, 4, color(0.5, 0.5, 0.5));
+        let mut b = a.clone();
+        b.write_pixel(1, 2, color(0.5, 0.5, 0.51));
+        b.write_pixel(3, 3, Color::WHITE);
+        let cmp = compare(&a, &b, ToneMap::Clamp, Metric::DeltaE);
+        assert_eq!(cmp.bad_pixels(0.0), 2);
+        assert_eq!(cmp.bad_pixels(1.0), 1);
+        assert!(cmp.passes(Tolerance { metric: Metric::DeltaE, per_pixel: 1.0, max_bad_pixels: 1 }));
+        assert!(!cmp.passes(Tolerance::default()));
+
+        let heatmap = cmp.heatmap(1.0);
+        assert_eq!(heatmap.pixel_at(0, 0), Color::BLACK);
+        assert_eq!(heatmap.pixel_at(3, 3), color(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn missing_reference_keeps_the_render() {
+        let dir = scratch_dir("missing");
+        let image = Canvas::new_with_color(2, 2, Color::WHITE);
+        match golden(&dir).check("white", &image) {
+            Err(GoldenError::Missing { report, .. }) => assert!(report.exists()),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(dir.join("out/white.actual.pfm").exists());
+
+        golden(&dir).update(true).check("white", &image).unwrap();
+        assert!(golden(&dir).check("white", &image).is_ok());
+        assert!(!dir.join("out/white.actual.pfm").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn mismatch_writes_side_by_side_report() {
+        let dir = scratch_dir("mismatch");
+        let expected = Canvas::new_with_color(3, 2, color(0.2, 0.3, 0.4));
+        golden(&dir).update(true).check("img", &expected).unwrap();
+
+        let mut actual = expected.clone();
+        actual.write_pixel(0, 0, color(0.9, 0.3, 0.4));
+        let report = match golden(&dir).check("img", &actual) {
+            Err(GoldenError::Mismatch { bad_pixels: 1, report, .. }) => report,
+            other => panic!("unexpected {:?}", other),
+        };
+        let png = fs::read(&report).unwrap();
+        assert_eq!(&png[16..20], &(3 * 3 + 2 * 4u32).to_be_bytes());
+        assert!(golden(&dir).max_bad_pixels(1).check("img", &actual).is_ok());
+
+        match golden(&dir).check("img", &Canvas::new(2, 2)) {
+            Err(GoldenError::SizeMismatch { expected: (3, 2), actual: (2, 2), .. }) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}